@@ -0,0 +1,41 @@
+-- Portable between Postgres and SQLite: only TEXT and BIGINT columns, no dialect-specific types.
+
+-- Live tasks. A queued task has a `score` (when it becomes due) and no lease; a claimed task has a
+-- `lease_deadline` and no score. `priority` is 0 for high, 1 for normal and 2 for low, so ordering
+-- by it claims higher priorities first. `payload` is the task as JSON.
+CREATE TABLE thermite_tasks (
+    id TEXT PRIMARY KEY,
+    category TEXT NOT NULL,
+    priority BIGINT NOT NULL,
+    scheduled_at BIGINT NOT NULL,
+    score BIGINT,
+    lease_deadline BIGINT,
+    payload TEXT NOT NULL
+);
+
+CREATE INDEX thermite_tasks_due ON thermite_tasks (priority, score, id);
+CREATE INDEX thermite_tasks_leases ON thermite_tasks (lease_deadline);
+
+CREATE TABLE thermite_dead_letters (
+    id TEXT PRIMARY KEY,
+    category TEXT NOT NULL,
+    dead_lettered_at BIGINT NOT NULL,
+    payload TEXT NOT NULL
+);
+
+CREATE INDEX thermite_dead_letters_order ON thermite_dead_letters (dead_lettered_at, id);
+
+-- The most recent occurrences of each periodic task.
+CREATE TABLE thermite_occurrences (
+    series_id TEXT NOT NULL,
+    id TEXT NOT NULL,
+    scheduled_at BIGINT NOT NULL,
+    PRIMARY KEY (series_id, id)
+);
+
+CREATE TABLE thermite_idempotency_keys (
+    idempotency_key TEXT PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    response TEXT,
+    expires_at BIGINT NOT NULL
+);
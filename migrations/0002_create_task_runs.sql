@@ -0,0 +1,12 @@
+-- Each task's most recent delivery attempts. `seq` numbers a task's runs in the order they were
+-- recorded; `recorded_at` is when the run finished, so histories can be expired. `payload` is the
+-- run as JSON.
+CREATE TABLE thermite_task_runs (
+    task_id TEXT NOT NULL,
+    seq BIGINT NOT NULL,
+    recorded_at BIGINT NOT NULL,
+    payload TEXT NOT NULL,
+    PRIMARY KEY (task_id, seq)
+);
+
+CREATE INDEX thermite_task_runs_recorded ON thermite_task_runs (recorded_at);
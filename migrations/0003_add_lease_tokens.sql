@@ -0,0 +1,3 @@
+-- The token of a claimed task's current lease, set with `lease_deadline`. Settling a task requires
+-- it, so a worker whose lease expired cannot settle a later claim of the same task.
+ALTER TABLE thermite_tasks ADD COLUMN lease_token BIGINT;
@@ -0,0 +1,2 @@
+-- Expired idempotency keys are deleted by their expiry time.
+CREATE INDEX thermite_idempotency_keys_expiry ON thermite_idempotency_keys (expires_at);
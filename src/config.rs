@@ -0,0 +1,601 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::Deserialize;
+use url::Url;
+
+use crate::errors::TaskQueueError;
+use crate::queue::{self, QueueOrder};
+use crate::signing::WebhookSigner;
+use crate::task::DEFAULT_TIMEOUT_SECS;
+
+/// How failed deliveries are retried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries of a task that does not set its own `max_retries`.
+    pub max_retries: u32,
+    /// Delay before the first retry; it doubles with every further retry.
+    pub base_delay_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_retries: 3, base_delay_secs: 30 }
+    }
+}
+
+/// Operator restrictions on delivery targets, on top of the checks in `task::validate_target`.
+/// They apply to submitted and fetched tasks and to every redirect a delivery follows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TargetPolicy {
+    /// Rejects plain `http` targets.
+    pub require_https: bool,
+    /// When not empty, only these hosts and their subdomains may be targeted. Read from the
+    /// environment as a comma-separated list.
+    pub allowed_hosts: Vec<String>,
+}
+
+impl TargetPolicy {
+    pub fn check(&self, url: &str) -> Result<(), TaskQueueError> {
+        let parsed_url = Url::parse(url).map_err(|e| {
+            TaskQueueError::InvalidTaskTarget(format!("Invalid task URL '{url}': {e}"))
+        })?;
+
+        if self.require_https && parsed_url.scheme() != "https" {
+            return Err(TaskQueueError::InvalidTaskTarget(
+                "Only HTTPS task URLs are allowed when require_https is enabled".to_string(),
+            ));
+        }
+
+        let host = parsed_url.host_str().unwrap_or_default().to_ascii_lowercase();
+        if !self.allowed_hosts.is_empty() && !self.is_host_allowed(&host) {
+            return Err(TaskQueueError::InvalidTaskTarget(format!(
+                "Task host '{host}' is not in the allowed hosts"
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn is_host_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .map(|entry| entry.trim().to_ascii_lowercase())
+            .filter(|entry| !entry.is_empty())
+            .any(|entry| host == entry || host.ends_with(&format!(".{entry}")))
+    }
+}
+
+/// How the fetcher polls `fetch_url`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetcherConfig {
+    /// Pause between successful fetches.
+    pub interval_secs: u64,
+    /// Up to this much random delay is added to every pause, so replicas do not poll in lockstep.
+    pub jitter_secs: u64,
+    /// Failed fetches back off exponentially from `interval_secs` up to this pause.
+    pub max_backoff_secs: u64,
+    pub timeout_secs: u64,
+    /// Sent as `Authorization: Bearer <token>`.
+    pub bearer_token: Option<String>,
+    /// Extra headers sent with every fetch, e.g. a source-specific API key. Read from the
+    /// environment as comma-separated `Name: value` pairs.
+    pub headers: BTreeMap<String, String>,
+    /// How the fetcher tells the source what it has already seen.
+    pub cursor: FetchCursor,
+    /// Where the ids of accepted and rejected tasks are POSTed after every fetch that returned some.
+    pub ack_url: Option<String>,
+}
+
+impl Default for FetcherConfig {
+    fn default() -> Self {
+        FetcherConfig {
+            interval_secs: 10,
+            jitter_secs: 2,
+            max_backoff_secs: 300,
+            timeout_secs: 15,
+            bearer_token: None,
+            headers: BTreeMap::new(),
+            cursor: FetchCursor::None,
+            ack_url: None,
+        }
+    }
+}
+
+/// What the fetcher sends so the source can skip tasks it already handed over.
+///
+/// The cursor is kept in memory only, so the first fetch after a restart pulls everything again.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum FetchCursor {
+    /// Every fetch asks for all tasks.
+    #[default]
+    None,
+    /// The last `ETag` is sent as `If-None-Match`, and a `304 Not Modified` is an empty fetch.
+    Etag,
+    /// `?since=<cursor>` is added, the `Thermite-Cursor` header of the last complete fetch. The
+    /// value is the source's own, so it does not depend on Thermite's clock.
+    Since,
+}
+
+impl FromStr for FetchCursor {
+    type Err = TaskQueueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(FetchCursor::None),
+            "etag" => Ok(FetchCursor::Etag),
+            "since" => Ok(FetchCursor::Since),
+            other => Err(invalid(format!("Unknown fetch cursor '{other}'; expected 'none', 'etag' or 'since'"))),
+        }
+    }
+}
+
+impl TryFrom<String> for FetchCursor {
+    type Error = TaskQueueError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Which components run in this process: the HTTP API, the worker that claims and delivers due
+/// tasks, and the fetcher that pulls tasks from `fetch_url`.
+///
+/// Parsed from a comma-separated list of `api`, `worker`, `fetcher` and `all`. `receiver` is the
+/// older name for `api,worker`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct Modes {
+    pub api: bool,
+    pub worker: bool,
+    pub fetcher: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Modes { api: true, worker: true, fetcher: false }
+    }
+}
+
+impl FromStr for Modes {
+    type Err = TaskQueueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut modes = Modes { api: false, worker: false, fetcher: false };
+        for mode in value.split(',').map(|mode| mode.trim().to_ascii_lowercase()) {
+            match mode.as_str() {
+                "api" => modes.api = true,
+                "worker" => modes.worker = true,
+                "fetcher" => modes.fetcher = true,
+                "receiver" => {
+                    modes.api = true;
+                    modes.worker = true;
+                }
+                "all" => modes = Modes { api: true, worker: true, fetcher: true },
+                other => {
+                    return Err(invalid(format!(
+                        "Unknown mode '{other}'; expected a comma-separated list of 'api', 'worker', 'fetcher' or 'all'"
+                    )));
+                }
+            }
+        }
+        Ok(modes)
+    }
+}
+
+impl TryFrom<String> for Modes {
+    type Error = TaskQueueError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for Modes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled = [(self.api, "api"), (self.worker, "worker"), (self.fetcher, "fetcher")];
+        let names: Vec<&str> = enabled.iter().filter(|(on, _)| *on).map(|(_, name)| *name).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+/// The validated runtime configuration of the `thermite` binary.
+///
+/// It is assembled by [`Config::load`] from built-in defaults, a TOML file, environment variables
+/// and command-line flags, each overriding the ones before it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub mode: Modes,
+    /// Where tasks are stored; see `store::connect`.
+    pub store_url: String,
+    /// The address the HTTP API listens on.
+    pub bind_address: String,
+    /// The task source polled in fetcher mode.
+    pub fetch_url: Option<String>,
+    /// How many deliveries may run concurrently.
+    pub workers: usize,
+    pub queue_order: QueueOrder,
+    /// How long a shutdown waits for running deliveries.
+    pub shutdown_timeout_secs: u64,
+    /// How long a claimed task is leased before it is handed out again.
+    pub visibility_timeout_secs: u64,
+    /// When set, API requests must send it in `X-API-Key` or as a bearer token.
+    pub api_key: Option<String>,
+    /// Secrets deliveries are signed with; the first signs, all are accepted while rotating. Read
+    /// from the environment as a comma-separated list.
+    pub signing_secrets: Vec<String>,
+    pub retry: RetryPolicy,
+    pub targets: TargetPolicy,
+    pub fetcher: FetcherConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            mode: Modes::default(),
+            store_url: "redis://localhost:6379".to_string(),
+            bind_address: "127.0.0.1:8080".to_string(),
+            fetch_url: None,
+            workers: 4,
+            queue_order: QueueOrder::default(),
+            shutdown_timeout_secs: 30,
+            visibility_timeout_secs: queue::DEFAULT_VISIBILITY_TIMEOUT.as_secs(),
+            api_key: None,
+            signing_secrets: Vec::new(),
+            retry: RetryPolicy::default(),
+            targets: TargetPolicy::default(),
+            fetcher: FetcherConfig::default(),
+        }
+    }
+}
+
+/// Settings from one configuration source. Unset fields leave the value of earlier sources alone.
+///
+/// The TOML file uses the same names as [`Config`], with `[retry]`, `[targets]` and `[fetcher]`
+/// tables.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigLayer {
+    pub mode: Option<Modes>,
+    pub store_url: Option<String>,
+    pub bind_address: Option<String>,
+    pub fetch_url: Option<String>,
+    pub workers: Option<usize>,
+    pub queue_order: Option<QueueOrder>,
+    pub shutdown_timeout_secs: Option<u64>,
+    pub visibility_timeout_secs: Option<u64>,
+    pub api_key: Option<String>,
+    pub signing_secrets: Option<Vec<String>>,
+    #[serde(default)]
+    pub retry: RetryLayer,
+    #[serde(default)]
+    pub targets: TargetLayer,
+    #[serde(default)]
+    pub fetcher: FetcherLayer,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetryLayer {
+    pub max_retries: Option<u32>,
+    pub base_delay_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TargetLayer {
+    pub require_https: Option<bool>,
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FetcherLayer {
+    pub interval_secs: Option<u64>,
+    pub jitter_secs: Option<u64>,
+    pub max_backoff_secs: Option<u64>,
+    pub timeout_secs: Option<u64>,
+    pub bearer_token: Option<String>,
+    pub headers: Option<BTreeMap<String, String>>,
+    pub cursor: Option<FetchCursor>,
+    pub ack_url: Option<String>,
+}
+
+fn invalid(message: String) -> TaskQueueError {
+    TaskQueueError::InvalidConfiguration(message)
+}
+
+// Reads and parses one variable. Empty values count as unset.
+fn env_value<T>(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>, TaskQueueError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match lookup(name) {
+        Some(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|e| invalid(format!("{name}: '{}' is not valid: {e}", value.trim()))),
+        _ => Ok(None),
+    }
+}
+
+fn env_flag(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<bool>, TaskQueueError> {
+    match lookup(name) {
+        Some(value) if !value.trim().is_empty() => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Some(true)),
+            "0" | "false" | "no" | "off" => Ok(Some(false)),
+            other => Err(invalid(format!("{name}: '{other}' is not a boolean"))),
+        },
+        _ => Ok(None),
+    }
+}
+
+// Reads a comma-separated list. Entries cannot contain a comma, so an empty entry is rejected
+// rather than dropped: it usually means a value was split where it should not have been.
+fn env_list(lookup: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<Vec<String>>, TaskQueueError> {
+    let Some(value) = lookup(name).filter(|value| !value.trim().is_empty()) else {
+        return Ok(None);
+    };
+    let entries: Vec<String> = value.split(',').map(|entry| entry.trim().to_string()).collect();
+    match entries.iter().position(|entry| entry.is_empty()) {
+        Some(index) => Err(invalid(format!(
+            "{name}: entry {} is empty; entries are separated by ',' and cannot contain one",
+            index + 1
+        ))),
+        None => Ok(Some(entries)),
+    }
+}
+
+fn check_http_url(name: &str, value: &str) -> Result<(), TaskQueueError> {
+    match Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+        Ok(url) => Err(invalid(format!("{name} must be an http(s) URL, not {}", url.scheme()))),
+        Err(e) => Err(invalid(format!("{name} '{value}' is not a valid URL: {e}"))),
+    }
+}
+
+// Reads `Name: value` pairs separated by commas.
+fn env_headers(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+) -> Result<Option<BTreeMap<String, String>>, TaskQueueError> {
+    let Some(entries) = env_list(lookup, name)? else {
+        return Ok(None);
+    };
+    entries
+        .iter()
+        .map(|entry| match entry.split_once(':') {
+            Some((header, value)) => Ok((header.trim().to_string(), value.trim().to_string())),
+            None => Err(invalid(format!(
+                "{name}: '{entry}' is not a 'Name: value' header; headers are separated by ',', so a \
+                 value containing one has to be set in the config file"
+            ))),
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+impl ConfigLayer {
+    pub fn from_toml(contents: &str) -> Result<Self, TaskQueueError> {
+        toml::from_str(contents).map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, TaskQueueError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| invalid(format!("cannot read {}: {e}", path.display())))?;
+        toml::from_str(&contents).map_err(|e| invalid(format!("{}: {e}", path.display())))
+    }
+
+    /// Reads the `THERMITE_*` variables (and the older `REDIS_URL`, `TASKS_URL` and `FETCH_URL`)
+    /// through `lookup`.
+    ///
+    /// `THERMITE_SIGNING_SECRETS`, `THERMITE_ALLOWED_HOSTS` and `THERMITE_FETCH_HEADERS` are
+    /// comma-separated lists, so their entries cannot contain a comma; such values have to be set
+    /// in the config file. Empty entries are rejected.
+    pub fn from_env(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, TaskQueueError> {
+        Ok(ConfigLayer {
+            mode: env_value(&lookup, "THERMITE_MODE")?,
+            store_url: env_value(&lookup, "THERMITE_STORE_URL")?.or(env_value(&lookup, "REDIS_URL")?),
+            bind_address: env_value(&lookup, "TASKS_URL")?,
+            fetch_url: env_value(&lookup, "FETCH_URL")?,
+            workers: env_value(&lookup, "THERMITE_WORKERS")?,
+            queue_order: env_value(&lookup, "THERMITE_QUEUE_ORDER")?,
+            shutdown_timeout_secs: env_value(&lookup, "THERMITE_SHUTDOWN_TIMEOUT_SECS")?,
+            visibility_timeout_secs: env_value(&lookup, "THERMITE_VISIBILITY_TIMEOUT_SECS")?,
+            api_key: env_value(&lookup, "THERMITE_API_KEY")?,
+            signing_secrets: env_list(&lookup, "THERMITE_SIGNING_SECRETS")?,
+            retry: RetryLayer {
+                max_retries: env_value(&lookup, "THERMITE_MAX_RETRIES")?,
+                base_delay_secs: env_value(&lookup, "THERMITE_RETRY_BASE_DELAY_SECS")?,
+            },
+            targets: TargetLayer {
+                require_https: env_flag(&lookup, "THERMITE_REQUIRE_HTTPS")?,
+                allowed_hosts: env_list(&lookup, "THERMITE_ALLOWED_HOSTS")?,
+            },
+            fetcher: FetcherLayer {
+                interval_secs: env_value(&lookup, "THERMITE_FETCH_INTERVAL_SECS")?,
+                jitter_secs: env_value(&lookup, "THERMITE_FETCH_JITTER_SECS")?,
+                max_backoff_secs: env_value(&lookup, "THERMITE_FETCH_MAX_BACKOFF_SECS")?,
+                timeout_secs: env_value(&lookup, "THERMITE_FETCH_TIMEOUT_SECS")?,
+                bearer_token: env_value(&lookup, "THERMITE_FETCH_TOKEN")?,
+                headers: env_headers(&lookup, "THERMITE_FETCH_HEADERS")?,
+                cursor: env_value(&lookup, "THERMITE_FETCH_CURSOR")?,
+                ack_url: env_value(&lookup, "THERMITE_FETCH_ACK_URL")?,
+            },
+        })
+    }
+
+    /// Returns this layer with every field that `over` sets replaced.
+    pub fn merge(self, over: ConfigLayer) -> ConfigLayer {
+        ConfigLayer {
+            mode: over.mode.or(self.mode),
+            store_url: over.store_url.or(self.store_url),
+            bind_address: over.bind_address.or(self.bind_address),
+            fetch_url: over.fetch_url.or(self.fetch_url),
+            workers: over.workers.or(self.workers),
+            queue_order: over.queue_order.or(self.queue_order),
+            shutdown_timeout_secs: over.shutdown_timeout_secs.or(self.shutdown_timeout_secs),
+            visibility_timeout_secs: over.visibility_timeout_secs.or(self.visibility_timeout_secs),
+            api_key: over.api_key.or(self.api_key),
+            signing_secrets: over.signing_secrets.or(self.signing_secrets),
+            retry: RetryLayer {
+                max_retries: over.retry.max_retries.or(self.retry.max_retries),
+                base_delay_secs: over.retry.base_delay_secs.or(self.retry.base_delay_secs),
+            },
+            targets: TargetLayer {
+                require_https: over.targets.require_https.or(self.targets.require_https),
+                allowed_hosts: over.targets.allowed_hosts.or(self.targets.allowed_hosts),
+            },
+            fetcher: FetcherLayer {
+                interval_secs: over.fetcher.interval_secs.or(self.fetcher.interval_secs),
+                jitter_secs: over.fetcher.jitter_secs.or(self.fetcher.jitter_secs),
+                max_backoff_secs: over.fetcher.max_backoff_secs.or(self.fetcher.max_backoff_secs),
+                timeout_secs: over.fetcher.timeout_secs.or(self.fetcher.timeout_secs),
+                bearer_token: over.fetcher.bearer_token.or(self.fetcher.bearer_token),
+                headers: over.fetcher.headers.or(self.fetcher.headers),
+                cursor: over.fetcher.cursor.or(self.fetcher.cursor),
+                ack_url: over.fetcher.ack_url.or(self.fetcher.ack_url),
+            },
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the TOML file at `path`, then the environment, then `cli`, and
+    /// validates the result.
+    pub fn load(path: Option<&Path>, cli: ConfigLayer) -> Result<Config, TaskQueueError> {
+        let file = match path {
+            Some(path) => ConfigLayer::from_file(path)?,
+            None => ConfigLayer::default(),
+        };
+        let env = ConfigLayer::from_env(|name| std::env::var(name).ok())?;
+        Config::from_layer(file.merge(env).merge(cli))
+    }
+
+    /// Applies `layer` over the defaults and validates the result.
+    pub fn from_layer(layer: ConfigLayer) -> Result<Config, TaskQueueError> {
+        let defaults = Config::default();
+        let config = Config {
+            mode: layer.mode.unwrap_or(defaults.mode),
+            store_url: layer.store_url.unwrap_or(defaults.store_url),
+            bind_address: layer.bind_address.unwrap_or(defaults.bind_address),
+            fetch_url: layer.fetch_url,
+            workers: layer.workers.unwrap_or(defaults.workers),
+            queue_order: layer.queue_order.unwrap_or(defaults.queue_order),
+            shutdown_timeout_secs: layer.shutdown_timeout_secs.unwrap_or(defaults.shutdown_timeout_secs),
+            visibility_timeout_secs: layer.visibility_timeout_secs.unwrap_or(defaults.visibility_timeout_secs),
+            api_key: layer.api_key.filter(|key| !key.trim().is_empty()),
+            signing_secrets: layer.signing_secrets.unwrap_or_default(),
+            retry: RetryPolicy {
+                max_retries: layer.retry.max_retries.unwrap_or(defaults.retry.max_retries),
+                base_delay_secs: layer.retry.base_delay_secs.unwrap_or(defaults.retry.base_delay_secs),
+            },
+            targets: TargetPolicy {
+                require_https: layer.targets.require_https.unwrap_or(defaults.targets.require_https),
+                allowed_hosts: layer.targets.allowed_hosts.unwrap_or_default(),
+            },
+            fetcher: FetcherConfig {
+                interval_secs: layer.fetcher.interval_secs.unwrap_or(defaults.fetcher.interval_secs),
+                jitter_secs: layer.fetcher.jitter_secs.unwrap_or(defaults.fetcher.jitter_secs),
+                max_backoff_secs: layer.fetcher.max_backoff_secs.unwrap_or(defaults.fetcher.max_backoff_secs),
+                timeout_secs: layer.fetcher.timeout_secs.unwrap_or(defaults.fetcher.timeout_secs),
+                bearer_token: layer.fetcher.bearer_token.filter(|token| !token.trim().is_empty()),
+                headers: layer.fetcher.headers.unwrap_or_default(),
+                cursor: layer.fetcher.cursor.unwrap_or(defaults.fetcher.cursor),
+                ack_url: layer.fetcher.ack_url,
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), TaskQueueError> {
+        if self.store_url.trim().is_empty() {
+            return Err(invalid("store_url must not be empty".to_string()));
+        }
+        if self.bind_address.trim().is_empty() {
+            return Err(invalid("bind_address must not be empty".to_string()));
+        }
+        if let Some(fetch_url) = &self.fetch_url {
+            check_http_url("fetch_url", fetch_url)?;
+        }
+        if self.mode.fetcher && self.fetch_url.is_none() {
+            return Err(invalid("fetch_url must be set when the fetcher runs".to_string()));
+        }
+        if self.workers == 0 {
+            return Err(invalid("workers must be at least 1".to_string()));
+        }
+        // Tasks that set `timeout_secs` are leased for as long as their delivery may take; the
+        // others must be delivered within the visibility timeout.
+        let min_visibility_timeout_secs = DEFAULT_TIMEOUT_SECS + queue::LEASE_MARGIN.as_secs();
+        if self.visibility_timeout_secs < min_visibility_timeout_secs {
+            return Err(invalid(format!(
+                "visibility_timeout_secs must be at least {min_visibility_timeout_secs}, longer than the \
+                 {DEFAULT_TIMEOUT_SECS} second default delivery timeout"
+            )));
+        }
+        if self.retry.base_delay_secs == 0 {
+            return Err(invalid("retry.base_delay_secs must be at least 1".to_string()));
+        }
+        if !self.signing_secrets.is_empty() {
+            self.signer()?;
+        }
+        for host in &self.targets.allowed_hosts {
+            let parsed = Url::parse(&format!("http://{host}/")).ok();
+            if parsed.as_ref().and_then(Url::host_str) != Some(host.to_ascii_lowercase().as_str()) {
+                return Err(invalid(format!("targets.allowed_hosts: '{host}' is not a host name")));
+            }
+        }
+        self.validate_fetcher()
+    }
+
+    fn validate_fetcher(&self) -> Result<(), TaskQueueError> {
+        let fetcher = &self.fetcher;
+        if fetcher.interval_secs == 0 {
+            return Err(invalid("fetcher.interval_secs must be at least 1".to_string()));
+        }
+        if fetcher.max_backoff_secs < fetcher.interval_secs {
+            return Err(invalid("fetcher.max_backoff_secs must not be shorter than fetcher.interval_secs".to_string()));
+        }
+        if fetcher.timeout_secs == 0 {
+            return Err(invalid("fetcher.timeout_secs must be at least 1".to_string()));
+        }
+        if let Some(token) = &fetcher.bearer_token {
+            if HeaderValue::from_str(&format!("Bearer {token}")).is_err() {
+                return Err(invalid("fetcher.bearer_token contains characters not allowed in a header".to_string()));
+            }
+        }
+        for (name, value) in &fetcher.headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err() {
+                return Err(invalid(format!("fetcher.headers: '{name}' is not a valid header")));
+            }
+        }
+        if let Some(ack_url) = &fetcher.ack_url {
+            check_http_url("fetcher.ack_url", ack_url)?;
+        }
+        Ok(())
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    pub fn visibility_timeout(&self) -> Duration {
+        Duration::from_secs(self.visibility_timeout_secs)
+    }
+
+    /// The signer for task deliveries, or `None` when no signing secrets are configured.
+    pub fn signer(&self) -> Result<Option<WebhookSigner>, TaskQueueError> {
+        if self.signing_secrets.is_empty() {
+            return Ok(None);
+        }
+        WebhookSigner::new(self.signing_secrets.clone()).map(Some)
+    }
+}
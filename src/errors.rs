@@ -16,15 +16,78 @@ pub enum TaskQueueError {
     #[error("Redis error: {0}")]
     RedisError(String),
 
+    #[cfg(feature = "sql")]
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
     #[error("Serialization error: {0}")]
     SerializationError(String),
 
     #[error("Invalid cron expression: {0}")]
     InvalidCronExpression(String),
 
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+
+    #[error("Unknown timezone '{0}'; expected an IANA name such as 'Europe/Berlin'")]
+    InvalidTimezone(String),
+
+    #[error("Invalid priority '{0}'; expected 'high', 'normal' or 'low'")]
+    InvalidPriority(String),
+
+    #[error("Invalid task id: {0}")]
+    InvalidTaskId(String),
+
     #[error("Invalid task target: {0}")]
     InvalidTaskTarget(String),
 
+    #[error("Invalid delivery options: {0}")]
+    InvalidDeliveryOptions(String),
+
+    #[error("Task '{0}' already exists")]
+    DuplicateTask(String),
+
+    #[error("Task '{0}' is currently being delivered")]
+    TaskInFlight(String),
+
+    #[error("Fetching tasks failed: {0}")]
+    FetchFailed(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfiguration(String),
+
     #[error("Application state error: {0}")]
     StateError(String),
 }
+
+impl TaskQueueError {
+    /// Whether the error is caused by the task or request itself, rather than by Thermite or its
+    /// store, so repeating the same request cannot succeed.
+    pub fn is_client_error(&self) -> bool {
+        matches!(
+            self,
+            TaskQueueError::InvalidCronExpression(_)
+                | TaskQueueError::InvalidSchedule(_)
+                | TaskQueueError::InvalidTimezone(_)
+                | TaskQueueError::InvalidTaskId(_)
+                | TaskQueueError::InvalidTaskTarget(_)
+                | TaskQueueError::InvalidPriority(_)
+                | TaskQueueError::InvalidDeliveryOptions(_)
+                | TaskQueueError::DuplicateTask(_)
+                | TaskQueueError::TaskInFlight(_)
+        )
+    }
+}
+
+/// Reasons a received delivery fails `signing::verify_signature`.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("Signature header is malformed")]
+    MalformedHeader,
+
+    #[error("Signature timestamp is outside the allowed tolerance")]
+    TimestampOutOfTolerance,
+
+    #[error("No signature matches the configured secrets")]
+    NoMatchingSignature,
+}
@@ -0,0 +1,279 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use rand::Rng;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, ETAG, IF_NONE_MATCH};
+use reqwest::{Client, StatusCode};
+use serde_json::json;
+use tracing::{debug, info, warn};
+
+use crate::config::{Config, FetchCursor, FetcherConfig, TargetPolicy};
+use crate::errors::TaskQueueError;
+use crate::health::Health;
+use crate::metrics;
+use crate::queue;
+use crate::shutdown::Shutdown;
+use crate::store::TaskStore;
+use crate::task::BaseTask;
+
+/// Response header a source sets to the cursor the next `?since=` fetch should send back.
+pub const CURSOR_HEADER: &str = "thermite-cursor";
+
+// How much of an error response is kept for the log, after collapsing its whitespace.
+const MAX_ERROR_BODY_CHARS: usize = 512;
+
+/// A fetched item that was not enqueued, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedTask {
+    /// The item's `id`, when it has one.
+    pub id: Option<String>,
+    pub error: String,
+}
+
+/// What one fetch did with the items the source returned.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FetchReport {
+    /// Ids of the tasks that are now queued, including ones that already were.
+    pub accepted: Vec<String>,
+    pub rejected: Vec<RejectedTask>,
+    /// Ids of the tasks the store failed to take; they are picked up again by the next fetch.
+    pub failed: Vec<String>,
+}
+
+impl FetchReport {
+    /// Whether every returned task was either queued or rejected. A fetch whose tasks the store
+    /// could not take does not count as a successful fetch.
+    pub fn is_complete(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+/// Polls the task source at `fetch_url` and enqueues the tasks it returns.
+pub struct Fetcher {
+    store: Arc<dyn TaskStore>,
+    health: Arc<Health>,
+    client: Client,
+    url: String,
+    settings: FetcherConfig,
+    targets: TargetPolicy,
+    // The ETag or `since` value sent with the next fetch, depending on `settings.cursor`.
+    cursor: Option<String>,
+}
+
+/// The pause before the next fetch, without jitter: the poll interval, doubled for every
+/// consecutive failure up to `max_backoff_secs`.
+pub fn poll_delay(settings: &FetcherConfig, consecutive_failures: u32) -> Duration {
+    let multiplier = 1_u64.checked_shl(consecutive_failures).unwrap_or(u64::MAX);
+    let delay = settings.interval_secs.saturating_mul(multiplier).min(settings.max_backoff_secs);
+    Duration::from_secs(delay)
+}
+
+// A random delay of up to `max`.
+fn jitter(max: Duration) -> Duration {
+    let max_millis = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(rand::thread_rng().gen_range(0..=max_millis))
+}
+
+fn error_excerpt(body: &str) -> String {
+    let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    match body.char_indices().nth(MAX_ERROR_BODY_CHARS) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body,
+    }
+}
+
+impl Fetcher {
+    /// Builds a fetcher for `config.fetch_url`, sending the configured bearer token and headers with
+    /// every request.
+    pub fn new(store: Arc<dyn TaskStore>, config: &Config, health: Arc<Health>) -> Result<Self, TaskQueueError> {
+        let url = config
+            .fetch_url
+            .clone()
+            .ok_or_else(|| TaskQueueError::InvalidConfiguration("fetch_url must be set when the fetcher runs".to_string()))?;
+        let settings = config.fetcher.clone();
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in &settings.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| TaskQueueError::InvalidConfiguration(format!("fetcher.headers: {e}")))?;
+            let mut value = HeaderValue::from_str(value)
+                .map_err(|e| TaskQueueError::InvalidConfiguration(format!("fetcher.headers: {e}")))?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+        if let Some(token) = &settings.bearer_token {
+            let mut value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .map_err(|e| TaskQueueError::InvalidConfiguration(format!("fetcher.bearer_token: {e}")))?;
+            value.set_sensitive(true);
+            headers.insert(AUTHORIZATION, value);
+        }
+
+        // The task source is operator-configured and usually internal, so it is fetched with a
+        // plain client rather than the guarded delivery client.
+        let client = Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .default_headers(headers)
+            .build()
+            .map_err(|e| TaskQueueError::InvalidConfiguration(format!("Failed to build HTTP client: {e}")))?;
+
+        Ok(Fetcher { store, health, client, url, settings, targets: config.targets.clone(), cursor: None })
+    }
+
+    /// Fetches the source once, enqueues every valid task it returns and acknowledges them at
+    /// `ack_url`. Fails when the source cannot be reached, answers with a non-2xx status, does not
+    /// return a JSON array, or does not take the acknowledgement; invalid items only end up in the
+    /// report's `rejected`.
+    ///
+    /// The cursor only moves on once every returned task was either queued or rejected and
+    /// acknowledged, so anything else is fetched again.
+    pub async fn fetch_once(&mut self) -> Result<FetchReport, TaskQueueError> {
+        let mut request = self.client.get(&self.url);
+        match (self.settings.cursor, &self.cursor) {
+            (FetchCursor::Etag, Some(etag)) => request = request.header(IF_NONE_MATCH, etag),
+            (FetchCursor::Since, Some(since)) => request = request.query(&[("since", since)]),
+            _ => {}
+        }
+        let response = request
+            .send()
+            .await
+            .map_err(|e| TaskQueueError::FetchFailed(format!("request to the task source failed: {e}")))?;
+
+        let status = response.status();
+        if status == StatusCode::NOT_MODIFIED {
+            debug!("task source has no new tasks");
+            return Ok(FetchReport::default());
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(TaskQueueError::FetchFailed(format!(
+                "task source answered {status}: {}",
+                error_excerpt(&body)
+            )));
+        }
+
+        let header = |name: &str| response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string);
+        let etag = header(ETAG.as_str());
+        let next_since = header(CURSOR_HEADER);
+        let items: Vec<serde_json::Value> = response
+            .json()
+            .await
+            .map_err(|e| TaskQueueError::FetchFailed(format!("task source did not return a JSON array: {e}")))?;
+
+        let mut report = FetchReport::default();
+        for item in items {
+            let id = item.get("id").and_then(|id| id.as_str()).map(str::to_string);
+            match self.enqueue_item(item).await {
+                Ok(task) => {
+                    debug!(task_id = %task.id, "task enqueued from fetcher");
+                    report.accepted.push(task.id);
+                }
+                Err(e) if e.is_client_error() || matches!(e, TaskQueueError::Serde(_)) => {
+                    warn!(task_id = id.as_deref().unwrap_or("<missing>"), error = %e, "rejected fetched task");
+                    report.rejected.push(RejectedTask { id, error: e.to_string() });
+                }
+                Err(e) => {
+                    warn!(task_id = id.as_deref().unwrap_or("<missing>"), error = %e, "failed to enqueue fetched task");
+                    report.failed.extend(id);
+                }
+            }
+        }
+
+        self.acknowledge(&report).await?;
+        if report.is_complete() {
+            self.cursor = match self.settings.cursor {
+                FetchCursor::None => None,
+                FetchCursor::Etag => etag,
+                // A source that does not hand out a cursor keeps getting the last one it did.
+                FetchCursor::Since => next_since.or(self.cursor.take()),
+            };
+        }
+        Ok(report)
+    }
+
+    // POSTs the accepted ids and the rejected items that have one to `ack_url`, if it is set.
+    async fn acknowledge(&self, report: &FetchReport) -> Result<(), TaskQueueError> {
+        let Some(ack_url) = &self.settings.ack_url else {
+            return Ok(());
+        };
+        let rejected: Vec<serde_json::Value> = report
+            .rejected
+            .iter()
+            .filter_map(|rejected| rejected.id.as_ref().map(|id| json!({"id": id, "error": rejected.error})))
+            .collect();
+        if report.accepted.is_empty() && rejected.is_empty() {
+            return Ok(());
+        }
+
+        let response = self
+            .client
+            .post(ack_url)
+            .json(&json!({"accepted": report.accepted, "rejected": rejected}))
+            .send()
+            .await
+            .map_err(|e| TaskQueueError::FetchFailed(format!("acknowledging fetched tasks failed: {e}")))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(TaskQueueError::FetchFailed(format!(
+                "task source answered {status} to the acknowledgement: {}",
+                error_excerpt(&body)
+            )));
+        }
+        debug!(accepted = report.accepted.len(), rejected = rejected.len(), "acknowledged fetched tasks");
+        Ok(())
+    }
+
+    async fn enqueue_item(&self, item: serde_json::Value) -> Result<BaseTask, TaskQueueError> {
+        let task: BaseTask = serde_json::from_value(item)?;
+        task.validate_submission()?;
+        self.targets.check(&task.task)?;
+        queue::enqueue_task(self.store.as_ref(), &task).await?;
+        Ok(task)
+    }
+
+    /// Polls until `shutdown` is triggered, backing off while the source keeps failing.
+    pub async fn run(mut self, mut shutdown: Shutdown) {
+        info!(fetch_url = %self.url, interval_secs = self.settings.interval_secs, "starting fetcher loop");
+        self.health.fetcher_started(Utc::now().timestamp(), self.settings.interval_secs);
+
+        let mut consecutive_failures = 0_u32;
+        loop {
+            let now = Utc::now().timestamp();
+            match self.fetch_once().await {
+                Ok(report) if report.is_complete() => {
+                    consecutive_failures = 0;
+                    self.health.fetcher_succeeded(now);
+                    metrics::record_fetch(true, now);
+                    metrics::record_fetched_tasks(report.accepted.len(), report.rejected.len());
+                    info!(accepted = report.accepted.len(), rejected = report.rejected.len(), "fetched tasks");
+                }
+                Ok(report) => {
+                    consecutive_failures = consecutive_failures.saturating_add(1);
+                    metrics::record_fetch(false, now);
+                    metrics::record_fetched_tasks(report.accepted.len(), report.rejected.len());
+                    warn!(
+                        accepted = report.accepted.len(),
+                        rejected = report.rejected.len(),
+                        failed = report.failed.len(),
+                        consecutive_failures,
+                        "fetched tasks, but the store failed to take some of them"
+                    );
+                }
+                Err(e) => {
+                    consecutive_failures = consecutive_failures.saturating_add(1);
+                    metrics::record_fetch(false, now);
+                    warn!(error = %e, consecutive_failures, "failed to fetch tasks");
+                }
+            }
+
+            let delay = poll_delay(&self.settings, consecutive_failures)
+                + jitter(Duration::from_secs(self.settings.jitter_secs));
+            tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = tokio::time::sleep(delay) => {}
+            }
+        }
+        info!("stopped fetcher loop");
+    }
+}
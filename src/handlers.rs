@@ -1,20 +1,38 @@
+use actix_web::http::StatusCode;
 use actix_web::{web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::sync::Mutex;
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tracing::{error, info, warn};
 
+use crate::config::{Config, TargetPolicy};
 use crate::errors::TaskQueueError;
-use crate::queue;
-use crate::task::BaseTask;
+use crate::health::Health;
+use crate::metrics;
+use crate::queue::{self, DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, TaskFilter};
+use crate::store::TaskStore;
+use crate::task::{BaseTask, DeliveryHeaders, TaskPriority, TaskUpdate};
 
 pub struct AppState {
-    pub redis_client: redis::Client,
+    pub store: Arc<dyn TaskStore>,
+    pub health: Arc<Health>,
+    pub config: Arc<Config>,
 }
 
-fn authorize_request(req: &HttpRequest) -> Result<(), HttpResponse> {
-    let configured_api_key = match std::env::var("THERMITE_API_KEY") {
-        Ok(value) if !value.trim().is_empty() => value,
-        _ => return Ok(()),
+fn authorize_request(req: &HttpRequest, data: &web::Data<Mutex<AppState>>) -> Result<(), HttpResponse> {
+    let configured_api_key = match data.lock() {
+        Ok(state) => match &state.config.api_key {
+            Some(api_key) => api_key.clone(),
+            None => return Ok(()),
+        },
+        Err(e) => {
+            return Err(HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")})));
+        }
     };
 
     let provided_api_key = req
@@ -37,30 +55,147 @@ fn authorize_request(req: &HttpRequest) -> Result<(), HttpResponse> {
     }
 }
 
+fn reject_malformed_request<E>(error: E, req: &HttpRequest) -> actix_web::Error
+where
+    E: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    warn!(path = %req.path(), error = %error, "rejected malformed request");
+    let response = HttpResponse::BadRequest().json(json!({"error": error.to_string()}));
+    actix_web::error::InternalError::from_response(error, response).into()
+}
+
+/// JSON extractor configuration that reports malformed task payloads, such as an unknown
+/// `priority`, as a `400` with the same `{"error": ...}` body the handlers use.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default().error_handler(reject_malformed_request)
+}
+
+/// Query string extractor configuration with the same `400` error body as [`json_config`].
+pub fn query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(reject_malformed_request)
+}
+
+fn task_error_body(error: TaskQueueError) -> (StatusCode, serde_json::Value) {
+    let status = match error {
+        TaskQueueError::DuplicateTask(_) | TaskQueueError::TaskInFlight(_) => StatusCode::CONFLICT,
+        ref error if error.is_client_error() => StatusCode::BAD_REQUEST,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+
+    if status.is_server_error() {
+        error!(error = %error, "task request failed due to a server-side issue");
+    } else {
+        warn!(error = %error, "task request validation failed");
+    }
+    (status, json!({"error": error.to_string()}))
+}
+
 fn task_error_response(error: TaskQueueError) -> HttpResponse {
-    match error {
-        TaskQueueError::InvalidCronExpression(_) | TaskQueueError::InvalidTaskTarget(_) => {
-            warn!(error = %error, "task request validation failed");
-            HttpResponse::BadRequest().json(json!({"error": error.to_string()}))
+    let (status, body) = task_error_body(error);
+    HttpResponse::build(status).json(body)
+}
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SubmitQuery {
+    /// How to treat a task whose id already exists: `reject`, `replace` or `keep_existing`.
+    pub on_duplicate: Option<DuplicatePolicy>,
+}
+
+// A stable digest of the submitted payload and options. Serializing through `serde_json::Value`
+// sorts object keys, so the same tasks always produce the same fingerprint.
+fn request_fingerprint<T: Serialize>(payload: &T, policy: DuplicatePolicy) -> Result<String, TaskQueueError> {
+    let canonical = serde_json::to_string(&json!({"payload": payload, "on_duplicate": policy}))?;
+    Ok(hex::encode(Sha256::digest(canonical.as_bytes())))
+}
+
+// Enqueues a submitted task once it passes the checks `BaseTask::validate` does not know about:
+// the operator's target policy and the fields only Thermite may set.
+async fn enqueue_submitted_task(
+    store: &dyn TaskStore,
+    targets: &TargetPolicy,
+    task: &BaseTask,
+    policy: DuplicatePolicy,
+) -> Result<EnqueueOutcome, TaskQueueError> {
+    task.validate_submission()?;
+    targets.check(&task.task)?;
+    queue::enqueue_task_with_policy(store, task, policy).await
+}
+
+// Runs `handle` at most once per `Idempotency-Key`. Repeated requests with the same key and payload
+// get the stored response back; server errors release the key so the client can retry.
+async fn respond_idempotently<F, Fut>(
+    req: &HttpRequest,
+    store: &dyn TaskStore,
+    fingerprint: &str,
+    handle: F,
+) -> HttpResponse
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = (StatusCode, serde_json::Value)>,
+{
+    let key = match req.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        None => {
+            let (status, body) = handle().await;
+            return HttpResponse::build(status).json(body);
         }
-        _ => {
-            error!(error = %error, "task request failed due to a server-side issue");
-            HttpResponse::InternalServerError().json(json!({"error": error.to_string()}))
+        Some(value) => match value.to_str() {
+            Ok(key) if !key.trim().is_empty() && key.len() <= 255 => key.to_string(),
+            _ => {
+                return HttpResponse::BadRequest()
+                    .json(json!({"error": "Idempotency-Key must be 1 to 255 visible ASCII characters"}));
+            }
+        },
+    };
+    let scope = req.path();
+
+    match queue::begin_idempotent_request(store, scope, &key, fingerprint).await {
+        Ok(IdempotencyState::New) => {}
+        Ok(IdempotencyState::InProgress) => {
+            return HttpResponse::Conflict()
+                .json(json!({"error": "A request with this Idempotency-Key is still being processed"}));
         }
+        Ok(IdempotencyState::Mismatch) => {
+            return HttpResponse::UnprocessableEntity()
+                .json(json!({"error": "Idempotency-Key was already used with a different payload"}));
+        }
+        Ok(IdempotencyState::Completed(response)) => {
+            info!(idempotency_key = %key, path = %scope, "replaying stored response for idempotent request");
+            let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+            return HttpResponse::build(status)
+                .insert_header(("Idempotent-Replayed", "true"))
+                .json(response.body);
+        }
+        Err(e) => return task_error_response(e),
+    }
+
+    let (status, body) = handle().await;
+    let stored = if status.is_server_error() {
+        queue::release_idempotent_request(store, scope, &key).await
+    } else {
+        let response = IdempotentResponse { status: status.as_u16(), body: body.clone() };
+        queue::complete_idempotent_request(store, scope, &key, fingerprint, &response).await
+    };
+    if let Err(e) = stored {
+        error!(idempotency_key = %key, error = %e, "failed to record idempotent request outcome");
     }
+
+    HttpResponse::build(status).json(body)
 }
 
 pub async fn submit_task(
     req: HttpRequest,
     data: web::Data<Mutex<AppState>>,
     task: web::Json<BaseTask>,
+    query: web::Query<SubmitQuery>,
 ) -> impl Responder {
-    if let Err(response) = authorize_request(&req) {
+    if let Err(response) = authorize_request(&req, &data) {
         return response;
     }
 
-    let redis_client = match data.lock() {
-        Ok(state) => state.redis_client.clone(),
+    let (store, config) = match data.lock() {
+        Ok(state) => (state.store.clone(), state.config.clone()),
         Err(e) => {
             return HttpResponse::InternalServerError()
                 .json(json!({"error": format!("Application state unavailable: {e}")}));
@@ -68,25 +203,37 @@ pub async fn submit_task(
     };
 
     let task = task.into_inner();
+    let policy = query.on_duplicate.unwrap_or_default();
     info!(task_id = %task.id, category = %task.category, path = %req.path(), "received task submission");
 
-    match queue::enqueue_task(&redis_client, &task).await {
-        Ok(_) => HttpResponse::Ok().json(json!({"status": "Task submitted"})),
-        Err(e) => task_error_response(e),
-    }
+    let fingerprint = match request_fingerprint(&task, policy) {
+        Ok(fingerprint) => fingerprint,
+        Err(e) => return task_error_response(e),
+    };
+
+    respond_idempotently(&req, store.as_ref(), &fingerprint, || async {
+        match enqueue_submitted_task(store.as_ref(), &config.targets, &task, policy).await {
+            Ok(EnqueueOutcome::Enqueued) => (StatusCode::OK, json!({"status": "Task submitted"})),
+            Ok(EnqueueOutcome::Replaced) => (StatusCode::OK, json!({"status": "Task replaced"})),
+            Ok(EnqueueOutcome::AlreadyExists) => (StatusCode::OK, json!({"status": "Task already exists"})),
+            Err(e) => task_error_body(e),
+        }
+    })
+    .await
 }
 
 pub async fn submit_tasks(
     req: HttpRequest,
     data: web::Data<Mutex<AppState>>,
     tasks: web::Json<Vec<BaseTask>>,
+    query: web::Query<SubmitQuery>,
 ) -> impl Responder {
-    if let Err(response) = authorize_request(&req) {
+    if let Err(response) = authorize_request(&req, &data) {
         return response;
     }
 
-    let redis_client = match data.lock() {
-        Ok(state) => state.redis_client.clone(),
+    let (store, config) = match data.lock() {
+        Ok(state) => (state.store.clone(), state.config.clone()),
         Err(e) => {
             return HttpResponse::InternalServerError()
                 .json(json!({"error": format!("Application state unavailable: {e}")}));
@@ -94,63 +241,414 @@ pub async fn submit_tasks(
     };
 
     let tasks = tasks.into_inner();
+    let policy = query.on_duplicate.unwrap_or_default();
     info!(count = tasks.len(), path = %req.path(), "received batch task submission");
 
-    let mut submitted = 0usize;
-    let mut failures = Vec::new();
-    let mut has_server_error = false;
+    let fingerprint = match request_fingerprint(&tasks, policy) {
+        Ok(fingerprint) => fingerprint,
+        Err(e) => return task_error_response(e),
+    };
 
-    for task in tasks {
-        match queue::enqueue_task(&redis_client, &task).await {
-            Ok(_) => {
-                info!(task_id = %task.id, "task enqueued from batch request");
-                submitted += 1;
-            }
-            Err(e) => {
-                warn!(task_id = %task.id, error = %e, "failed to enqueue task from batch request");
-                if !matches!(e, TaskQueueError::InvalidCronExpression(_) | TaskQueueError::InvalidTaskTarget(_)) {
-                    has_server_error = true;
+    respond_idempotently(&req, store.as_ref(), &fingerprint, || async {
+        let mut submitted = 0usize;
+        let mut failures = Vec::new();
+        let mut has_server_error = false;
+
+        for task in tasks {
+            match enqueue_submitted_task(store.as_ref(), &config.targets, &task, policy).await {
+                Ok(_) => {
+                    info!(task_id = %task.id, "task enqueued from batch request");
+                    submitted += 1;
+                }
+                Err(e) => {
+                    warn!(task_id = %task.id, error = %e, "failed to enqueue task from batch request");
+                    if !e.is_client_error() {
+                        has_server_error = true;
+                    }
+                    failures.push(json!({"id": task.id, "error": e.to_string()}));
                 }
-                failures.push(json!({"id": task.id, "error": e.to_string()}));
             }
         }
+
+        if failures.is_empty() {
+            (StatusCode::OK, json!({"status": "Tasks submitted", "submitted": submitted}))
+        } else if has_server_error {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                json!({
+                    "status": "Some tasks failed",
+                    "submitted": submitted,
+                    "failed": failures
+                }),
+            )
+        } else {
+            (
+                StatusCode::BAD_REQUEST,
+                json!({
+                    "status": "Some tasks failed validation",
+                    "submitted": submitted,
+                    "failed": failures
+                }),
+            )
+        }
+    })
+    .await
+}
+
+pub async fn dead_letter_tasks(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    query: web::Query<TaskListQuery>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
     }
 
-    if failures.is_empty() {
-        HttpResponse::Ok().json(json!({"status": "Tasks submitted", "submitted": submitted}))
-    } else if has_server_error {
-        HttpResponse::InternalServerError().json(json!({
-            "status": "Some tasks failed",
-            "submitted": submitted,
-            "failed": failures
-        }))
-    } else {
-        HttpResponse::BadRequest().json(json!({
-            "status": "Some tasks failed validation",
-            "submitted": submitted,
-            "failed": failures
-        }))
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    let (filter, offset, limit) = query.into_inner().into_page();
+
+    match queue::list_dead_letter_tasks(store.as_ref(), &filter, offset, limit).await {
+        Ok((tasks, total)) => HttpResponse::Ok().json(json!({
+            "tasks": tasks.iter().map(BaseTask::redacted).collect::<Vec<_>>(),
+            "count": tasks.len(),
+            "total": total,
+            "offset": offset,
+            "limit": limit
+        })),
+        Err(error) => task_error_response(error),
     }
 }
 
-pub async fn dead_letter_tasks(
+/// The optional body of `POST /dead-letter-tasks/{id}/replay`.
+#[derive(Deserialize, Debug, Default)]
+pub struct ReplayRequest {
+    /// Set on the task before it is requeued, e.g. credentials redacted in the dead-letter queue.
+    #[serde(default)]
+    pub headers: DeliveryHeaders,
+}
+
+pub async fn replay_dead_letter_task(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
+    body: Option<web::Json<ReplayRequest>>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    let headers = body.map(|body| body.into_inner().headers).unwrap_or_default();
+    match queue::replay_dead_letter_task_with_headers(store.as_ref(), &task_id, &headers).await {
+        Ok(Some(task)) => HttpResponse::Ok().json(json!({"status": "Task replayed", "task": task.redacted()})),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "Dead-lettered task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+// One bulk replay request handles at most this many tasks; `remaining` tells the client to repeat it.
+const MAX_BULK_REPLAY: usize = 1000;
+
+pub async fn replay_dead_letter_tasks(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    filter: web::Json<TaskFilter>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::replay_dead_letter_tasks(store.as_ref(), &filter, MAX_BULK_REPLAY).await {
+        Ok(report) => {
+            let failed: Vec<_> = report
+                .failed
+                .into_iter()
+                .map(|(id, error)| json!({"id": id, "error": error.to_string()}))
+                .collect();
+            HttpResponse::Ok().json(json!({
+                "status": "Dead-lettered tasks replayed",
+                "replayed": report.replayed.len(),
+                "replayed_ids": report.replayed,
+                "failed": failed,
+                "remaining": report.remaining
+            }))
+        }
+        Err(error) => task_error_response(error),
+    }
+}
+
+pub async fn delete_dead_letter_task(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::delete_dead_letter_task(store.as_ref(), &task_id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({"status": "Dead-lettered task deleted"})),
+        Ok(false) => HttpResponse::NotFound().json(json!({"error": "Dead-lettered task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+pub async fn purge_dead_letter_tasks(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::purge_dead_letter_tasks(store.as_ref()).await {
+        Ok(purged) => HttpResponse::Ok().json(json!({"status": "Dead-letter queue purged", "purged": purged})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+pub async fn get_task(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::get_task(store.as_ref(), &task_id).await {
+        Ok(Some((task, state))) => HttpResponse::Ok().json(json!({"task": task.redacted(), "state": state})),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "Task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+pub async fn task_occurrences(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::list_occurrences(store.as_ref(), &task_id).await {
+        Ok(Some(occurrences)) => HttpResponse::Ok().json(json!({
+            "task_id": task_id.as_str(),
+            "count": occurrences.len(),
+            "occurrences": occurrences
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "Task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+/// Lists the recorded delivery attempts of a task, newest first.
+pub async fn task_runs(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::list_task_runs(store.as_ref(), &task_id).await {
+        Ok(Some(runs)) => HttpResponse::Ok().json(json!({
+            "task_id": task_id.as_str(),
+            "count": runs.len(),
+            "runs": runs
+        })),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "Task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Deserialize, Debug)]
+pub struct TaskListQuery {
+    pub category: Option<String>,
+    pub priority: Option<TaskPriority>,
+    pub scheduled_after: Option<u64>,
+    pub scheduled_before: Option<u64>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+impl TaskListQuery {
+    fn into_page(self) -> (TaskFilter, usize, usize) {
+        let filter = TaskFilter {
+            category: self.category,
+            priority: self.priority,
+            scheduled_after: self.scheduled_after,
+            scheduled_before: self.scheduled_before,
+        };
+        let offset = self.offset.unwrap_or(0);
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        (filter, offset, limit)
+    }
+}
+
+pub async fn list_tasks(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    query: web::Query<TaskListQuery>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    let (filter, offset, limit) = query.into_inner().into_page();
+
+    match queue::list_tasks(store.as_ref(), &filter, offset, limit).await {
+        Ok((tasks, total)) => HttpResponse::Ok().json(json!({
+            "tasks": tasks.iter().map(BaseTask::redacted).collect::<Vec<_>>(),
+            "count": tasks.len(),
+            "total": total,
+            "offset": offset,
+            "limit": limit
+        })),
+        Err(error) => task_error_response(error),
+    }
+}
+
+pub async fn update_task(
+    req: HttpRequest,
+    data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
+    update: web::Json<TaskUpdate>,
+) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::update_task(store.as_ref(), &task_id, &update).await {
+        Ok(Some(task)) => HttpResponse::Ok().json(json!({"status": "Task updated", "task": task.redacted()})),
+        Ok(None) => HttpResponse::NotFound().json(json!({"error": "Task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+pub async fn cancel_task(
     req: HttpRequest,
     data: web::Data<Mutex<AppState>>,
+    task_id: web::Path<String>,
 ) -> impl Responder {
-    if let Err(response) = authorize_request(&req) {
+    if let Err(response) = authorize_request(&req, &data) {
+        return response;
+    }
+
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    match queue::cancel_task(store.as_ref(), &task_id).await {
+        Ok(true) => HttpResponse::Ok().json(json!({"status": "Task cancelled"})),
+        Ok(false) => HttpResponse::NotFound().json(json!({"error": "Task not found"})),
+        Err(error) => task_error_response(error),
+    }
+}
+
+/// Reports scheduler metrics in the Prometheus text format.
+pub async fn metrics_report(req: HttpRequest, data: web::Data<Mutex<AppState>>) -> impl Responder {
+    if let Err(response) = authorize_request(&req, &data) {
         return response;
     }
 
-    let redis_client = match data.lock() {
-        Ok(state) => state.redis_client.clone(),
+    let store = match data.lock() {
+        Ok(state) => state.store.clone(),
         Err(e) => {
             return HttpResponse::InternalServerError()
                 .json(json!({"error": format!("Application state unavailable: {e}")}));
         }
     };
 
-    match queue::get_dead_letter_tasks(&redis_client).await {
-        Ok(tasks) => HttpResponse::Ok().json(json!({"tasks": tasks, "count": tasks.len()})),
+    match queue::queue_stats(store.as_ref()).await.and_then(|stats| metrics::render(&stats)) {
+        Ok(report) => HttpResponse::Ok().content_type(metrics::content_type()).body(report),
         Err(error) => task_error_response(error),
     }
 }
@@ -159,6 +657,30 @@ pub async fn health_check() -> impl Responder {
     HttpResponse::Ok().json(json!({"status": "ok"}))
 }
 
+// How long `/readyz` waits for the store to answer a ping.
+const STORE_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Reports whether the store, dispatcher, fetcher and workers are healthy. Answers `503` when any of
+/// them is degraded or down. Unlike `/healthz`, which only shows the process is up, this is meant
+/// for readiness probes.
+pub async fn readiness_check(data: web::Data<Mutex<AppState>>) -> impl Responder {
+    let (store, health) = match data.lock() {
+        Ok(state) => (state.store.clone(), state.health.clone()),
+        Err(e) => {
+            return HttpResponse::InternalServerError()
+                .json(json!({"error": format!("Application state unavailable: {e}")}));
+        }
+    };
+
+    let latency = queue::store_latency(store.as_ref(), STORE_PING_TIMEOUT).await;
+    let readiness = health.readiness(Utc::now().timestamp(), latency);
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
 pub async fn not_found() -> impl Responder {
     HttpResponse::NotFound().json(json!({"error": "Not Found"}))
 }
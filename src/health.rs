@@ -0,0 +1,155 @@
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::errors::TaskQueueError;
+
+// The dispatcher beats at least every few seconds, even while it waits for a free worker.
+const DISPATCHER_STALE_AFTER_SECS: i64 = 30;
+// This many seconds without a good fetch means the fetcher's source is failing. Slow poll
+// intervals get three intervals instead.
+const FETCHER_STALE_AFTER_SECS: i64 = 60;
+const FETCHER_STALE_AFTER_INTERVALS: i64 = 3;
+// A full worker channel is normal under load; only a full channel that no worker has taken a task
+// from for this long, as long as the default lease, means the workers are stuck.
+const WORKERS_STALLED_AFTER_SECS: i64 = 300;
+// A store that answers, but this slowly, is reported as degraded.
+const STORE_SLOW_AFTER: Duration = Duration::from_secs(1);
+
+/// Liveness signals of the background loops, reported by `/readyz`. Timestamps are Unix seconds,
+/// and 0 means the loop is not running in this process.
+#[derive(Debug, Default)]
+pub struct Health {
+    dispatcher_heartbeat: AtomicI64,
+    fetcher_started_at: AtomicI64,
+    fetcher_stale_after: AtomicI64,
+    fetcher_success: AtomicI64,
+    channel_occupied: AtomicUsize,
+    channel_capacity: AtomicUsize,
+    last_dispatch: AtomicI64,
+}
+
+/// How one component is doing.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Ok,
+    Degraded,
+    Down,
+    /// Not running in this process.
+    Disabled,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ComponentReport {
+    pub status: ComponentStatus,
+    #[serde(flatten)]
+    pub details: serde_json::Value,
+}
+
+/// The readiness of each component, as reported by `/readyz`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Readiness {
+    pub ready: bool,
+    pub store: ComponentReport,
+    pub dispatcher: ComponentReport,
+    pub fetcher: ComponentReport,
+    pub workers: ComponentReport,
+}
+
+fn report(status: ComponentStatus, details: serde_json::Value) -> ComponentReport {
+    ComponentReport { status, details }
+}
+
+// How stale a loop's last beat is, or None when the loop is not running.
+fn age(beat: i64, now: i64) -> Option<i64> {
+    (beat > 0).then(|| (now - beat).max(0))
+}
+
+impl Health {
+    /// Records that the dispatcher is alive and how full the channel to the workers is.
+    pub fn dispatcher_heartbeat(&self, now: i64, occupied: usize, capacity: usize) {
+        self.dispatcher_heartbeat.store(now, Ordering::Relaxed);
+        self.channel_occupied.store(occupied, Ordering::Relaxed);
+        self.channel_capacity.store(capacity, Ordering::Relaxed);
+    }
+
+    /// Records that the dispatcher handed a claimed task to the worker channel. While the channel
+    /// is full a send only completes once a worker takes a task, so a full channel without recent
+    /// dispatches means the workers are stuck.
+    pub fn task_dispatched(&self, now: i64) {
+        self.last_dispatch.store(now, Ordering::Relaxed);
+    }
+
+    /// Records that the fetcher started polling every `interval_secs`.
+    pub fn fetcher_started(&self, now: i64, interval_secs: u64) {
+        let interval_secs = i64::try_from(interval_secs).unwrap_or(i64::MAX);
+        let stale_after = FETCHER_STALE_AFTER_SECS.max(interval_secs.saturating_mul(FETCHER_STALE_AFTER_INTERVALS));
+        self.fetcher_stale_after.store(stale_after, Ordering::Relaxed);
+        self.fetcher_started_at.store(now, Ordering::Relaxed);
+    }
+
+    pub fn fetcher_succeeded(&self, now: i64) {
+        self.fetcher_success.store(now, Ordering::Relaxed);
+    }
+
+    /// Reports every component at `now`, given how long the store took to answer a ping.
+    pub fn readiness(&self, now: i64, store_latency: Result<Duration, TaskQueueError>) -> Readiness {
+        let store = match store_latency {
+            Ok(latency) => {
+                let status = if latency > STORE_SLOW_AFTER { ComponentStatus::Degraded } else { ComponentStatus::Ok };
+                report(status, serde_json::json!({"latency_ms": latency.as_millis() as u64}))
+            }
+            Err(error) => report(ComponentStatus::Down, serde_json::json!({"error": error.to_string()})),
+        };
+
+        let heartbeat = self.dispatcher_heartbeat.load(Ordering::Relaxed);
+        let dispatcher = match age(heartbeat, now) {
+            None => report(ComponentStatus::Disabled, serde_json::json!({})),
+            Some(age) => {
+                let status = if age > DISPATCHER_STALE_AFTER_SECS { ComponentStatus::Down } else { ComponentStatus::Ok };
+                report(status, serde_json::json!({"last_heartbeat": heartbeat, "age_secs": age}))
+            }
+        };
+
+        let started_at = self.fetcher_started_at.load(Ordering::Relaxed);
+        let success = self.fetcher_success.load(Ordering::Relaxed);
+        let fetcher = match age(success.max(started_at), now) {
+            None => report(ComponentStatus::Disabled, serde_json::json!({})),
+            Some(age) => {
+                let stale_after = self.fetcher_stale_after.load(Ordering::Relaxed);
+                let status = if age > stale_after { ComponentStatus::Degraded } else { ComponentStatus::Ok };
+                let last_success = (success > 0).then_some(success);
+                report(status, serde_json::json!({"last_success": last_success, "age_secs": age}))
+            }
+        };
+
+        let workers = match dispatcher.status {
+            ComponentStatus::Disabled => report(ComponentStatus::Disabled, serde_json::json!({})),
+            _ => {
+                let occupied = self.channel_occupied.load(Ordering::Relaxed);
+                let capacity = self.channel_capacity.load(Ordering::Relaxed);
+                let last_dispatch = self.last_dispatch.load(Ordering::Relaxed);
+                // A full channel means every worker is busy and more claimed tasks are waiting.
+                let saturated = capacity > 0 && occupied >= capacity;
+                let stalled = saturated && age(last_dispatch, now).is_none_or(|age| age > WORKERS_STALLED_AFTER_SECS);
+                let status = if stalled { ComponentStatus::Degraded } else { ComponentStatus::Ok };
+                report(
+                    status,
+                    serde_json::json!({
+                        "channel_occupied": occupied,
+                        "channel_capacity": capacity,
+                        "saturated": saturated,
+                        "last_dispatch": (last_dispatch > 0).then_some(last_dispatch),
+                    }),
+                )
+            }
+        };
+
+        let ready = [&store, &dispatcher, &fetcher, &workers]
+            .iter()
+            .all(|component| matches!(component.status, ComponentStatus::Ok | ComponentStatus::Disabled));
+        Readiness { ready, store, dispatcher, fetcher, workers }
+    }
+}
@@ -1,5 +1,13 @@
 pub mod task;
 pub mod worker;
 pub mod queue;
+pub mod store;
 pub mod errors;
 pub mod handlers;
+pub mod signing;
+pub mod ssrf;
+pub mod metrics;
+pub mod shutdown;
+pub mod health;
+pub mod config;
+pub mod fetcher;
@@ -1,20 +1,33 @@
 use std::env;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use actix_web::{web, App, HttpServer};
+use chrono::Utc;
 use clap::{Arg, ArgAction, Command};
-use redis::Client;
-use reqwest::Client as HttpClient;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 // local package imports
-use thermite::task::BaseTask;
-use thermite::worker;
-use thermite::queue;
-use thermite::handlers::{dead_letter_tasks, health_check, not_found, submit_task, submit_tasks, AppState};
+use thermite::config::{Config, ConfigLayer, Modes};
+use thermite::shutdown::{self, Shutdown};
+use thermite::fetcher::Fetcher;
+use thermite::health::Health;
+use thermite::metrics;
+use thermite::ssrf::{self, SystemResolver};
+use thermite::store::{self, TaskStore};
+use thermite::task::{self, BaseTask};
+use thermite::worker::{self, DeliveryPolicy};
+use thermite::queue::{self, QueueOrder};
+use thermite::handlers::{
+    cancel_task, dead_letter_tasks, delete_dead_letter_task, get_task, health_check, json_config, list_tasks,
+    metrics_report, not_found, purge_dead_letter_tasks, query_config, readiness_check, replay_dead_letter_task,
+    replay_dead_letter_tasks, submit_task, submit_tasks, task_occurrences, task_runs, update_task, AppState,
+};
 
 fn init_tracing() {
     let env_filter = EnvFilter::try_from_default_env()
@@ -27,181 +40,280 @@ fn init_tracing() {
         .try_init();
 }
 
-fn spawn_queue_dispatcher(redis_client: Client, tx: mpsc::Sender<BaseTask>) {
+fn invalid_input(error: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, error.to_string())
+}
+
+// Settings given on the command line, which override the config file and the environment.
+fn cli_layer(matches: &clap::ArgMatches) -> ConfigLayer {
+    ConfigLayer {
+        mode: matches.get_one::<Modes>("mode").copied(),
+        store_url: matches
+            .get_one::<String>("store-url")
+            .or_else(|| matches.get_one::<String>("redis-url"))
+            .cloned(),
+        bind_address: matches.get_one::<String>("tasks-url").cloned(),
+        workers: matches.get_one::<usize>("workers").copied(),
+        ..ConfigLayer::default()
+    }
+}
+
+fn load_config(matches: &clap::ArgMatches) -> std::io::Result<Config> {
+    let path = matches
+        .get_one::<String>("config")
+        .cloned()
+        .or_else(|| env::var("THERMITE_CONFIG").ok().filter(|path| !path.trim().is_empty()));
+    Config::load(path.as_deref().map(Path::new), cli_layer(matches)).map_err(invalid_input)
+}
+
+// How often the dispatcher reports a heartbeat while it waits for a free worker.
+const DISPATCHER_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+fn spawn_queue_dispatcher(
+    store: Arc<dyn TaskStore>,
+    health: Arc<Health>,
+    order: QueueOrder,
+    visibility_timeout: Duration,
+    tx: mpsc::Sender<BaseTask>,
+    mut shutdown: Shutdown,
+) {
     tokio::spawn(async move {
         loop {
-            match queue::dequeue_task(&redis_client).await {
-                Ok(Some(task)) => {
-                    if tx.send(task).await.is_err() {
+            let occupied = tx.max_capacity() - tx.capacity();
+            metrics::set_channel_occupancy(occupied, tx.max_capacity());
+            health.dispatcher_heartbeat(Utc::now().timestamp(), occupied, tx.max_capacity());
+
+            // A channel slot is reserved before claiming, so a claimed task never waits outside the
+            // channel where a shutdown could not release it.
+            let slot = tokio::select! {
+                biased;
+                _ = shutdown.triggered() => break,
+                slot = tx.reserve() => match slot {
+                    Ok(slot) => slot,
+                    Err(_) => {
                         warn!("worker channel closed while dispatching task");
                         break;
                     }
+                },
+                _ = tokio::time::sleep(DISPATCHER_HEARTBEAT_INTERVAL) => continue,
+            };
+            match queue::dequeue_task_with_lease(store.as_ref(), order, visibility_timeout).await {
+                Ok(Some(task)) => {
+                    slot.send(task);
+                    health.task_dispatched(Utc::now().timestamp());
+                    continue;
                 }
-                Ok(None) => {
-                    debug!("no tasks in the queue");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                }
-                Err(e) => {
-                    error!(error = %e, "failed to dequeue task");
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-                }
+                Ok(None) => debug!("no tasks in the queue"),
+                Err(e) => error!(error = %e, "failed to dequeue task"),
+            }
+            drop(slot);
+            tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
             }
         }
+        info!("stopped claiming tasks");
     });
 }
 
-fn spawn_task_processor(
-    redis_client: Client,
-    http_client: HttpClient,
-    mut rx: mpsc::Receiver<BaseTask>,
-) {
-    let http_client = Arc::new(http_client);
-
+fn spawn_lease_reaper(store: Arc<dyn TaskStore>, mut shutdown: Shutdown) {
     tokio::spawn(async move {
-        while let Some(task) = rx.recv().await {
-            let client = Arc::clone(&http_client);
-            let failure_client = redis_client.clone();
-            let handle = tokio::spawn(async move {
-                let failed_task = task.clone();
-
-                match worker::execute_task(client, task).await {
-                    Ok(_) => Ok::<(), reqwest::Error>(()),
-                    Err(error) => {
-                        if let Err(queue_error) =
-                            queue::handle_task_failure(&failure_client, &failed_task, &error.to_string()).await
-                        {
-                            error!(
-                                task_id = %failed_task.id,
-                                error = %queue_error,
-                                "failed to persist retry or dead-letter state"
-                            );
-                        }
-
-                        Err(error)
-                    }
-                }
-            });
-
-            match handle.await {
-                Ok(Ok(())) => info!("task executed successfully"),
-                Ok(Err(e)) => error!(error = %e, "task execution failed"),
-                Err(e) => error!(error = %e, "task worker join failed"),
+        loop {
+            if let Err(e) = queue::requeue_expired_leases(store.as_ref()).await {
+                error!(error = %e, "failed to requeue expired in-flight leases");
+            }
+            tokio::select! {
+                _ = shutdown.triggered() => break,
+                _ = tokio::time::sleep(Duration::from_secs(5)) => {}
             }
         }
     });
 }
 
-async fn start_receiver(
-    redis_client: Client,
-    http_client: HttpClient,
+async fn start_api(
     data: web::Data<Mutex<AppState>>,
-    tx: mpsc::Sender<BaseTask>,
-    rx: mpsc::Receiver<BaseTask>
+    config: &Config,
+    mut shutdown: Shutdown,
 ) -> std::io::Result<()> {
+    let bind_address = config.bind_address.clone();
+    info!(bind_address = %bind_address, "starting API HTTP server");
 
-    spawn_queue_dispatcher(redis_client.clone(), tx);
-    spawn_task_processor(redis_client, http_client, rx);
-
-    let bind_address = env::var("TASKS_URL").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
-    info!(bind_address = %bind_address, "starting receiver HTTP server");
-
-    match HttpServer::new(move || {
+    // Signals are handled by `main`, which stops the server through its handle.
+    let server = match HttpServer::new(move || {
         App::new()
             .app_data(data.clone())
+            .app_data(json_config())
+            .app_data(query_config())
             .route("/healthz", web::get().to(health_check))
+            .route("/readyz", web::get().to(readiness_check))
+            .route("/metrics", web::get().to(metrics_report))
             .route("/dead-letter-tasks", web::get().to(dead_letter_tasks))
+            .route("/dead-letter-tasks", web::delete().to(purge_dead_letter_tasks))
+            .route("/dead-letter-tasks/replay", web::post().to(replay_dead_letter_tasks))
+            .route("/dead-letter-tasks/{id}/replay", web::post().to(replay_dead_letter_task))
+            .route("/dead-letter-tasks/{id}", web::delete().to(delete_dead_letter_task))
             .route("/submit-task",web::post().to(submit_task))
             .route("/submit-tasks",web::post().to(submit_tasks))
+            .route("/tasks", web::get().to(list_tasks))
+            .route("/tasks/{id}", web::get().to(get_task))
+            .route("/tasks/{id}/occurrences", web::get().to(task_occurrences))
+            .route("/tasks/{id}/runs", web::get().to(task_runs))
+            .route("/tasks/{id}", web::patch().to(update_task))
+            .route("/tasks/{id}", web::delete().to(cancel_task))
             .default_service(web::route().to(not_found))
     })
+    .disable_signals()
+    .shutdown_timeout(config.shutdown_timeout_secs)
     .bind(&bind_address) {
-        Ok(server) => server.run().await,
+        Ok(server) => server.run(),
         Err(e) => {
             error!(error = %e, bind_address = %bind_address, "failed to bind server");
-            Err(e)
+            return Err(e);
         }
-    }
+    };
+
+    let handle = server.handle();
+    tokio::spawn(async move {
+        shutdown.triggered().await;
+        info!("stopping API HTTP server");
+        handle.stop(true).await;
+    });
+    server.await
 }
 
 async fn start_fetcher(
-    redis_client: Client,
-    http_client: HttpClient,
-    data: web::Data<Mutex<AppState>>,
-    tx: mpsc::Sender<BaseTask>,
-    rx: mpsc::Receiver<BaseTask>
+    store: Arc<dyn TaskStore>,
+    config: &Config,
+    health: Arc<Health>,
+    shutdown: Shutdown,
 ) -> std::io::Result<()> {
+    let fetcher = Fetcher::new(store, config, health).map_err(invalid_input)?;
+    fetcher.run(shutdown).await;
+    Ok(())
+}
 
-    // Get the URL to fetch tasks from
-    let fetch_url = env::var("FETCH_URL").map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            format!("FETCH_URL must be set: {e}"),
-        )
-    })?;
-    info!("starting fetcher loop");
-
-    spawn_queue_dispatcher(redis_client.clone(), tx);
-    spawn_task_processor(redis_client, http_client.clone(), rx);
-
-    // Fetch tasks from the URL and enqueue them
-    // Spawning a task to fetch tasks from the given URL every second
-    loop {
-        let res = http_client.get(fetch_url.as_str()).send().await;
-        match res {
-            Ok(res) => {
-                if let Ok(tasks) = res.json::<Vec<BaseTask>>().await {
-                    for task in tasks {
-                        let redis_client = match data.lock() {
-                            Ok(state) => state.redis_client.clone(),
-                            Err(e) => {
-                                error!(error = %e, "application state unavailable while enqueueing fetched tasks");
-                                continue;
-                            }
-                        };
-                        match queue::enqueue_task(&redis_client, &task).await {
-                            Ok(_) => info!(task_id = %task.id, "task enqueued from fetcher"),
-                            Err(e) => warn!(task_id = %task.id, error = %e, "failed to enqueue fetched task"),
-                        }
-                    }
-                }
-            }
-            Err(e) => warn!(error = %e, "failed to fetch tasks"),
-        }
-        tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+// Claims due tasks and delivers them: the dispatcher, the lease reaper and the worker pool. Returns
+// the pool's handle, which completes once running deliveries have drained after a shutdown.
+fn start_worker(
+    store: Arc<dyn TaskStore>,
+    health: Arc<Health>,
+    config: &Config,
+    shutdown: Shutdown,
+) -> std::io::Result<JoinHandle<()>> {
+    // Create the HTTP client used for task deliveries. It refuses to connect to private or
+    // otherwise blocked addresses, whatever a target's hostname resolves to.
+    let timeout = Duration::from_secs(task::DEFAULT_TIMEOUT_SECS);
+    let http_client = ssrf::delivery_client(SystemResolver, timeout, config.targets.clone())
+        .map_err(|e| std::io::Error::other(format!("Failed to build HTTP client: {e}")))?;
+
+    let workers = config.workers;
+    info!(workers, "configured task worker pool");
+
+    let signer = config.signer().map_err(invalid_input)?;
+    if signer.is_some() {
+        info!("signing task deliveries with HMAC-SHA256");
     }
+
+    // The channel holds at most one claimed task per worker so the dispatcher stops claiming
+    // (and leasing) tasks as soon as every worker is busy.
+    let (tx, rx): (mpsc::Sender<BaseTask>, mpsc::Receiver<BaseTask>) = mpsc::channel(workers);
+    spawn_queue_dispatcher(
+        Arc::clone(&store),
+        health,
+        config.queue_order,
+        config.visibility_timeout(),
+        tx,
+        shutdown.clone(),
+    );
+    spawn_lease_reaper(Arc::clone(&store), shutdown.clone());
+    let policy = DeliveryPolicy { retry: config.retry.clone(), targets: config.targets.clone() };
+    Ok(worker::spawn_task_processor(store, http_client, signer, policy, rx, workers, shutdown))
 }
 
+async fn run_if(enabled: bool, component: impl Future<Output = std::io::Result<()>>) -> std::io::Result<()> {
+    if enabled {
+        component.await
+    } else {
+        Ok(())
+    }
+}
 
 fn cli() -> Command {
     Command::new("thermite")
         .version("1.0")
         .author("Uka Osim <hsojo91@gmail.com>")
-        .about("Runs tasks either as a receiver or fetcher")
+        .about("Receives, fetches, schedules and delivers HTTP tasks")
         .arg(Arg::new("mode")
             .short('m')
             .long("mode")
-            .help("Sets the operation mode of the application")
+            .help("Sets which components run: a comma-separated list of api, worker, fetcher or all; \
+                   receiver is short for api,worker [env: THERMITE_MODE] (default: api,worker)")
             .action(ArgAction::Set)
             .value_name("MODE")
-            .value_parser(["receiver", "fetcher"])
-            .default_value("receiver")
-            .required(true))
+            .value_parser(|value: &str| value.parse::<Modes>()))
         .arg(Arg::new("redis-url")
             .short('r')
             .long("redis-url")
-            .help("Sets the Redis server URL")
+            .help("Sets the Redis server URL [env: REDIS_URL]")
+            .action(ArgAction::Set)
+            .value_name("REDIS_URL"))
+        .arg(Arg::new("store-url")
+            .short('s')
+            .long("store-url")
+            .help("Sets where tasks are stored: a redis://, postgres://, sqlite: or memory:// URL \
+                   [env: THERMITE_STORE_URL] (overrides --redis-url)")
             .action(ArgAction::Set)
-            .value_name("REDIS_URL")
-            // .required(true)  // If you want this to be always required
-            .default_value("redis://localhost:6379"))
+            .value_name("STORE_URL"))
         .arg(Arg::new("tasks-url")
             .short('t')
             .long("tasks-url")
-            .help("Sets the URL to listen for tasks")
+            .help("Sets the address to listen for tasks on [env: TASKS_URL] (default: 127.0.0.1:8080)")
+            .action(ArgAction::Set)
+            .value_name("TASKS_URL"))
+        .arg(Arg::new("workers")
+            .short('w')
+            .long("workers")
+            .help("Sets how many task deliveries may run concurrently [env: THERMITE_WORKERS]")
+            .action(ArgAction::Set)
+            .value_name("WORKERS")
+            .value_parser(parse_worker_count))
+        .arg(Arg::new("config")
+            .short('c')
+            .long("config")
+            .help("Reads settings from a TOML file; environment variables and flags override it \
+                   [env: THERMITE_CONFIG]")
             .action(ArgAction::Set)
-            .value_name("TASKS_URL")
-            .requires_if("receiver", "mode")
-            .default_value("localhost:8080"))
+            .value_name("CONFIG"))
+        .subcommand(Command::new("migrate")
+            .about("Moves queued and dead-lettered tasks a Redis store still holds in the layout of earlier \
+                    releases into the current one, then exits"))
+}
+
+async fn migrate_store(config: &Config) -> std::io::Result<()> {
+    match store::migrate_legacy_layout(&config.store_url).await {
+        Ok(Some(migration)) => {
+            info!(
+                tasks = migration.tasks,
+                dead_letters = migration.dead_letters,
+                skipped = migration.skipped,
+                categorized = migration.categorized,
+                "migrated the legacy Redis layout"
+            );
+            Ok(())
+        }
+        Ok(None) => {
+            info!("only Redis stores have a legacy layout; nothing to migrate");
+            Ok(())
+        }
+        Err(e) => Err(std::io::Error::other(format!("Migration failed: {e}"))),
+    }
+}
+
+fn parse_worker_count(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(workers) if workers > 0 => Ok(workers),
+        _ => Err(format!("'{value}' is not a positive integer")),
+    }
 }
 
 #[tokio::main]
@@ -210,36 +322,63 @@ async fn main() -> std::io::Result<()> {
 
     let matches = cli().get_matches();
 
-    let mode = matches.get_one::<String>("mode").unwrap();
-    info!(mode = %mode, "starting thermite");
-
-    let default_redis_url = env::var("REDIS_URL").unwrap_or_else(|_| "redis://localhost:6379".to_string());
-    let redis_url = matches.get_one::<String>("redis-url").unwrap_or(&default_redis_url);
+    let config = load_config(&matches)?;
+    if matches.subcommand_matches("migrate").is_some() {
+        return migrate_store(&config).await;
+    }
+    info!(mode = %config.mode, "starting thermite");
 
-    // Create the Redis client
-    let redis_client = Client::open(redis_url.as_str()).map_err(|e| {
+    // Open the task store. Postgres and SQLite URLs need the `sql` feature.
+    let store = store::connect(&config.store_url).await.map_err(|e| {
         std::io::Error::new(
             std::io::ErrorKind::InvalidInput,
-            format!("Invalid Redis URL: {e}"),
+            format!("Invalid store URL: {e}"),
         )
     })?;
-    // Create a new HTTP client allow for http requests
-    let http_client = HttpClient::builder()
-        .timeout(std::time::Duration::from_secs(15))
-        .build()
-        .map_err(|e| std::io::Error::other(format!("Failed to build HTTP client: {e}")))?;
+    let health = Arc::new(Health::default());
+    let config = Arc::new(config);
     let data = web::Data::new(Mutex::new(AppState {
-        redis_client: redis_client.clone(),
+        store: Arc::clone(&store),
+        health: Arc::clone(&health),
+        config: Arc::clone(&config),
     }));
 
-    let (tx, rx): (mpsc::Sender<BaseTask>, mpsc::Receiver<BaseTask>) = mpsc::channel(32);
+    let (trigger, shutdown) = shutdown::channel();
+    let trigger = Arc::new(trigger);
+    let signal_trigger = Arc::clone(&trigger);
+    tokio::spawn(async move {
+        shutdown::wait_for_signal().await;
+        info!("shutdown requested; draining task deliveries");
+        signal_trigger.trigger();
+    });
 
-    if mode == "receiver" {
-        let _ = start_receiver(redis_client, http_client, data, tx, rx).await;
-    } else if mode == "fetcher" {
-        let _ = start_fetcher(redis_client, http_client, data, tx, rx).await;
+    let processor = if config.mode.worker {
+        Some(start_worker(Arc::clone(&store), Arc::clone(&health), &config, shutdown.clone())?)
     } else {
-        error!(mode = %mode, "invalid APP_MODE; must be 'receiver' or 'fetcher'");
+        None
+    };
+
+    let result = tokio::try_join!(
+        run_if(config.mode.api, start_api(data, &config, shutdown.clone())),
+        run_if(config.mode.fetcher, start_fetcher(Arc::clone(&store), &config, Arc::clone(&health), shutdown.clone())),
+        // A worker-only process has nothing in the foreground and runs until it is asked to stop.
+        run_if(!config.mode.api && !config.mode.fetcher, async {
+            shutdown.clone().triggered().await;
+            Ok(())
+        }),
+    )
+    .map(|_| ());
+
+    // A component may also have stopped on its own, e.g. when the server failed to bind.
+    trigger.trigger();
+    if let Some(processor) = processor {
+        match tokio::time::timeout(config.shutdown_timeout(), processor).await {
+            Ok(_) => info!("all task deliveries finished; shutting down"),
+            Err(_) => warn!(
+                timeout_secs = config.shutdown_timeout_secs,
+                "task deliveries still running at the shutdown deadline; they are retried once their leases expire"
+            ),
+        }
     }
-    Ok(())
+    result
 }
@@ -0,0 +1,209 @@
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::errors::TaskQueueError;
+use crate::queue::QueueStats;
+use crate::task::BaseTask;
+
+// Every per-task metric is labelled with the task's category and priority. Categories are free
+// text, so only the ones Thermite schedules on become label values; any other is counted as
+// `other`, which keeps the number of series bounded whatever clients submit.
+const TASK_LABELS: &[&str] = &["category", "priority"];
+const CATEGORY_LABELS: &[&str] = &["periodic", "non_periodic"];
+
+// Deliveries range from a few milliseconds to the longest delivery timeout.
+const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+// Lag is normally under the dispatcher's one second poll, and grows when workers fall behind.
+const LAG_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0, 900.0, 3600.0];
+
+struct Metrics {
+    registry: Registry,
+    enqueued: IntCounterVec,
+    dequeued: IntCounterVec,
+    succeeded: IntCounterVec,
+    failed: IntCounterVec,
+    retried: IntCounterVec,
+    dead_lettered: IntCounterVec,
+    delivery_duration: HistogramVec,
+    scheduling_lag: HistogramVec,
+    queue_depth: IntGaugeVec,
+    in_flight: IntGauge,
+    dead_letter_size: IntGauge,
+    channel_occupancy: IntGauge,
+    channel_capacity: IntGauge,
+    fetches: IntCounterVec,
+    fetched_tasks: IntCounterVec,
+    fetcher_last_success: IntGauge,
+}
+
+fn task_counter(registry: &Registry, name: &str, help: &str) -> IntCounterVec {
+    let counter = IntCounterVec::new(Opts::new(name, help), TASK_LABELS).expect("valid counter");
+    registry.register(Box::new(counter.clone())).expect("unique metric name");
+    counter
+}
+
+fn task_histogram(registry: &Registry, name: &str, help: &str, buckets: &[f64]) -> HistogramVec {
+    let opts = HistogramOpts::new(name, help).buckets(buckets.to_vec());
+    let histogram = HistogramVec::new(opts, TASK_LABELS).expect("valid histogram");
+    registry.register(Box::new(histogram.clone())).expect("unique metric name");
+    histogram
+}
+
+fn gauge(registry: &Registry, name: &str, help: &str) -> IntGauge {
+    let gauge = IntGauge::new(name, help).expect("valid gauge");
+    registry.register(Box::new(gauge.clone())).expect("unique metric name");
+    gauge
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+
+        let queue_depth = IntGaugeVec::new(
+            Opts::new("thermite_queue_depth", "Queued tasks, split into those already due and those scheduled later"),
+            &["state"],
+        )
+        .expect("valid gauge");
+        registry.register(Box::new(queue_depth.clone())).expect("unique metric name");
+
+        let fetches = IntCounterVec::new(
+            Opts::new("thermite_fetches_total", "Polls of the task source, by whether they succeeded"),
+            &["outcome"],
+        )
+        .expect("valid counter");
+        registry.register(Box::new(fetches.clone())).expect("unique metric name");
+        let fetched_tasks = IntCounterVec::new(
+            Opts::new("thermite_fetched_tasks_total", "Tasks returned by the task source, by whether they were accepted"),
+            &["outcome"],
+        )
+        .expect("valid counter");
+        registry.register(Box::new(fetched_tasks.clone())).expect("unique metric name");
+
+        Metrics {
+            enqueued: task_counter(&registry, "thermite_tasks_enqueued_total", "Tasks stored by an enqueue"),
+            dequeued: task_counter(&registry, "thermite_tasks_dequeued_total", "Tasks claimed for delivery"),
+            succeeded: task_counter(&registry, "thermite_task_deliveries_succeeded_total", "Successful deliveries"),
+            failed: task_counter(&registry, "thermite_task_deliveries_failed_total", "Failed deliveries"),
+            retried: task_counter(&registry, "thermite_tasks_retried_total", "Failed tasks requeued for a retry"),
+            dead_lettered: task_counter(
+                &registry,
+                "thermite_tasks_dead_lettered_total",
+                "Tasks moved to the dead-letter queue after exhausting their retries",
+            ),
+            delivery_duration: task_histogram(
+                &registry,
+                "thermite_task_delivery_duration_seconds",
+                "How long deliveries took, successful or not",
+                LATENCY_BUCKETS,
+            ),
+            scheduling_lag: task_histogram(
+                &registry,
+                "thermite_task_scheduling_lag_seconds",
+                "How late tasks were claimed, relative to the time they were scheduled for",
+                LAG_BUCKETS,
+            ),
+            queue_depth,
+            in_flight: gauge(&registry, "thermite_tasks_in_flight", "Tasks currently leased to a worker"),
+            dead_letter_size: gauge(&registry, "thermite_dead_letter_tasks", "Tasks in the dead-letter queue"),
+            channel_occupancy: gauge(
+                &registry,
+                "thermite_dispatcher_channel_occupancy",
+                "Claimed tasks waiting in the dispatcher channel for a free worker",
+            ),
+            channel_capacity: gauge(
+                &registry,
+                "thermite_dispatcher_channel_capacity",
+                "How many claimed tasks the dispatcher channel holds",
+            ),
+            fetches,
+            fetched_tasks,
+            fetcher_last_success: gauge(
+                &registry,
+                "thermite_fetcher_last_success_timestamp_seconds",
+                "Unix time of the last successful poll of the task source",
+            ),
+            registry,
+        }
+    }
+}
+
+static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+fn labels(task: &BaseTask) -> [&str; 2] {
+    let category = CATEGORY_LABELS
+        .iter()
+        .find(|&&known| known == task.category)
+        .copied()
+        .unwrap_or("other");
+    [category, task.priority.as_str()]
+}
+
+pub fn record_enqueued(task: &BaseTask) {
+    METRICS.enqueued.with_label_values(&labels(task)).inc();
+}
+
+/// Counts a claimed task and how long after its scheduled time it was claimed.
+pub fn record_dequeued(task: &BaseTask, now: i64) {
+    let lag = (now - task.scheduled_at as i64).max(0);
+    METRICS.dequeued.with_label_values(&labels(task)).inc();
+    METRICS.scheduling_lag.with_label_values(&labels(task)).observe(lag as f64);
+}
+
+pub fn record_delivery(task: &BaseTask, elapsed: Duration, succeeded: bool) {
+    METRICS.delivery_duration.with_label_values(&labels(task)).observe(elapsed.as_secs_f64());
+    let outcome = if succeeded { &METRICS.succeeded } else { &METRICS.failed };
+    outcome.with_label_values(&labels(task)).inc();
+}
+
+pub fn record_retried(task: &BaseTask) {
+    METRICS.retried.with_label_values(&labels(task)).inc();
+}
+
+pub fn record_dead_lettered(task: &BaseTask) {
+    METRICS.dead_lettered.with_label_values(&labels(task)).inc();
+}
+
+/// Records how full the channel between the dispatcher and the worker pool is.
+pub fn set_channel_occupancy(occupied: usize, capacity: usize) {
+    METRICS.channel_occupancy.set(i64::try_from(occupied).unwrap_or(i64::MAX));
+    METRICS.channel_capacity.set(i64::try_from(capacity).unwrap_or(i64::MAX));
+}
+
+/// Counts a poll of the task source; a successful one also becomes the last success at `now`.
+pub fn record_fetch(succeeded: bool, now: i64) {
+    let outcome = if succeeded { "succeeded" } else { "failed" };
+    METRICS.fetches.with_label_values(&[outcome]).inc();
+    if succeeded {
+        METRICS.fetcher_last_success.set(now);
+    }
+}
+
+pub fn record_fetched_tasks(accepted: usize, rejected: usize) {
+    METRICS.fetched_tasks.with_label_values(&["accepted"]).inc_by(accepted as u64);
+    METRICS.fetched_tasks.with_label_values(&["rejected"]).inc_by(rejected as u64);
+}
+
+/// Renders every metric in the Prometheus text format, after updating the queue gauges from
+/// `stats`.
+pub fn render(stats: &QueueStats) -> Result<String, TaskQueueError> {
+    let gauge_value = |count: usize| i64::try_from(count).unwrap_or(i64::MAX);
+    METRICS.queue_depth.with_label_values(&["due"]).set(gauge_value(stats.due));
+    METRICS.queue_depth.with_label_values(&["scheduled"]).set(gauge_value(stats.scheduled));
+    METRICS.in_flight.set(gauge_value(stats.in_flight));
+    METRICS.dead_letter_size.set(gauge_value(stats.dead_lettered));
+
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&METRICS.registry.gather(), &mut buffer)
+        .map_err(|e| TaskQueueError::StateError(format!("Failed to encode metrics: {e}")))?;
+    String::from_utf8(buffer).map_err(|e| TaskQueueError::StateError(format!("Failed to encode metrics: {e}")))
+}
+
+/// The content type of `render`'s output.
+pub fn content_type() -> String {
+    TextEncoder::new().format_type().to_string()
+}
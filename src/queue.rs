@@ -1,83 +1,356 @@
-use chrono::Utc;
-use redis::AsyncCommands;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, warn};
 
+use crate::config::RetryPolicy;
 use crate::errors::TaskQueueError;
-use crate::task::BaseTask;
+use crate::metrics;
+use crate::store::{SeriesOutcome, TaskStore};
+use crate::task::{BaseTask, DeliveryHeaders, MisfirePolicy, TaskPriority, TaskUpdate};
+
+/// What `enqueue_task_with_policy` does when a task with the same id already exists.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Fail with `TaskQueueError::DuplicateTask`.
+    Reject,
+    /// Overwrite the queued task (upsert). Tasks that are being delivered cannot be replaced.
+    Replace,
+    /// Leave the existing task untouched and report success.
+    #[default]
+    KeepExisting,
+}
+
+impl DuplicatePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DuplicatePolicy::Reject => "reject",
+            DuplicatePolicy::Replace => "replace",
+            DuplicatePolicy::KeepExisting => "keep_existing",
+        }
+    }
+}
+
+/// The result of a successful enqueue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnqueueOutcome {
+    Enqueued,
+    Replaced,
+    AlreadyExists,
+}
 
+/// Enqueues a task, keeping any existing task with the same id.
+pub async fn enqueue_task(store: &dyn TaskStore, task: &BaseTask) -> Result<(), TaskQueueError> {
+    enqueue_task_with_policy(store, task, DuplicatePolicy::KeepExisting).await?;
+    Ok(())
+}
 
-pub async fn enqueue_task(client: &redis::Client, task: &BaseTask) -> Result<(), TaskQueueError> {
+/// Enqueues a task, deduplicating on its id according to `policy`.
+pub async fn enqueue_task_with_policy(
+    store: &dyn TaskStore,
+    task: &BaseTask,
+    policy: DuplicatePolicy,
+) -> Result<EnqueueOutcome, TaskQueueError> {
     task.validate()?;
 
-    let mut conn = client.get_multiplexed_async_connection().await?;
-    let task_json = serde_json::to_string(task)?;
+    info!(
+        task_id = %task.id,
+        scheduled_at = task.scheduled_at,
+        category = %task.category,
+        priority = %task.priority,
+        "enqueuing task"
+    );
+
+    let outcome = store.enqueue(task, policy).await?;
+    if outcome != EnqueueOutcome::AlreadyExists {
+        metrics::record_enqueued(task);
+    }
+    match outcome {
+        EnqueueOutcome::Enqueued => info!(task_id = %task.id, "task enqueued"),
+        EnqueueOutcome::Replaced => info!(task_id = %task.id, "replaced existing task"),
+        EnqueueOutcome::AlreadyExists => info!(task_id = %task.id, "task already existed in queue"),
+    }
+    Ok(outcome)
+}
 
-    info!(task_id = %task.id, scheduled_at = task.scheduled_at, category = %task.category, "enqueuing task");
+/// The order in which due tasks of the same priority are claimed.
+///
+/// `Fifo` claims the task that has been due the longest first, so an overdue backlog drains in
+/// order. `Lifo` claims the most recently due task first.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum QueueOrder {
+    #[default]
+    Fifo,
+    Lifo,
+}
 
-    let was_set: bool = conn.zadd("task_queue", task_json, task.scheduled_at).await?;
-    if !was_set {
-        info!(task_id = %task.id, "task already existed in queue");
-    } else {
-        info!(task_id = %task.id, "task enqueued");
+impl QueueOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueOrder::Fifo => "fifo",
+            QueueOrder::Lifo => "lifo",
+        }
     }
-    Ok(())
 }
 
-async fn get_task(mut conn: redis::aio::MultiplexedConnection, now: i64) -> redis::RedisResult<Option<String>> {
-    // Get tasks from the queue based on the score (timestamp), taking the highest score up to 'now'
-    let tasks: Vec<(String, f64)> = conn.zrevrangebyscore_withscores("task_queue", now as f64, "-inf").await?;
-    // Retrieve the first task in the list, which will have the highest score within the range
-    Ok(tasks.into_iter().next().map(|(task, _score)| task))
+impl FromStr for QueueOrder {
+    type Err = TaskQueueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "fifo" => Ok(QueueOrder::Fifo),
+            "lifo" => Ok(QueueOrder::Lifo),
+            other => Err(TaskQueueError::InvalidConfiguration(format!(
+                "Unknown queue order '{other}'; expected 'fifo' or 'lifo'"
+            ))),
+        }
+    }
 }
 
-pub async fn dequeue_task(client: &redis::Client) -> Result<Option<BaseTask>, TaskQueueError> {
-    let mut conn = client.get_multiplexed_async_connection().await?;
-    let now = Utc::now().timestamp() as u64;
-    debug!(now, "checking queue for due tasks");
+impl TryFrom<String> for QueueOrder {
+    type Error = TaskQueueError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Identifies one claim of a task. The store hands out a new token with every lease and only acts
+/// on settle, release and lease extensions carrying the current one, so a worker whose lease ran
+/// out and was re-claimed by another cannot settle the newer claim.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LeaseToken(i64);
+
+impl LeaseToken {
+    pub(crate) fn new() -> Self {
+        LeaseToken(rand::random())
+    }
+
+    pub(crate) fn raw(self) -> i64 {
+        self.0
+    }
+}
+
+/// How long `dequeue_task` leases a claimed task before it is handed out again.
+pub const DEFAULT_VISIBILITY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How much longer than its delivery timeout a claimed task stays leased, so the worker has time to
+/// record the outcome before the task can be handed out again.
+pub const LEASE_MARGIN: Duration = Duration::from_secs(10);
+
+// When the lease on a task claimed at `now` runs out: after the visibility timeout, or once the
+// task's own `timeout_secs` has passed when that is later, so a slow delivery is never handed to a
+// second worker while it is still running. Tasks without a timeout use the client's, which
+// `Config::validate` keeps within the visibility timeout.
+fn lease_deadline(task: &BaseTask, now: i64, lease_secs: i64) -> i64 {
+    let delivery_secs = task
+        .timeout_secs
+        .map_or(0, |timeout_secs| timeout_secs.saturating_add(LEASE_MARGIN.as_secs()));
+    now.saturating_add(lease_secs.max(i64::try_from(delivery_secs).unwrap_or(i64::MAX)))
+}
+
+/// Claims the next due task for delivery.
+///
+/// Due periodic tasks are not delivered themselves: claiming one reschedules it from its own
+/// schedule and misfire policy, and returns a new occurrence of it (see `BaseTask::occurrence`) that
+/// is already leased for delivery. A stale run under the `skip` policy is only rescheduled.
+pub async fn dequeue_task(store: &dyn TaskStore, order: QueueOrder) -> Result<Option<BaseTask>, TaskQueueError> {
+    dequeue_task_with_lease(store, order, DEFAULT_VISIBILITY_TIMEOUT).await
+}
+
+/// Claims the next due task like `dequeue_task`, leasing it for `visibility_timeout`, or until its
+/// own `timeout_secs` (plus [`LEASE_MARGIN`]) has passed when that is longer.
+pub async fn dequeue_task_with_lease(
+    store: &dyn TaskStore,
+    order: QueueOrder,
+    visibility_timeout: Duration,
+) -> Result<Option<BaseTask>, TaskQueueError> {
+    let lease_secs = i64::try_from(visibility_timeout.as_secs()).unwrap_or(i64::MAX);
+    loop {
+        let now = Utc::now();
+        debug!(now = now.timestamp(), "checking queue for due tasks");
+
+        let lease_until = now.timestamp().saturating_add(lease_secs);
+        let Some(task) = store.claim(now.timestamp(), lease_until, order).await? else {
+            return Ok(None);
+        };
+        info!(task_id = %task.id, category = %task.category, priority = %task.priority, "dequeued task");
+
+        // The occurrences of a periodic task share its timeout, so they are leased like it.
+        let task_lease_until = lease_deadline(&task, now.timestamp(), lease_secs);
+        if task.category != "periodic" {
+            let extended = match task.lease {
+                Some(lease) if task_lease_until > lease_until => {
+                    store.extend_lease(&task.id, lease, task_lease_until).await?
+                }
+                _ => true,
+            };
+            if !extended {
+                info!(task_id = %task.id, "task was cancelled while being claimed");
+                continue;
+            }
+            metrics::record_dequeued(&task, now.timestamp());
+            return Ok(Some(task));
+        }
+        if let Some(occurrence) = spawn_occurrence(store, task, now, task_lease_until).await? {
+            metrics::record_dequeued(&occurrence, now.timestamp());
+            return Ok(Some(occurrence));
+        }
+    }
+}
 
-    let task_str = match get_task(conn.clone(), now as i64).await? {
-        Some(task_str) => task_str,
-        None => return Ok(None),
+// Reschedules a claimed periodic task and, unless its stale run is skipped, creates and leases the
+// occurrence to deliver. Returns None when there is nothing to deliver.
+async fn spawn_occurrence(
+    store: &dyn TaskStore,
+    mut series: BaseTask,
+    now: DateTime<Utc>,
+    lease_until: i64,
+) -> Result<Option<BaseTask>, TaskQueueError> {
+    series.missed_runs = series.count_missed_runs(now).unwrap_or_else(|e| {
+        warn!(task_id = %series.id, error = %e, "failed to count missed runs");
+        0
+    });
+    let skipped = series.misfire_policy == MisfirePolicy::Skip && series.missed_runs > 0;
+    let occurrence = (!skipped).then(|| series.occurrence());
+
+    let mut next_run = series.clone();
+    next_run.advance_schedule(now)?;
+    next_run.retry_count = 0;
+    next_run.is_retry = false;
+    next_run.last_error = None;
+    next_run.missed_runs = 0;
+
+    let outcome = store
+        .reschedule_series(&next_run, occurrence.as_ref(), lease_until)
+        .await?;
+
+    match (outcome, occurrence) {
+        (SeriesOutcome::Spawned(lease), Some(mut occurrence)) => {
+            occurrence.lease = Some(lease);
+            info!(
+                task_id = %series.id,
+                occurrence_id = %occurrence.id,
+                missed_runs = series.missed_runs,
+                next_scheduled_at = next_run.scheduled_at,
+                "created occurrence of periodic task"
+            );
+            Ok(Some(occurrence))
+        }
+        (SeriesOutcome::Cancelled, _) => {
+            info!(task_id = %series.id, "periodic task was cancelled while being claimed");
+            Ok(None)
+        }
+        (_, None) => {
+            info!(
+                task_id = %series.id,
+                missed_runs = series.missed_runs,
+                next_scheduled_at = next_run.scheduled_at,
+                "skipped stale run of periodic task"
+            );
+            Ok(None)
+        }
+        (_, Some(occurrence)) => {
+            warn!(task_id = %series.id, occurrence_id = %occurrence.id, "occurrence already exists; not delivering it again");
+            Ok(None)
+        }
+    }
+}
+
+// Settles a claimed task under the lease it was claimed with. A task that no longer holds its lease
+// (it was cancelled, or its lease expired and it was claimed again) is left alone.
+async fn settle_task(
+    store: &dyn TaskStore,
+    task: &BaseTask,
+    requeued: Option<&BaseTask>,
+    dead_letter: Option<&BaseTask>,
+) -> Result<bool, TaskQueueError> {
+    let Some(lease) = task.lease else {
+        warn!(task_id = %task.id, "task was not claimed; not settling it");
+        return Ok(false);
     };
+    let settled = store.settle(&task.id, lease, requeued, dead_letter, Utc::now().timestamp()).await?;
+    if !settled {
+        info!(task_id = %task.id, "task was cancelled or claimed again while in flight");
+    }
+    Ok(settled)
+}
 
-    let task: BaseTask = serde_json::from_str(&task_str)?;
+// `dequeue_task` never hands out periodic tasks themselves, only their occurrences. A periodic task
+// settled directly (e.g. one leased before occurrences existed) is still queued again for its next
+// run, with a clean retry state, so the series is never lost.
+fn next_periodic_run(task: &BaseTask) -> Result<Option<BaseTask>, TaskQueueError> {
+    if task.category != "periodic" {
+        return Ok(None);
+    }
 
-    let _: () = conn.zrem("task_queue", &task_str).await?;
-    info!(task_id = %task.id, category = %task.category, "dequeued task");
+    let mut next_task = task.clone();
+    next_task.advance_schedule(Utc::now())?;
+    next_task.retry_count = 0;
+    next_task.is_retry = false;
+    next_task.last_error = None;
+    next_task.missed_runs = 0;
+    Ok(Some(next_task))
+}
+
+/// Acknowledges a delivered task by releasing its in-flight lease and forgetting it. Periodic tasks
+/// settled directly are requeued for their next run instead.
+pub async fn ack_task(store: &dyn TaskStore, task: &BaseTask) -> Result<(), TaskQueueError> {
+    let next_task = next_periodic_run(task)?;
 
-    if task.category == "periodic" && !task.is_retry {
-        let mut rescheduled_task = task.clone();
-        rescheduled_task.set_next_unix_datetime()?;
-        let task_json = serde_json::to_string(&rescheduled_task)?;
-        let _: () = conn.zadd("task_queue", task_json, rescheduled_task.scheduled_at).await?;
-        info!(task_id = %rescheduled_task.id, next_scheduled_at = rescheduled_task.scheduled_at, "rescheduled periodic task");
+    if settle_task(store, task, next_task.as_ref(), None).await? {
+        debug!(task_id = %task.id, "acknowledged task");
+        if let Some(next_task) = next_task {
+            info!(task_id = %next_task.id, next_scheduled_at = next_task.scheduled_at, "rescheduled periodic task");
+        }
     }
+    Ok(())
+}
+
+/// Hands a claimed task that will not be delivered back to its queue, due at its scheduled time, so
+/// it can be claimed again without waiting for its lease to expire.
+pub async fn release_task(store: &dyn TaskStore, task: &BaseTask) -> Result<(), TaskQueueError> {
+    if settle_task(store, task, Some(task), None).await? {
+        debug!(task_id = %task.id, "released claimed task");
+    }
+    Ok(())
+}
+
+/// Moves every task whose lease has expired back into its queue and returns how many were requeued.
+pub async fn requeue_expired_leases(store: &dyn TaskStore) -> Result<usize, TaskQueueError> {
+    let requeued = store.requeue_expired_leases(Utc::now().timestamp(), 100).await?;
 
-    Ok(Some(task))
+    if requeued > 0 {
+        warn!(requeued, "requeued tasks whose in-flight lease expired");
+    }
+    Ok(requeued)
 }
 
+/// Records a failed delivery: requeues the task for a retry under `retry`, or dead-letters it once
+/// it is out of retries.
 pub async fn handle_task_failure(
-    client: &redis::Client,
+    store: &dyn TaskStore,
     task: &BaseTask,
     error_message: &str,
+    retry: &RetryPolicy,
 ) -> Result<(), TaskQueueError> {
-    let mut conn = client.get_multiplexed_async_connection().await?;
     let mut failed_task = task.clone();
 
-    if failed_task.schedule_retry(error_message) {
-        let task_json = serde_json::to_string(&failed_task)?;
-        let _: () = conn
-            .zadd("task_queue", task_json, failed_task.scheduled_at)
-            .await?;
-        warn!(
-            task_id = %failed_task.id,
-            retry_count = failed_task.retry_count,
-            scheduled_at = failed_task.scheduled_at,
-            "requeued failed task for retry"
-        );
-    } else {
-        let task_json = serde_json::to_string(&failed_task)?;
-        let _: () = conn.rpush("dead_letter_queue", task_json).await?;
+    if failed_task.schedule_retry(error_message, retry) {
+        if settle_task(store, task, Some(&failed_task), None).await? {
+            metrics::record_retried(&failed_task);
+            warn!(
+                task_id = %failed_task.id,
+                retry_count = failed_task.retry_count,
+                scheduled_at = failed_task.scheduled_at,
+                "requeued failed task for retry"
+            );
+        }
+    } else if settle_dead_letter(store, task, &failed_task).await? {
         error!(
             task_id = %failed_task.id,
             retry_count = failed_task.retry_count,
@@ -88,18 +361,378 @@ pub async fn handle_task_failure(
     Ok(())
 }
 
-pub async fn get_dead_letter_tasks(client: &redis::Client) -> Result<Vec<BaseTask>, TaskQueueError> {
-    let mut conn = client.get_multiplexed_async_connection().await?;
-    let entries: Vec<String> = conn.lrange("dead_letter_queue", 0, -1).await?;
+/// Dead-letters a failed delivery on its first failure, without retrying it. For failures no retry
+/// can fix, such as a target that is not allowed.
+pub async fn dead_letter_task(
+    store: &dyn TaskStore,
+    task: &BaseTask,
+    error_message: &str,
+) -> Result<(), TaskQueueError> {
+    let mut failed_task = task.clone();
+    failed_task.last_error = Some(error_message.to_string());
 
-    entries
-        .into_iter()
-        .map(|entry| serde_json::from_str(&entry).map_err(TaskQueueError::from))
-        .collect()
+    if settle_dead_letter(store, task, &failed_task).await? {
+        error!(task_id = %failed_task.id, "moved task to dead-letter queue without retrying it");
+    }
+    Ok(())
 }
 
-pub async fn clear_task_queue(client: &redis::Client) -> Result<(), TaskQueueError> {
-    let mut conn = client.get_multiplexed_async_connection().await?;
-    let _: () = conn.del("task_queue").await?;
+// Settles `task` into the dead-letter queue as `failed_task`. A periodic task settled directly is
+// queued again for its next run. Returns whether the task was still leased.
+async fn settle_dead_letter(
+    store: &dyn TaskStore,
+    task: &BaseTask,
+    failed_task: &BaseTask,
+) -> Result<bool, TaskQueueError> {
+    let next_task = next_periodic_run(failed_task)?;
+    // The dead-letter copy outlives the delivery, so it does not keep credentials around.
+    let settled = settle_task(store, task, next_task.as_ref(), Some(&failed_task.redacted())).await?;
+    if settled {
+        metrics::record_dead_lettered(failed_task);
+    }
+    Ok(settled)
+}
+
+/// Whether a stored task is waiting in its queue or currently leased to a worker.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Queued,
+    InFlight,
+}
+
+/// Looks up a queued or in-flight task by its id.
+pub async fn get_task(store: &dyn TaskStore, task_id: &str) -> Result<Option<(BaseTask, TaskState)>, TaskQueueError> {
+    store.get(task_id).await
+}
+
+/// Where an occurrence of a periodic task is in its lifecycle.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OccurrenceState {
+    Queued,
+    InFlight,
+    DeadLettered,
+    /// Delivered, or cancelled; no longer stored.
+    Finished,
+}
+
+/// One entry of a periodic task's occurrence history.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct OccurrenceSummary {
+    pub id: String,
+    pub scheduled_at: u64,
+    pub state: OccurrenceState,
+}
+
+/// Lists the most recent occurrences of a periodic task, newest first. Returns None when neither
+/// the task nor any occurrence of it is known.
+pub async fn list_occurrences(
+    store: &dyn TaskStore,
+    series_id: &str,
+) -> Result<Option<Vec<OccurrenceSummary>>, TaskQueueError> {
+    store.list_occurrences(series_id).await
+}
+
+/// How many tasks are in each part of the queue.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct QueueStats {
+    /// Queued tasks whose scheduled time has passed.
+    pub due: usize,
+    /// Queued tasks scheduled for later.
+    pub scheduled: usize,
+    pub in_flight: usize,
+    pub dead_lettered: usize,
+}
+
+/// Counts the queued, in-flight and dead-lettered tasks.
+pub async fn queue_stats(store: &dyn TaskStore) -> Result<QueueStats, TaskQueueError> {
+    store.stats(Utc::now().timestamp()).await
+}
+
+/// Pings the store and returns how long it took to answer. A store that does not answer within
+/// `timeout` counts as unreachable.
+pub async fn store_latency(store: &dyn TaskStore, timeout: Duration) -> Result<Duration, TaskQueueError> {
+    let started = Instant::now();
+    match tokio::time::timeout(timeout, store.ping()).await {
+        Ok(result) => result.map(|_| started.elapsed()),
+        Err(_) => Err(TaskQueueError::ConnectionError(format!(
+            "Store did not answer within {} ms",
+            timeout.as_millis()
+        ))),
+    }
+}
+
+/// One delivery attempt of a task, kept in the task's run history.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TaskRun {
+    /// 1 for the first delivery, 2 for the first retry, and so on.
+    pub attempt: u32,
+    /// Unix timestamp the delivery started at.
+    pub started_at: u64,
+    /// Unix timestamp the delivery finished at.
+    pub finished_at: u64,
+    pub latency_ms: u64,
+    /// The HTTP status the target answered with; unset when no response arrived.
+    pub status: Option<u16>,
+    /// Why the attempt failed; unset when it succeeded.
+    pub error: Option<String>,
+    /// For a run of a periodic task, the occurrence that was delivered.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub occurrence_id: Option<String>,
+}
+
+/// Records a delivery attempt of `task` in its run history. Runs of an occurrence are also recorded
+/// in the history of the periodic task it was created from.
+pub async fn record_task_run(store: &dyn TaskStore, task: &BaseTask, run: &TaskRun) -> Result<(), TaskQueueError> {
+    store.record_run(&task.id, run).await?;
+
+    if let Some(series_id) = task.series_id.as_deref() {
+        let series_run = TaskRun { occurrence_id: Some(task.id.clone()), ..run.clone() };
+        store.record_run(series_id, &series_run).await?;
+    }
     Ok(())
 }
+
+/// Lists the recorded runs of a task, newest first. Returns None when neither the task nor any run
+/// of it is known.
+pub async fn list_task_runs(store: &dyn TaskStore, task_id: &str) -> Result<Option<Vec<TaskRun>>, TaskQueueError> {
+    let runs = store.list_runs(task_id).await?;
+
+    if runs.is_empty() && store.get(task_id).await?.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(runs))
+}
+
+/// Narrows a task listing. Every field that is set must match.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub category: Option<String>,
+    pub priority: Option<TaskPriority>,
+    /// Only tasks scheduled at or after this Unix timestamp.
+    pub scheduled_after: Option<u64>,
+    /// Only tasks scheduled at or before this Unix timestamp.
+    pub scheduled_before: Option<u64>,
+}
+
+impl TaskFilter {
+    pub fn is_empty(&self) -> bool {
+        self.category.is_none()
+            && self.priority.is_none()
+            && self.scheduled_after.is_none()
+            && self.scheduled_before.is_none()
+    }
+
+    pub fn matches(&self, task: &BaseTask) -> bool {
+        self.category.as_ref().is_none_or(|category| &task.category == category)
+            && self.priority.is_none_or(|priority| task.priority == priority)
+            && self.scheduled_after.is_none_or(|after| task.scheduled_at >= after)
+            && self.scheduled_before.is_none_or(|before| task.scheduled_at <= before)
+    }
+}
+
+/// Lists queued tasks matching `filter`, ordered by due time, and returns the requested page along
+/// with the total number of matches.
+pub async fn list_tasks(
+    store: &dyn TaskStore,
+    filter: &TaskFilter,
+    offset: usize,
+    limit: usize,
+) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+    store.list(filter, offset, limit).await
+}
+
+/// Atomically applies `update` to a queued task and returns the updated task, or `None` when no
+/// such task exists. Tasks that are being delivered cannot be updated.
+pub async fn update_task(
+    store: &dyn TaskStore,
+    task_id: &str,
+    update: &TaskUpdate,
+) -> Result<Option<BaseTask>, TaskQueueError> {
+    let updated = store.update(task_id, &|task| update.apply(task)).await?;
+
+    if let Some(task) = &updated {
+        info!(task_id = %task_id, scheduled_at = task.scheduled_at, priority = %task.priority, "updated task");
+    }
+    Ok(updated)
+}
+
+/// Removes a queued or in-flight task by its id. Returns `false` when no such task exists.
+pub async fn cancel_task(store: &dyn TaskStore, task_id: &str) -> Result<bool, TaskQueueError> {
+    let removed = store.cancel(task_id).await?;
+
+    if removed {
+        info!(task_id = %task_id, "cancelled task");
+    }
+    Ok(removed)
+}
+
+/// Lists dead-lettered tasks matching `filter`, oldest failure first, and returns the requested page
+/// along with the total number of matches.
+pub async fn list_dead_letter_tasks(
+    store: &dyn TaskStore,
+    filter: &TaskFilter,
+    offset: usize,
+    limit: usize,
+) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+    store.list_dead_letters(filter, offset, limit).await
+}
+
+// Gives a replayed task a fresh retry budget and makes it due immediately. Headers redacted when
+// it was dead-lettered have to be in `headers`.
+fn prepare_replay(task: &mut BaseTask, headers: &DeliveryHeaders) -> Result<(), TaskQueueError> {
+    task.headers.merge(headers);
+    if let Some(name) = task.headers.redacted_names().first() {
+        return Err(TaskQueueError::InvalidDeliveryOptions(format!(
+            "Header '{name}' was redacted when the task was dead-lettered; supply it again to replay the task"
+        )));
+    }
+    task.retry_count = 0;
+    task.is_retry = false;
+    task.last_error = None;
+    task.scheduled_at = Utc::now().timestamp().max(0) as u64;
+    task.validate()
+}
+
+/// Requeues a dead-lettered task with a fresh retry budget, due immediately. Returns the requeued
+/// task, or `None` when no dead-lettered task has this id. Fails with
+/// `TaskQueueError::DuplicateTask` while a live task uses the same id, and with
+/// `TaskQueueError::InvalidDeliveryOptions` when the task had sensitive headers, whose values are
+/// not kept in the dead-letter queue; use [`replay_dead_letter_task_with_headers`] for those.
+pub async fn replay_dead_letter_task(store: &dyn TaskStore, task_id: &str) -> Result<Option<BaseTask>, TaskQueueError> {
+    replay_dead_letter_task_with_headers(store, task_id, &DeliveryHeaders::default()).await
+}
+
+/// Like [`replay_dead_letter_task`], setting `headers` on the task first, e.g. the credentials
+/// that were redacted when it was dead-lettered.
+pub async fn replay_dead_letter_task_with_headers(
+    store: &dyn TaskStore,
+    task_id: &str,
+    headers: &DeliveryHeaders,
+) -> Result<Option<BaseTask>, TaskQueueError> {
+    let replayed = store.replay_dead_letter(task_id, &|task| prepare_replay(task, headers)).await?;
+
+    if replayed.is_some() {
+        info!(task_id = %task_id, "replayed dead-lettered task");
+    }
+    Ok(replayed)
+}
+
+// Dead-lettered tasks are listed this many at a time while replaying in bulk.
+const REPLAY_BATCH_SIZE: usize = 100;
+
+/// What [`replay_dead_letter_tasks`] did.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub replayed: Vec<String>,
+    /// Ids that could not be replayed, with the reason. They stay dead-lettered.
+    pub failed: Vec<(String, TaskQueueError)>,
+    /// Matching tasks that were not tried because `limit` was reached.
+    pub remaining: usize,
+}
+
+/// Replays up to `limit` dead-lettered tasks matching `filter`, oldest failure first, listing them
+/// in batches of [`REPLAY_BATCH_SIZE`].
+pub async fn replay_dead_letter_tasks(
+    store: &dyn TaskStore,
+    filter: &TaskFilter,
+    limit: usize,
+) -> Result<ReplayReport, TaskQueueError> {
+    let mut report = ReplayReport::default();
+    loop {
+        let attempted = report.replayed.len() + report.failed.len();
+        let batch_size = limit.saturating_sub(attempted).min(REPLAY_BATCH_SIZE);
+        // Replayed tasks leave the dead-letter queue and failed ones stay, so the next untried task
+        // is always right after the failed ones.
+        let (tasks, total) = store.list_dead_letters(filter, report.failed.len(), batch_size).await?;
+        report.remaining = total.saturating_sub(report.failed.len());
+        if tasks.is_empty() {
+            break;
+        }
+
+        for task in tasks {
+            report.remaining = report.remaining.saturating_sub(1);
+            match replay_dead_letter_task(store, &task.id).await {
+                Ok(Some(_)) => report.replayed.push(task.id),
+                // Gone since it was listed, most likely replayed or deleted concurrently; leave it.
+                Ok(None) => {}
+                Err(e) => {
+                    warn!(task_id = %task.id, error = %e, "failed to replay dead-lettered task");
+                    report.failed.push((task.id, e));
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Deletes one dead-lettered task. Returns `false` when no dead-lettered task has this id.
+pub async fn delete_dead_letter_task(store: &dyn TaskStore, task_id: &str) -> Result<bool, TaskQueueError> {
+    let removed = store.delete_dead_letter(task_id).await?;
+
+    if removed {
+        info!(task_id = %task_id, "deleted dead-lettered task");
+    }
+    Ok(removed)
+}
+
+/// Deletes every dead-lettered task and returns how many there were.
+pub async fn purge_dead_letter_tasks(store: &dyn TaskStore) -> Result<usize, TaskQueueError> {
+    let purged = store.purge_dead_letters().await?;
+
+    warn!(purged, "purged dead-letter queue");
+    Ok(purged)
+}
+
+/// A response stored against an `Idempotency-Key`, replayed for repeated requests.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IdempotentResponse {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+/// Where a request carrying an `Idempotency-Key` stands.
+#[derive(Debug)]
+pub enum IdempotencyState {
+    /// First time this key is seen; the caller owns the key and must complete or release it.
+    New,
+    /// Another request with this key is still being processed.
+    InProgress,
+    /// The key was already used for a request with a different payload.
+    Mismatch,
+    /// The key was already used for this payload; replay the stored response.
+    Completed(IdempotentResponse),
+}
+
+/// Reserves `key` for a request with the given payload fingerprint, or reports how an earlier
+/// request with the same key went.
+pub async fn begin_idempotent_request(
+    store: &dyn TaskStore,
+    scope: &str,
+    key: &str,
+    fingerprint: &str,
+) -> Result<IdempotencyState, TaskQueueError> {
+    store.begin_idempotent_request(scope, key, fingerprint).await
+}
+
+/// Stores the response for a reserved idempotency key so repeated requests replay it.
+pub async fn complete_idempotent_request(
+    store: &dyn TaskStore,
+    scope: &str,
+    key: &str,
+    fingerprint: &str,
+    response: &IdempotentResponse,
+) -> Result<(), TaskQueueError> {
+    store.complete_idempotent_request(scope, key, fingerprint, response).await
+}
+
+/// Releases a reserved idempotency key without storing a response, so the request can be retried.
+pub async fn release_idempotent_request(store: &dyn TaskStore, scope: &str, key: &str) -> Result<(), TaskQueueError> {
+    store.release_idempotent_request(scope, key).await
+}
+
+pub async fn clear_task_queue(store: &dyn TaskStore) -> Result<(), TaskQueueError> {
+    store.clear().await
+}
@@ -0,0 +1,64 @@
+use tokio::sync::watch;
+use tracing::warn;
+
+/// Tells long-running loops that the process is shutting down. Clones observe the same trigger.
+#[derive(Debug, Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+/// Starts the shutdown every `Shutdown` created with it observes.
+#[derive(Debug)]
+pub struct ShutdownTrigger {
+    tx: watch::Sender<bool>,
+}
+
+/// Creates a trigger and the `Shutdown` it controls.
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (tx, rx) = watch::channel(false);
+    (ShutdownTrigger { tx }, Shutdown { rx })
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once shutdown was triggered. Never resolves if the trigger was dropped without
+    /// being used.
+    pub async fn triggered(&mut self) {
+        if self.rx.wait_for(|triggered| *triggered).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Waits for Ctrl-C or, on Unix, SIGTERM.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!(error = %e, "failed to listen for SIGTERM; only Ctrl-C triggers a shutdown"),
+        }
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!(error = %e, "failed to listen for Ctrl-C; shutdown signals are ignored");
+        std::future::pending::<()>().await;
+    }
+}
@@ -0,0 +1,128 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::errors::{SignatureError, TaskQueueError};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The header carrying the signature of every task delivery.
+pub const SIGNATURE_HEADER: &str = "Thermite-Signature";
+
+/// Signs outgoing task deliveries with HMAC-SHA256.
+///
+/// The header value looks like `t=1700000000,v1=5257a869...,v1=6ffbb59b...`: the Unix timestamp
+/// of the delivery followed by one `v1` signature per active secret. Each signature is computed
+/// over `"{t}.{body}"`. Signing with several secrets lets receivers rotate keys: add the new secret
+/// to Thermite, move receivers over, then drop the old one.
+#[derive(Clone)]
+pub struct WebhookSigner {
+    secrets: Vec<String>,
+}
+
+impl std::fmt::Debug for WebhookSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebhookSigner")
+            .field("secrets", &format!("<{} redacted>", self.secrets.len()))
+            .finish()
+    }
+}
+
+impl WebhookSigner {
+    pub fn new(secrets: Vec<String>) -> Result<Self, TaskQueueError> {
+        let secrets: Vec<String> = secrets
+            .into_iter()
+            .map(|secret| secret.trim().to_string())
+            .filter(|secret| !secret.is_empty())
+            .collect();
+
+        if secrets.is_empty() {
+            return Err(TaskQueueError::InvalidConfiguration(
+                "At least one non-empty signing secret is required".to_string(),
+            ));
+        }
+        Ok(WebhookSigner { secrets })
+    }
+
+    /// Returns the `Thermite-Signature` header value for `body` sent at `timestamp`.
+    pub fn sign(&self, timestamp: i64, body: &[u8]) -> String {
+        let mut header = format!("t={timestamp}");
+        for secret in &self.secrets {
+            header.push_str(",v1=");
+            header.push_str(&compute_signature(secret, timestamp, body));
+        }
+        header
+    }
+}
+
+fn signature_mac(secret: &str, timestamp: i64, body: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
+
+/// Computes the hex-encoded `v1` signature of `body` sent at `timestamp` with `secret`.
+pub fn compute_signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    hex::encode(signature_mac(secret, timestamp, body).finalize().into_bytes())
+}
+
+/// Verifies a `Thermite-Signature` header on a received delivery.
+///
+/// Succeeds when any `v1` signature in the header matches any of `secrets`, and the signed
+/// timestamp is within `tolerance_secs` of `now` (a Unix timestamp), which rejects replayed
+/// requests. Signatures are compared in constant time.
+///
+/// ```
+/// use thermite::signing::{verify_signature, WebhookSigner};
+///
+/// let signer = WebhookSigner::new(vec!["whsec_test".to_string()]).unwrap();
+/// let body = br#"{"task_id":"1","args":null}"#;
+/// let header = signer.sign(1_700_000_000, body);
+///
+/// assert!(verify_signature(&header, body, &["whsec_test"], 1_700_000_030, 300).is_ok());
+/// ```
+pub fn verify_signature(
+    header: &str,
+    body: &[u8],
+    secrets: &[&str],
+    now: i64,
+    tolerance_secs: u64,
+) -> Result<(), SignatureError> {
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => {
+                timestamp = Some(value.parse::<i64>().map_err(|_| SignatureError::MalformedHeader)?);
+            }
+            Some(("v1", value)) => {
+                signatures.push(hex::decode(value).map_err(|_| SignatureError::MalformedHeader)?);
+            }
+            // Unknown schemes are ignored so newer signature versions can be added alongside v1.
+            Some(_) => {}
+            None => return Err(SignatureError::MalformedHeader),
+        }
+    }
+
+    let timestamp = timestamp.ok_or(SignatureError::MalformedHeader)?;
+    if signatures.is_empty() {
+        return Err(SignatureError::MalformedHeader);
+    }
+    if now.abs_diff(timestamp) > tolerance_secs {
+        return Err(SignatureError::TimestampOutOfTolerance);
+    }
+
+    let matched = secrets.iter().any(|secret| {
+        signatures
+            .iter()
+            .any(|signature| signature_mac(secret, timestamp, body).verify_slice(signature).is_ok())
+    });
+
+    if matched {
+        Ok(())
+    } else {
+        Err(SignatureError::NoMatchingSignature)
+    }
+}
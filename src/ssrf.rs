@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::Client;
+use tracing::warn;
+
+use crate::config::TargetPolicy;
+use crate::task::validate_target;
+
+/// The most redirects a delivery follows; every hop is validated like a submitted target.
+pub const MAX_REDIRECTS: usize = 5;
+
+/// Returns true for addresses task deliveries must never reach: private, loopback, link-local
+/// (including the 169.254.169.254 metadata endpoint), shared, benchmarking, protocol assignment,
+/// reserved, documentation, multicast and unspecified ranges, and the deprecated IPv6 site-local
+/// range. IPv6 addresses that carry an IPv4 address (IPv4-mapped, IPv4-compatible, NAT64 and 6to4)
+/// are checked as that IPv4 address, and Teredo addresses are blocked outright.
+pub fn is_blocked_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(address) => is_blocked_ipv4(address),
+        IpAddr::V6(address) => match embedded_ipv4(address) {
+            Some(embedded) => is_blocked_ipv4(embedded),
+            None => is_blocked_ipv6(address),
+        },
+    }
+}
+
+// The IPv4 address an IPv6 address is translated or tunnelled to: IPv4-mapped and IPv4-compatible
+// addresses, NAT64 addresses (64:ff9b::/96) and 6to4 addresses (2002::/16) each embed one.
+fn embedded_ipv4(address: Ipv6Addr) -> Option<Ipv4Addr> {
+    if address.is_loopback() || address.is_unspecified() {
+        return None;
+    }
+    if let Some(mapped) = address.to_ipv4() {
+        return Some(mapped);
+    }
+    let from_segments = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match address.segments() {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some(from_segments(high, low)),
+        [0x2002, high, low, ..] => Some(from_segments(high, low)),
+        _ => None,
+    }
+}
+
+fn is_blocked_ipv4(address: Ipv4Addr) -> bool {
+    let [first, second, third, _] = address.octets();
+
+    address.is_private()
+        || address.is_loopback()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_documentation()
+        || address.is_unspecified()
+        || address.is_multicast()
+        // 0.0.0.0/8 ("this network") and 100.64.0.0/10 (carrier-grade NAT).
+        || first == 0
+        || (first == 100 && (second & 0xc0) == 64)
+        // 192.0.0.0/24 (IETF protocol assignments), 198.18.0.0/15 (benchmarking) and 240.0.0.0/4
+        // (reserved).
+        || (first == 192 && second == 0 && third == 0)
+        || (first == 198 && (second & 0xfe) == 18)
+        || first >= 240
+}
+
+fn is_blocked_ipv6(address: Ipv6Addr) -> bool {
+    let [first_segment, second_segment, ..] = address.segments();
+
+    address.is_loopback()
+        || address.is_unspecified()
+        || address.is_multicast()
+        // fc00::/7 (unique local), fe80::/10 (link-local) and fec0::/10 (deprecated site-local,
+        // still routed internally by some stacks).
+        || (first_segment & 0xfe00) == 0xfc00
+        || (first_segment & 0xffc0) == 0xfe80
+        || (first_segment & 0xffc0) == 0xfec0
+        // 2001:db8::/32 (documentation) and 2001::/32 (Teredo, tunnelled to an IPv4 address that
+        // is only recoverable through the relay).
+        || (first_segment == 0x2001 && matches!(second_segment, 0 | 0x0db8))
+}
+
+/// Resolves names with the system resolver.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Addrs = Box::new(tokio::net::lookup_host((host, 0)).await?.collect::<Vec<_>>().into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// A resolver that drops blocked addresses from every lookup.
+///
+/// reqwest connects to exactly the addresses the resolver returns, so the connection is pinned to
+/// the addresses that passed the check: a name that re-resolves to a private address between
+/// validation and connect (DNS rebinding) cannot slip through. A lookup that yields only blocked
+/// addresses fails the delivery.
+#[derive(Clone)]
+pub struct GuardedResolver {
+    inner: Arc<dyn Resolve>,
+}
+
+impl GuardedResolver {
+    pub fn new<R: Resolve + 'static>(inner: R) -> Self {
+        GuardedResolver { inner: Arc::new(inner) }
+    }
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        let lookup = self.inner.resolve(name);
+
+        Box::pin(async move {
+            let (allowed, blocked): (Vec<SocketAddr>, Vec<SocketAddr>) =
+                lookup.await?.partition(|address| !is_blocked_ip(address.ip()));
+
+            if allowed.is_empty() {
+                warn!(host = %host, blocked = blocked.len(), "task target resolves only to blocked addresses");
+                let error: Box<dyn Error + Send + Sync> =
+                    format!("Task host '{host}' resolves to a blocked IP range").into();
+                return Err(error);
+            }
+            if !blocked.is_empty() {
+                warn!(host = %host, blocked = blocked.len(), "ignoring blocked addresses for task target");
+            }
+
+            let addrs: Addrs = Box::new(allowed.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+fn check_redirect(attempt: Attempt, targets: &TargetPolicy) -> reqwest::redirect::Action {
+    if attempt.previous().len() > MAX_REDIRECTS {
+        return attempt.error(format!("Stopped after {MAX_REDIRECTS} redirects"));
+    }
+
+    let url = attempt.url().as_str();
+    match validate_target(url).and_then(|()| targets.check(url)) {
+        Ok(()) => attempt.follow(),
+        Err(error) => attempt.error(error),
+    }
+}
+
+/// Builds the HTTP client used for task deliveries.
+///
+/// Names are resolved through a [`GuardedResolver`] wrapping `resolver`, and every redirect is
+/// re-validated like a submitted target, including the operator's `targets`, before it is
+/// followed. Literal IP targets never reach the resolver; they are checked when the task is
+/// submitted and on every redirect hop.
+///
+/// `HTTP_PROXY`, `HTTPS_PROXY` and `ALL_PROXY` are ignored: a proxy resolves the target itself,
+/// which would bypass the resolver and the address checks.
+pub fn delivery_client<R: Resolve + 'static>(
+    resolver: R,
+    timeout: Duration,
+    targets: TargetPolicy,
+) -> reqwest::Result<Client> {
+    Client::builder()
+        .timeout(timeout)
+        .no_proxy()
+        .dns_resolver(Arc::new(GuardedResolver::new(resolver)))
+        .redirect(Policy::custom(move |attempt| check_redirect(attempt, &targets)))
+        .build()
+}
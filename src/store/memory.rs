@@ -0,0 +1,550 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use super::{
+    SeriesOutcome, TaskMutation, TaskStore, IDEMPOTENCY_TTL_SECS, MAX_INDEXED_OCCURRENCES, MAX_RECORDED_RUNS,
+    RUN_HISTORY_TTL_SECS,
+};
+use crate::errors::TaskQueueError;
+use crate::queue::{
+    DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, LeaseToken, OccurrenceState,
+    OccurrenceSummary, QueueOrder, QueueStats, TaskFilter, TaskRun, TaskState,
+};
+use crate::task::{BaseTask, TaskPriority};
+
+// Expired run histories and idempotency records are swept on writes, at most this often.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+// A sorted set entry, ordered like Redis orders sorted set members: by score, then by id.
+type Entry = (i64, String);
+
+struct IdempotencyRecord {
+    fingerprint: String,
+    response: Option<IdempotentResponse>,
+    expires_at: Instant,
+}
+
+struct RunHistory {
+    // Newest first.
+    runs: VecDeque<TaskRun>,
+    expires_at: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    tasks: HashMap<String, BaseTask>,
+    // The queue a task id is in and its score there, so it can be found without scanning.
+    queued: HashMap<String, (TaskPriority, i64)>,
+    queues: HashMap<TaskPriority, BTreeSet<Entry>>,
+    // Lease deadlines and tokens of in-flight tasks, with an index ordered by deadline.
+    leases: HashMap<String, (i64, LeaseToken)>,
+    lease_index: BTreeSet<Entry>,
+    dead_letters: HashMap<String, BaseTask>,
+    dead_letter_index: BTreeSet<Entry>,
+    dead_lettered_at: HashMap<String, i64>,
+    // Each periodic task's most recent occurrences, scored by their scheduled time.
+    occurrences: HashMap<String, BTreeSet<(u64, String)>>,
+    run_histories: HashMap<String, RunHistory>,
+    idempotency: HashMap<String, IdempotencyRecord>,
+    next_sweep: Option<Instant>,
+}
+
+impl State {
+    fn enqueue_id(&mut self, task_id: &str, priority: TaskPriority, score: i64) {
+        self.dequeue_id(task_id);
+        self.queues.entry(priority).or_default().insert((score, task_id.to_string()));
+        self.queued.insert(task_id.to_string(), (priority, score));
+    }
+
+    fn dequeue_id(&mut self, task_id: &str) {
+        if let Some((priority, score)) = self.queued.remove(task_id) {
+            if let Some(queue) = self.queues.get_mut(&priority) {
+                queue.remove(&(score, task_id.to_string()));
+            }
+        }
+    }
+
+    fn lease(&mut self, task_id: &str, deadline: i64, token: LeaseToken) {
+        self.release(task_id);
+        self.lease_index.insert((deadline, task_id.to_string()));
+        self.leases.insert(task_id.to_string(), (deadline, token));
+    }
+
+    fn holds_lease(&self, task_id: &str, token: LeaseToken) -> bool {
+        self.leases.get(task_id).is_some_and(|&(_, current)| current == token)
+    }
+
+    // Returns whether the task was leased.
+    fn release(&mut self, task_id: &str) -> bool {
+        match self.leases.remove(task_id) {
+            Some((deadline, _)) => {
+                self.lease_index.remove(&(deadline, task_id.to_string()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn store(&mut self, task: &BaseTask, score: i64) {
+        self.tasks.insert(task.id.clone(), BaseTask { lease: None, ..task.clone() });
+        self.enqueue_id(&task.id, task.priority, score);
+    }
+
+    fn remove_dead_letter(&mut self, task_id: &str) -> Option<BaseTask> {
+        if let Some(dead_lettered_at) = self.dead_lettered_at.remove(task_id) {
+            self.dead_letter_index.remove(&(dead_lettered_at, task_id.to_string()));
+        }
+        self.dead_letters.remove(task_id)
+    }
+
+    fn dead_letters_in_order(&self) -> impl Iterator<Item = &BaseTask> {
+        self.dead_letter_index.iter().filter_map(|(_, id)| self.dead_letters.get(id))
+    }
+
+    fn live_run_history(&mut self, task_id: &str) -> Option<&mut RunHistory> {
+        if self.run_histories.get(task_id).is_some_and(|history| history.expires_at <= Instant::now()) {
+            self.run_histories.remove(task_id);
+        }
+        self.run_histories.get_mut(task_id)
+    }
+
+    // Drops the run histories and idempotency records that expired by `now`, returning how many.
+    fn sweep_expired(&mut self, now: Instant) -> usize {
+        let before = self.run_histories.len() + self.idempotency.len();
+        self.run_histories.retain(|_, history| history.expires_at > now);
+        self.idempotency.retain(|_, record| record.expires_at > now);
+        before - self.run_histories.len() - self.idempotency.len()
+    }
+
+    fn sweep_if_due(&mut self) {
+        let now = Instant::now();
+        if self.next_sweep.is_none_or(|next_sweep| next_sweep <= now) {
+            self.sweep_expired(now);
+            self.next_sweep = Some(now + SWEEP_INTERVAL);
+        }
+    }
+
+    fn live_idempotency_record(&mut self, key: &str) -> Option<&IdempotencyRecord> {
+        if self.idempotency.get(key).is_some_and(|record| record.expires_at <= Instant::now()) {
+            self.idempotency.remove(key);
+        }
+        self.idempotency.get(key)
+    }
+}
+
+fn score(scheduled_at: u64) -> i64 {
+    i64::try_from(scheduled_at).unwrap_or(i64::MAX)
+}
+
+fn idempotency_key(scope: &str, key: &str) -> String {
+    format!("{scope}:{key}")
+}
+
+fn page<T>(items: Vec<T>, offset: usize, limit: usize) -> (Vec<T>, usize) {
+    let total = items.len();
+    (items.into_iter().skip(offset).take(limit).collect(), total)
+}
+
+/// Keeps tasks in the memory of this process. It needs no server and behaves like `RedisStore`, so
+/// it suits tests and single-process setups, but nothing survives a restart and the queue cannot be
+/// shared with other replicas. Occurrence histories do not expire; run histories and idempotency
+/// keys expire as they do in Redis, and expired ones are swept out every minute as runs and keys
+/// are written.
+#[derive(Default)]
+pub struct MemoryStore {
+    state: Mutex<State>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> Result<MutexGuard<'_, State>, TaskQueueError> {
+        self.state
+            .lock()
+            .map_err(|e| TaskQueueError::StateError(format!("In-memory task store is unavailable: {e}")))
+    }
+
+    /// Drops the run histories and idempotency keys that have expired by `now` and returns how many
+    /// were dropped. Writes do this on their own every minute.
+    pub fn sweep_expired(&self, now: Instant) -> Result<usize, TaskQueueError> {
+        Ok(self.state()?.sweep_expired(now))
+    }
+}
+
+impl std::fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tasks = self.state.lock().map(|state| state.tasks.len()).unwrap_or_default();
+        f.debug_struct("MemoryStore").field("tasks", &tasks).finish()
+    }
+}
+
+#[async_trait]
+impl TaskStore for MemoryStore {
+    async fn enqueue(&self, task: &BaseTask, policy: DuplicatePolicy) -> Result<EnqueueOutcome, TaskQueueError> {
+        let mut state = self.state()?;
+
+        let exists = state.tasks.contains_key(&task.id);
+        if exists {
+            match policy {
+                DuplicatePolicy::Reject => return Err(TaskQueueError::DuplicateTask(task.id.clone())),
+                DuplicatePolicy::KeepExisting => return Ok(EnqueueOutcome::AlreadyExists),
+                DuplicatePolicy::Replace if state.leases.contains_key(&task.id) => {
+                    return Err(TaskQueueError::TaskInFlight(task.id.clone()));
+                }
+                DuplicatePolicy::Replace => {}
+            }
+        }
+
+        state.store(task, score(task.scheduled_at));
+        Ok(if exists { EnqueueOutcome::Replaced } else { EnqueueOutcome::Enqueued })
+    }
+
+    async fn claim(&self, now: i64, lease_deadline: i64, order: QueueOrder) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut state = self.state()?;
+        let due_before = (now.saturating_add(1), String::new());
+
+        for priority in TaskPriority::ALL {
+            let Some(queue) = state.queues.get(&priority) else {
+                continue;
+            };
+            let mut due = queue.range(..due_before.clone());
+            let claimed = match order {
+                QueueOrder::Fifo => due.next(),
+                QueueOrder::Lifo => due.next_back(),
+            };
+            let Some((_, task_id)) = claimed.cloned() else {
+                continue;
+            };
+
+            let token = LeaseToken::new();
+            state.dequeue_id(&task_id);
+            state.lease(&task_id, lease_deadline, token);
+            return Ok(state.tasks.get(&task_id).map(|task| BaseTask { lease: Some(token), ..task.clone() }));
+        }
+        Ok(None)
+    }
+
+    async fn extend_lease(&self, task_id: &str, lease: LeaseToken, lease_deadline: i64) -> Result<bool, TaskQueueError> {
+        let mut state = self.state()?;
+
+        match state.leases.get(task_id).copied() {
+            Some((current, token)) if token == lease => {
+                if current < lease_deadline {
+                    state.lease(task_id, lease_deadline, lease);
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn reschedule_series(
+        &self,
+        next_run: &BaseTask,
+        occurrence: Option<&BaseTask>,
+        lease_deadline: i64,
+    ) -> Result<SeriesOutcome, TaskQueueError> {
+        let mut state = self.state()?;
+
+        let holds_lease = next_run.lease.is_some_and(|lease| state.holds_lease(&next_run.id, lease));
+        if !holds_lease || !state.tasks.contains_key(&next_run.id) {
+            return Ok(SeriesOutcome::Cancelled);
+        }
+        state.release(&next_run.id);
+        state.store(next_run, score(next_run.scheduled_at));
+
+        let Some(occurrence) = occurrence.filter(|occurrence| !state.tasks.contains_key(&occurrence.id)) else {
+            return Ok(SeriesOutcome::Rescheduled);
+        };
+        let token = LeaseToken::new();
+        state.tasks.insert(occurrence.id.clone(), BaseTask { lease: None, ..occurrence.clone() });
+        state.lease(&occurrence.id, lease_deadline, token);
+
+        let index = state.occurrences.entry(next_run.id.clone()).or_default();
+        index.insert((occurrence.scheduled_at, occurrence.id.clone()));
+        while index.len() > MAX_INDEXED_OCCURRENCES {
+            index.pop_first();
+        }
+        Ok(SeriesOutcome::Spawned(token))
+    }
+
+    async fn settle(
+        &self,
+        task_id: &str,
+        lease: LeaseToken,
+        requeue: Option<&BaseTask>,
+        dead_letter: Option<&BaseTask>,
+        now: i64,
+    ) -> Result<bool, TaskQueueError> {
+        let mut state = self.state()?;
+
+        if !state.holds_lease(task_id, lease) || !state.tasks.contains_key(task_id) {
+            return Ok(false);
+        }
+        state.release(task_id);
+        if let Some(dead_letter) = dead_letter {
+            state.remove_dead_letter(task_id);
+            state.dead_letter_index.insert((now, task_id.to_string()));
+            state.dead_lettered_at.insert(task_id.to_string(), now);
+            state.dead_letters.insert(task_id.to_string(), BaseTask { lease: None, ..dead_letter.clone() });
+        }
+        match requeue {
+            Some(task) => state.store(task, score(task.scheduled_at)),
+            None => {
+                state.tasks.remove(task_id);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn requeue_expired_leases(&self, now: i64, limit: usize) -> Result<usize, TaskQueueError> {
+        let mut state = self.state()?;
+
+        let expired: Vec<String> = state
+            .lease_index
+            .range(..(now.saturating_add(1), String::new()))
+            .take(limit)
+            .map(|(_, task_id)| task_id.clone())
+            .collect();
+        for task_id in &expired {
+            state.release(task_id);
+            if let Some(priority) = state.tasks.get(task_id).map(|task| task.priority) {
+                state.enqueue_id(task_id, priority, now);
+            }
+        }
+        Ok(expired.len())
+    }
+
+    async fn get(&self, task_id: &str) -> Result<Option<(BaseTask, TaskState)>, TaskQueueError> {
+        let state = self.state()?;
+
+        Ok(state.tasks.get(task_id).map(|task| {
+            let task_state = if state.leases.contains_key(task_id) { TaskState::InFlight } else { TaskState::Queued };
+            (task.clone(), task_state)
+        }))
+    }
+
+    async fn list(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+        let state = self.state()?;
+
+        let mut entries: Vec<&Entry> = state.queues.values().flatten().collect();
+        entries.sort();
+        let matching = entries
+            .into_iter()
+            .filter_map(|(_, task_id)| state.tasks.get(task_id))
+            .filter(|task| filter.matches(task))
+            .cloned()
+            .collect();
+        Ok(page(matching, offset, limit))
+    }
+
+    async fn update(&self, task_id: &str, mutation: TaskMutation<'_>) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut state = self.state()?;
+
+        let Some(mut task) = state.tasks.get(task_id).cloned() else {
+            return Ok(None);
+        };
+        mutation(&mut task)?;
+        if state.leases.contains_key(task_id) {
+            return Err(TaskQueueError::TaskInFlight(task_id.to_string()));
+        }
+
+        state.store(&task, score(task.scheduled_at));
+        Ok(Some(task))
+    }
+
+    async fn cancel(&self, task_id: &str) -> Result<bool, TaskQueueError> {
+        let mut state = self.state()?;
+
+        state.dequeue_id(task_id);
+        state.release(task_id);
+        Ok(state.tasks.remove(task_id).is_some())
+    }
+
+    async fn list_occurrences(&self, series_id: &str) -> Result<Option<Vec<OccurrenceSummary>>, TaskQueueError> {
+        let state = self.state()?;
+
+        let Some(index) = state.occurrences.get(series_id).filter(|index| !index.is_empty()) else {
+            return Ok(state.tasks.contains_key(series_id).then(Vec::new));
+        };
+
+        let occurrences = index
+            .iter()
+            .rev()
+            .map(|(scheduled_at, id)| {
+                let occurrence_state = if state.tasks.contains_key(id) {
+                    if state.leases.contains_key(id) { OccurrenceState::InFlight } else { OccurrenceState::Queued }
+                } else if state.dead_letters.contains_key(id) {
+                    OccurrenceState::DeadLettered
+                } else {
+                    OccurrenceState::Finished
+                };
+                OccurrenceSummary { id: id.clone(), scheduled_at: *scheduled_at, state: occurrence_state }
+            })
+            .collect();
+        Ok(Some(occurrences))
+    }
+
+    async fn ping(&self) -> Result<(), TaskQueueError> {
+        self.state().map(|_| ())
+    }
+
+    async fn stats(&self, now: i64) -> Result<QueueStats, TaskQueueError> {
+        let state = self.state()?;
+
+        let due = state.queues.values().map(|queue| queue.range(..(now.saturating_add(1), String::new())).count()).sum();
+        Ok(QueueStats {
+            due,
+            scheduled: state.queued.len().saturating_sub(due),
+            in_flight: state.leases.len(),
+            dead_lettered: state.dead_letters.len(),
+        })
+    }
+
+    async fn record_run(&self, task_id: &str, run: &TaskRun) -> Result<(), TaskQueueError> {
+        let mut state = self.state()?;
+        state.sweep_if_due();
+        let expires_at = Instant::now() + Duration::from_secs(RUN_HISTORY_TTL_SECS);
+
+        match state.live_run_history(task_id) {
+            Some(history) => {
+                history.runs.push_front(run.clone());
+                history.runs.truncate(MAX_RECORDED_RUNS);
+                history.expires_at = expires_at;
+            }
+            None => {
+                let history = RunHistory { runs: VecDeque::from([run.clone()]), expires_at };
+                state.run_histories.insert(task_id.to_string(), history);
+            }
+        }
+        Ok(())
+    }
+
+    async fn list_runs(&self, task_id: &str) -> Result<Vec<TaskRun>, TaskQueueError> {
+        let mut state = self.state()?;
+        Ok(state.live_run_history(task_id).map(|history| history.runs.iter().cloned().collect()).unwrap_or_default())
+    }
+
+    async fn list_dead_letters(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+        let state = self.state()?;
+
+        let matching = state.dead_letters_in_order().filter(|task| filter.matches(task)).cloned().collect();
+        Ok(page(matching, offset, limit))
+    }
+
+    async fn replay_dead_letter(
+        &self,
+        task_id: &str,
+        mutation: TaskMutation<'_>,
+    ) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut state = self.state()?;
+
+        let Some(mut task) = state.dead_letters.get(task_id).cloned() else {
+            return Ok(None);
+        };
+        mutation(&mut task)?;
+        if state.tasks.contains_key(task_id) {
+            return Err(TaskQueueError::DuplicateTask(task.id));
+        }
+
+        state.remove_dead_letter(task_id);
+        state.store(&task, score(task.scheduled_at));
+        Ok(Some(task))
+    }
+
+    async fn delete_dead_letter(&self, task_id: &str) -> Result<bool, TaskQueueError> {
+        Ok(self.state()?.remove_dead_letter(task_id).is_some())
+    }
+
+    async fn purge_dead_letters(&self) -> Result<usize, TaskQueueError> {
+        let mut state = self.state()?;
+
+        let purged = state.dead_letters.len();
+        state.dead_letters.clear();
+        state.dead_letter_index.clear();
+        state.dead_lettered_at.clear();
+        Ok(purged)
+    }
+
+    async fn begin_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<IdempotencyState, TaskQueueError> {
+        let mut state = self.state()?;
+        state.sweep_if_due();
+        let key = idempotency_key(scope, key);
+
+        if let Some(record) = state.live_idempotency_record(&key) {
+            if record.fingerprint != fingerprint {
+                return Ok(IdempotencyState::Mismatch);
+            }
+            return Ok(match &record.response {
+                Some(response) => IdempotencyState::Completed(response.clone()),
+                None => IdempotencyState::InProgress,
+            });
+        }
+
+        state.idempotency.insert(
+            key,
+            IdempotencyRecord {
+                fingerprint: fingerprint.to_string(),
+                response: None,
+                expires_at: Instant::now() + Duration::from_secs(IDEMPOTENCY_TTL_SECS),
+            },
+        );
+        Ok(IdempotencyState::New)
+    }
+
+    async fn complete_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), TaskQueueError> {
+        self.state()?.idempotency.insert(
+            idempotency_key(scope, key),
+            IdempotencyRecord {
+                fingerprint: fingerprint.to_string(),
+                response: Some(response.clone()),
+                expires_at: Instant::now() + Duration::from_secs(IDEMPOTENCY_TTL_SECS),
+            },
+        );
+        Ok(())
+    }
+
+    async fn release_idempotent_request(&self, scope: &str, key: &str) -> Result<(), TaskQueueError> {
+        self.state()?.idempotency.remove(&idempotency_key(scope, key));
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), TaskQueueError> {
+        let mut state = self.state()?;
+
+        state.tasks.clear();
+        state.queued.clear();
+        state.queues.clear();
+        state.leases.clear();
+        state.lease_index.clear();
+        state.occurrences.clear();
+        state.run_histories.clear();
+        Ok(())
+    }
+}
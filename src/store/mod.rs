@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::errors::TaskQueueError;
+use crate::queue::{
+    DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, LeaseToken, OccurrenceSummary, QueueOrder,
+    QueueStats, TaskFilter, TaskRun, TaskState,
+};
+use crate::task::BaseTask;
+
+mod memory;
+mod redis;
+#[cfg(feature = "sql")]
+mod sql;
+
+pub use self::memory::MemoryStore;
+pub use self::redis::{LegacyMigration, RedisStore};
+#[cfg(feature = "sql")]
+pub use self::sql::SqlStore;
+
+// How many occurrences of each periodic task are kept in its history.
+const MAX_INDEXED_OCCURRENCES: usize = 100;
+// How many runs of each task are kept in its run history.
+const MAX_RECORDED_RUNS: usize = 50;
+// How long recorded runs are kept.
+const RUN_HISTORY_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+// How long an idempotency key is remembered after it was last written.
+const IDEMPOTENCY_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Applies a change to a stored task; an error aborts the operation and leaves the task untouched.
+pub type TaskMutation<'a> = &'a (dyn Fn(&mut BaseTask) -> Result<(), TaskQueueError> + Sync);
+
+/// What happened when a claimed periodic task was handed back with `TaskStore::reschedule_series`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeriesOutcome {
+    /// The periodic task was cancelled or re-claimed while it was claimed; nothing was stored.
+    Cancelled,
+    /// The periodic task was queued for its next run and no occurrence was created.
+    Rescheduled,
+    /// The periodic task was queued for its next run and its occurrence was stored and leased
+    /// under the returned token.
+    Spawned(LeaseToken),
+}
+
+/// Where tasks, their leases and their dead letters are kept.
+///
+/// Every method is atomic with respect to the others, across every process sharing the backend:
+/// this is what guarantees a task is claimed by one worker only and that a task cancelled while
+/// leased is not brought back when its delivery settles. Scheduling decisions (retries, periodic
+/// runs, misfires) are made by the `queue` module; a store only records them. Timestamps are Unix
+/// seconds and are always passed in, so backends never read the clock themselves.
+#[async_trait]
+pub trait TaskStore: Send + Sync {
+    /// Stores `task` and queues it for its `scheduled_at`, deduplicating on its id according to
+    /// `policy`. Fails with `TaskQueueError::DuplicateTask` or `TaskQueueError::TaskInFlight` when
+    /// the policy forbids touching the existing task.
+    async fn enqueue(&self, task: &BaseTask, policy: DuplicatePolicy) -> Result<EnqueueOutcome, TaskQueueError>;
+
+    /// Claims the next task due at `now` and leases it until `lease_deadline` under a new token, set
+    /// as the returned task's `lease`. Higher priorities are claimed first, and `order` picks between
+    /// due tasks of the same priority.
+    async fn claim(&self, now: i64, lease_deadline: i64, order: QueueOrder) -> Result<Option<BaseTask>, TaskQueueError>;
+
+    /// Pushes the lease on a claimed task out to `lease_deadline`, keeping a lease that already runs
+    /// longer. Returns `false` when the task is not leased under `lease`, e.g. because it was
+    /// cancelled.
+    async fn extend_lease(&self, task_id: &str, lease: LeaseToken, lease_deadline: i64) -> Result<bool, TaskQueueError>;
+
+    /// Releases the lease `next_run.lease` on a claimed periodic task and queues `next_run` in its
+    /// place. When `occurrence` is set and its id is not taken, the occurrence is stored, leased
+    /// until `lease_deadline` and recorded in the task's occurrence history.
+    async fn reschedule_series(
+        &self,
+        next_run: &BaseTask,
+        occurrence: Option<&BaseTask>,
+        lease_deadline: i64,
+    ) -> Result<SeriesOutcome, TaskQueueError>;
+
+    /// Releases the lease on a delivered task and records its outcome: `requeue` replaces it in its
+    /// queue (a retry or the next periodic run), otherwise it is forgotten, and `dead_letter` is
+    /// dead-lettered at `now`. Returns `false`, changing nothing, when the task is no longer leased
+    /// under `lease`: it was cancelled, or its lease expired and it was claimed again.
+    async fn settle(
+        &self,
+        task_id: &str,
+        lease: LeaseToken,
+        requeue: Option<&BaseTask>,
+        dead_letter: Option<&BaseTask>,
+        now: i64,
+    ) -> Result<bool, TaskQueueError>;
+
+    /// Moves up to `limit` tasks whose lease expired by `now` back into their queue, due at `now`,
+    /// and returns how many were moved.
+    async fn requeue_expired_leases(&self, now: i64, limit: usize) -> Result<usize, TaskQueueError>;
+
+    /// Looks up a queued or in-flight task by its id.
+    async fn get(&self, task_id: &str) -> Result<Option<(BaseTask, TaskState)>, TaskQueueError>;
+
+    /// Lists queued tasks matching `filter`, ordered by due time, and returns the requested page
+    /// along with the total number of matches.
+    async fn list(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError>;
+
+    /// Applies `mutation` to a queued task and requeues it at its (possibly new) priority and
+    /// time. Returns `None` when no such task exists; fails with `TaskQueueError::TaskInFlight`
+    /// while the task is leased.
+    async fn update(&self, task_id: &str, mutation: TaskMutation<'_>) -> Result<Option<BaseTask>, TaskQueueError>;
+
+    /// Removes a queued or in-flight task. Returns `false` when no such task exists.
+    async fn cancel(&self, task_id: &str) -> Result<bool, TaskQueueError>;
+
+    /// Lists the recorded occurrences of a periodic task, newest first. Returns `None` when neither
+    /// the task nor any occurrence of it is known.
+    async fn list_occurrences(&self, series_id: &str) -> Result<Option<Vec<OccurrenceSummary>>, TaskQueueError>;
+
+    /// Checks that the backend is reachable.
+    async fn ping(&self) -> Result<(), TaskQueueError>;
+
+    /// Counts the queued tasks due at `now` and those scheduled later, the in-flight tasks and the
+    /// dead-lettered tasks.
+    async fn stats(&self, now: i64) -> Result<QueueStats, TaskQueueError>;
+
+    /// Adds `run` to the run history of `task_id`. Only the most recent runs are kept, for about a
+    /// week.
+    async fn record_run(&self, task_id: &str, run: &TaskRun) -> Result<(), TaskQueueError>;
+
+    /// Lists the recorded runs of a task, newest first.
+    async fn list_runs(&self, task_id: &str) -> Result<Vec<TaskRun>, TaskQueueError>;
+
+    /// Lists dead-lettered tasks matching `filter`, oldest failure first, and returns the requested
+    /// page along with the total number of matches. A store that has to read payloads to apply the
+    /// filter may stop counting some way past the page, so `total` can be a lower bound there.
+    async fn list_dead_letters(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError>;
+
+    /// Applies `mutation` to a dead-lettered task and moves it back into its queue. Returns `None`
+    /// when no dead-lettered task has this id; fails with `TaskQueueError::DuplicateTask` while a
+    /// live task uses the same id.
+    async fn replay_dead_letter(
+        &self,
+        task_id: &str,
+        mutation: TaskMutation<'_>,
+    ) -> Result<Option<BaseTask>, TaskQueueError>;
+
+    /// Deletes one dead-lettered task. Returns `false` when no dead-lettered task has this id.
+    async fn delete_dead_letter(&self, task_id: &str) -> Result<bool, TaskQueueError>;
+
+    /// Deletes every dead-lettered task and returns how many there were.
+    async fn purge_dead_letters(&self) -> Result<usize, TaskQueueError>;
+
+    /// Reserves an idempotency key for a request with the given payload fingerprint, or reports how
+    /// an earlier request with the same key went. Keys are forgotten a day after they were last
+    /// written.
+    async fn begin_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<IdempotencyState, TaskQueueError>;
+
+    /// Stores the response for a reserved idempotency key.
+    async fn complete_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), TaskQueueError>;
+
+    /// Releases a reserved idempotency key without storing a response.
+    async fn release_idempotent_request(&self, scope: &str, key: &str) -> Result<(), TaskQueueError>;
+
+    /// Removes every queued and in-flight task along with the occurrence and run histories. Dead
+    /// letters and idempotency keys are kept.
+    async fn clear(&self) -> Result<(), TaskQueueError>;
+}
+
+/// Opens the store a connection URL names: `redis://` (or `rediss://`) for Redis, `postgres://` or
+/// `sqlite:` for the SQL store when built with the `sql` feature, and `memory://` for a store that
+/// lives in this process only.
+pub async fn connect(url: &str) -> Result<Arc<dyn TaskStore>, TaskQueueError> {
+    let scheme = url_scheme(url);
+
+    match scheme.as_str() {
+        "redis" | "rediss" | "redis+unix" | "unix" => Ok(Arc::new(RedisStore::new(::redis::Client::open(url)?))),
+        "memory" => Ok(Arc::new(MemoryStore::new())),
+        #[cfg(feature = "sql")]
+        "postgres" | "postgresql" | "sqlite" => Ok(Arc::new(SqlStore::connect(url).await?)),
+        #[cfg(not(feature = "sql"))]
+        "postgres" | "postgresql" | "sqlite" => Err(TaskQueueError::InvalidConfiguration(format!(
+            "'{scheme}' stores need Thermite built with the `sql` feature"
+        ))),
+        _ => Err(TaskQueueError::InvalidConfiguration(format!(
+            "Unsupported store URL scheme '{scheme}'; expected redis://, postgres://, sqlite: or memory://"
+        ))),
+    }
+}
+
+/// Moves data a Redis store at `url` still holds in the original layout into the current one; see
+/// `RedisStore::migrate_legacy_layout`. Returns `None` for other stores, which have no legacy layout.
+pub async fn migrate_legacy_layout(url: &str) -> Result<Option<LegacyMigration>, TaskQueueError> {
+    match url_scheme(url).as_str() {
+        "redis" | "rediss" | "redis+unix" | "unix" => {
+            Ok(Some(RedisStore::new(::redis::Client::open(url)?).migrate_legacy_layout().await?))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn url_scheme(url: &str) -> String {
+    url.split(':').next().unwrap_or_default().to_ascii_lowercase()
+}
@@ -0,0 +1,1025 @@
+use ::redis::aio::MultiplexedConnection;
+use ::redis::AsyncCommands;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use super::{
+    SeriesOutcome, TaskMutation, TaskStore, IDEMPOTENCY_TTL_SECS, MAX_INDEXED_OCCURRENCES, MAX_RECORDED_RUNS,
+    RUN_HISTORY_TTL_SECS,
+};
+use crate::errors::TaskQueueError;
+use crate::queue::{
+    DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, LeaseToken, OccurrenceState,
+    OccurrenceSummary, QueueOrder, QueueStats, TaskFilter, TaskRun, TaskState,
+};
+use crate::task::{BaseTask, TaskPriority};
+
+// Task payloads live in the 'tasks' hash keyed by task id. The priority queues and the
+// 'in_flight' lease set only hold task ids, so a task can be found or removed by its id. The token
+// of each lease is kept in 'lease_tokens', keyed by task id.
+const TASKS_KEY: &str = "tasks";
+const IN_FLIGHT_KEY: &str = "in_flight";
+const LEASE_TOKENS_KEY: &str = "lease_tokens";
+// Dead-lettered payloads are kept by id in 'dead_letter_tasks', indexed by the time they were
+// dead-lettered in 'dead_letter_index'.
+const DEAD_LETTER_INDEX_KEY: &str = "dead_letter_index";
+const DEAD_LETTER_TASKS_KEY: &str = "dead_letter_tasks";
+
+// Each periodic task indexes the ids of its most recent occurrences in 'occurrences:{id}', scored
+// by the time the occurrence was scheduled for. An index expires once no occurrence was added to it
+// for OCCURRENCE_INDEX_TTL_SECS.
+const OCCURRENCE_INDEX_PREFIX: &str = "occurrences:";
+const OCCURRENCE_INDEX_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+fn occurrence_index_key(series_id: &str) -> String {
+    format!("{OCCURRENCE_INDEX_PREFIX}{series_id}")
+}
+
+// Each task's runs are kept in the list 'task_runs:{id}', newest first, trimmed to
+// MAX_RECORDED_RUNS entries and expiring RUN_HISTORY_TTL_SECS after the last run was recorded.
+const RUN_HISTORY_PREFIX: &str = "task_runs:";
+
+fn run_history_key(task_id: &str) -> String {
+    format!("{RUN_HISTORY_PREFIX}{task_id}")
+}
+
+// The original layout queued whole task payloads in the 'task_queue' sorted set, scored by their
+// scheduled time, and appended dead-lettered payloads to the 'dead_letter_queue' list.
+// `RedisStore::migrate_legacy_layout` moves both into the current layout.
+const LEGACY_QUEUE_KEY: &str = "task_queue";
+const LEGACY_DEAD_LETTER_KEY: &str = "dead_letter_queue";
+
+/// Returns the sorted set holding the ids of queued tasks of the given priority.
+fn queue_key(priority: TaskPriority) -> &'static str {
+    match priority {
+        TaskPriority::High => "task_queue:high",
+        TaskPriority::Normal => "task_queue:normal",
+        TaskPriority::Low => "task_queue:low",
+    }
+}
+
+// Every queued id is also kept in 'task_category:{priority}:{category}', scored like in its
+// priority queue, so tasks of one category are listed by paging that set instead of reading every
+// queued payload. The scripts that move ids in and out of the priority queues keep these sets in
+// step through `category_queue`, which derives the key from the payload.
+const CATEGORY_QUEUE_PREFIX: &str = "task_category:";
+
+fn category_queue_key(category: &str, priority: TaskPriority) -> String {
+    format!("{CATEGORY_QUEUE_PREFIX}{}:{category}", priority.as_str())
+}
+
+// Prepended to the scripts that queue or unqueue ids. Matches `category_queue_key`.
+const CATEGORY_QUEUE_LUA: &str = r#"
+local function category_queue(payload)
+    local task = cjson.decode(payload)
+    return 'task_category:' .. (task['priority'] or 'normal') .. ':' .. tostring(task['category'])
+end
+"#;
+
+fn queue_script(body: &str) -> ::redis::Script {
+    ::redis::Script::new(&format!("{CATEGORY_QUEUE_LUA}{body}"))
+}
+
+// Stores the payload under its id and queues the id in KEYS[2]. An existing task with the same id
+// is handled according to the duplicate policy in ARGV[4]; replacing removes it from every queue
+// first, since its priority may have changed.
+const ENQUEUE_TASK_SCRIPT: &str = r#"
+local exists = redis.call('HEXISTS', KEYS[1], ARGV[1]) == 1
+if exists then
+    if ARGV[4] == 'reject' then
+        return 'duplicate'
+    end
+    if ARGV[4] == 'keep_existing' then
+        return 'kept'
+    end
+    if redis.call('ZSCORE', KEYS[6], ARGV[1]) then
+        return 'in_flight'
+    end
+    for i = 3, 5 do
+        redis.call('ZREM', KEYS[i], ARGV[1])
+    end
+    redis.call('ZREM', category_queue(redis.call('HGET', KEYS[1], ARGV[1])), ARGV[1])
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+redis.call('ZADD', KEYS[2], ARGV[3], ARGV[1])
+redis.call('ZADD', category_queue(ARGV[2]), ARGV[3], ARGV[1])
+if exists then
+    return 'replaced'
+end
+return 'enqueued'
+"#;
+
+// Atomically claims one due task id (score up to 'now') so that only one worker, across every
+// replica polling the same Redis, can win it. Priority queues are passed highest first and the
+// first one holding a due task wins; within it 'fifo' takes the lowest score, 'lifo' the highest.
+// The claimed id is leased into 'in_flight' under token ARGV[4] until it is acked or the lease
+// expires. Ids whose payload has gone missing are dropped rather than returned.
+const CLAIM_DUE_TASK_SCRIPT: &str = r#"
+for i = 1, 3 do
+    while true do
+        local members
+        if ARGV[3] == 'lifo' then
+            members = redis.call('ZREVRANGEBYSCORE', KEYS[i], ARGV[1], '-inf', 'LIMIT', 0, 1)
+        else
+            members = redis.call('ZRANGEBYSCORE', KEYS[i], '-inf', ARGV[1], 'LIMIT', 0, 1)
+        end
+        if #members == 0 then
+            break
+        end
+        redis.call('ZREM', KEYS[i], members[1])
+        local payload = redis.call('HGET', KEYS[4], members[1])
+        if payload then
+            redis.call('ZREM', category_queue(payload), members[1])
+            redis.call('ZADD', KEYS[5], ARGV[2], members[1])
+            redis.call('HSET', KEYS[6], members[1], ARGV[4])
+            return payload
+        end
+    end
+end
+return false
+"#;
+
+// Pushes the lease of task ARGV[1] out to ARGV[2] unless it already runs longer. Returns 0 when the
+// task is not leased under token ARGV[3].
+const EXTEND_LEASE_SCRIPT: &str = r#"
+local deadline = redis.call('ZSCORE', KEYS[1], ARGV[1])
+if not deadline or redis.call('HGET', KEYS[2], ARGV[1]) ~= ARGV[3] then
+    return 0
+end
+if tonumber(deadline) < tonumber(ARGV[2]) then
+    redis.call('ZADD', KEYS[1], ARGV[2], ARGV[1])
+end
+return 1
+"#;
+
+// Returns tasks whose lease expired (their worker crashed or stalled) to their priority queue,
+// due immediately.
+const REQUEUE_EXPIRED_LEASES_SCRIPT: &str = r#"
+local queues = {high = KEYS[3], normal = KEYS[4], low = KEYS[5]}
+local task_ids = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+for _, task_id in ipairs(task_ids) do
+    local payload = redis.call('HGET', KEYS[2], task_id)
+    if payload then
+        local priority = cjson.decode(payload)['priority']
+        redis.call('ZADD', queues[priority] or KEYS[4], ARGV[1], task_id)
+        redis.call('ZADD', category_queue(payload), ARGV[1], task_id)
+    end
+    redis.call('ZREM', KEYS[1], task_id)
+    redis.call('HDEL', KEYS[6], task_id)
+end
+return #task_ids
+"#;
+
+// Releases the lease on a delivered task and records its outcome: ARGV[2] is the payload to queue
+// again (a retry or the next periodic run) at score ARGV[3], or '' to forget the task, and ARGV[4]
+// is a payload to dead-letter at time ARGV[5], or ''. Nothing changes unless the task is still
+// leased under token ARGV[6]: it may have been cancelled, or re-claimed after its lease expired.
+const SETTLE_TASK_SCRIPT: &str = r#"
+if redis.call('HGET', KEYS[6], ARGV[1]) ~= ARGV[6] or redis.call('HEXISTS', KEYS[1], ARGV[1]) == 0 then
+    return 0
+end
+redis.call('ZREM', KEYS[2], ARGV[1])
+redis.call('HDEL', KEYS[6], ARGV[1])
+if ARGV[4] ~= '' then
+    redis.call('ZADD', KEYS[4], ARGV[5], ARGV[1])
+    redis.call('HSET', KEYS[5], ARGV[1], ARGV[4])
+end
+if ARGV[2] == '' then
+    redis.call('HDEL', KEYS[1], ARGV[1])
+else
+    redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+    redis.call('ZADD', KEYS[3], ARGV[3], ARGV[1])
+    redis.call('ZADD', category_queue(ARGV[2]), ARGV[3], ARGV[1])
+end
+return 1
+"#;
+
+// Compare-and-swap of a queued task: writes the updated payload and moves the id to the queue of
+// its (possibly new) priority at its (possibly new) score, unless the task changed since it was
+// read or is being delivered.
+const UPDATE_TASK_SCRIPT: &str = r#"
+if redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] then
+    return 'conflict'
+end
+if redis.call('ZSCORE', KEYS[6], ARGV[1]) then
+    return 'in_flight'
+end
+for i = 3, 5 do
+    redis.call('ZREM', KEYS[i], ARGV[1])
+end
+redis.call('ZREM', category_queue(ARGV[2]), ARGV[1])
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+redis.call('ZADD', KEYS[2], ARGV[4], ARGV[1])
+redis.call('ZADD', category_queue(ARGV[3]), ARGV[4], ARGV[1])
+return 'updated'
+"#;
+
+// Moves a dead-lettered task back into the queue, unless the dead-letter entry changed since it
+// was read or a live task already uses the id.
+const REPLAY_DEAD_LETTER_SCRIPT: &str = r#"
+if redis.call('HGET', KEYS[4], ARGV[1]) ~= ARGV[2] then
+    return 'conflict'
+end
+if redis.call('HEXISTS', KEYS[1], ARGV[1]) == 1 then
+    return 'duplicate'
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+redis.call('ZADD', KEYS[2], ARGV[4], ARGV[1])
+redis.call('ZADD', category_queue(ARGV[3]), ARGV[4], ARGV[1])
+redis.call('ZREM', KEYS[3], ARGV[1])
+redis.call('HDEL', KEYS[4], ARGV[1])
+return 'replayed'
+"#;
+
+// Atomically hands a claimed periodic task (ARGV[1], leased in KEYS[2] under token ARGV[10]) back
+// to its queue KEYS[3] with its next run (payload ARGV[2], score ARGV[3]) and, when ARGV[4] is set,
+// stores occurrence ARGV[4] (payload ARGV[5]) leased until ARGV[6] under token ARGV[11] and records
+// it in the series index KEYS[4] under its scheduled time ARGV[7]. The index keeps the newest
+// ARGV[8] occurrences and expires after ARGV[9] seconds without a new one. Returns 'cancelled' if
+// the periodic task was removed or re-claimed while claimed, 'rescheduled' when no occurrence was
+// created and 'spawned' otherwise.
+const SPAWN_OCCURRENCE_SCRIPT: &str = r#"
+if redis.call('HGET', KEYS[5], ARGV[1]) ~= ARGV[10] or redis.call('HEXISTS', KEYS[1], ARGV[1]) == 0 then
+    return 'cancelled'
+end
+redis.call('ZREM', KEYS[2], ARGV[1])
+redis.call('HDEL', KEYS[5], ARGV[1])
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[2])
+redis.call('ZADD', KEYS[3], ARGV[3], ARGV[1])
+redis.call('ZADD', category_queue(ARGV[2]), ARGV[3], ARGV[1])
+if ARGV[4] == '' or redis.call('HEXISTS', KEYS[1], ARGV[4]) == 1 then
+    return 'rescheduled'
+end
+redis.call('HSET', KEYS[1], ARGV[4], ARGV[5])
+redis.call('ZADD', KEYS[2], ARGV[6], ARGV[4])
+redis.call('HSET', KEYS[5], ARGV[4], ARGV[11])
+redis.call('ZADD', KEYS[4], ARGV[7], ARGV[4])
+redis.call('ZREMRANGEBYRANK', KEYS[4], 0, -(tonumber(ARGV[8]) + 1))
+redis.call('EXPIRE', KEYS[4], ARGV[9])
+return 'spawned'
+"#;
+
+// Removes a task id from every queue and the lease set, along with its payload and lease token.
+const CANCEL_TASK_SCRIPT: &str = r#"
+for i = 3, #KEYS do
+    redis.call('ZREM', KEYS[i], ARGV[1])
+end
+local payload = redis.call('HGET', KEYS[1], ARGV[1])
+if payload then
+    redis.call('ZREM', category_queue(payload), ARGV[1])
+end
+redis.call('HDEL', KEYS[2], ARGV[1])
+return redis.call('HDEL', KEYS[1], ARGV[1])
+"#;
+
+// Moves entry ARGV[1] of the legacy dead-letter list KEYS[1] into the dead-letter index KEYS[2]
+// under id ARGV[2] at score ARGV[3], with payload ARGV[4] in KEYS[3], unless a task is already
+// dead-lettered under that id. Returns how many list entries were removed.
+const MIGRATE_DEAD_LETTER_SCRIPT: &str = r#"
+if redis.call('HEXISTS', KEYS[3], ARGV[2]) == 0 then
+    redis.call('ZADD', KEYS[2], ARGV[3], ARGV[2])
+    redis.call('HSET', KEYS[3], ARGV[2], ARGV[4])
+end
+return redis.call('LREM', KEYS[1], 1, ARGV[1])
+"#;
+
+// Adds the ids ARGV[1..] still queued in priority queue KEYS[1] to their category queues at the
+// same score. Returns how many were added.
+const INDEX_CATEGORIES_SCRIPT: &str = r#"
+local indexed = 0
+for _, task_id in ipairs(ARGV) do
+    local score = redis.call('ZSCORE', KEYS[1], task_id)
+    local payload = redis.call('HGET', KEYS[2], task_id)
+    if score and payload then
+        indexed = indexed + redis.call('ZADD', category_queue(payload), score, task_id)
+    end
+end
+return indexed
+"#;
+
+// A filtered dead-letter listing reads the index this many ids at a time, and stops counting this
+// many matches past the requested page.
+const DEAD_LETTER_SCAN_CHUNK: usize = 500;
+const DEAD_LETTER_COUNT_AHEAD: usize = 1000;
+
+// How many times a read-modify-write is retried when the task keeps changing underneath it.
+const MAX_UPDATE_ATTEMPTS: usize = 5;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct IdempotencyRecord {
+    fingerprint: String,
+    response: Option<IdempotentResponse>,
+}
+
+fn idempotency_key(scope: &str, key: &str) -> String {
+    format!("idempotency:{scope}:{key}")
+}
+
+/// What `RedisStore::migrate_legacy_layout` moved into the current layout.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LegacyMigration {
+    /// Queued tasks moved out of the `task_queue` sorted set.
+    pub tasks: usize,
+    /// Dead-lettered tasks moved out of the `dead_letter_queue` list.
+    pub dead_letters: usize,
+    /// Entries left in place because they are not task payloads.
+    pub skipped: usize,
+    /// Queued tasks added to the category queues, which releases before them did not keep.
+    pub categorized: usize,
+}
+
+// Parses a payload stored in the original layout, where `priority` was free text: values other than
+// the current priorities fall back to the default one.
+fn parse_legacy_task(payload: &str) -> Result<BaseTask, TaskQueueError> {
+    let mut value: serde_json::Value = serde_json::from_str(payload)?;
+    if let Some(fields) = value.as_object_mut() {
+        let known_priority = fields
+            .get("priority")
+            .and_then(|priority| priority.as_str())
+            .is_some_and(|priority| priority.parse::<TaskPriority>().is_ok());
+        if !known_priority {
+            fields.remove("priority");
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Keeps tasks in Redis, so every replica pointed at the same server shares one queue. The atomic
+/// steps run as Lua scripts.
+#[derive(Debug, Clone)]
+pub struct RedisStore {
+    client: ::redis::Client,
+}
+
+impl RedisStore {
+    pub fn new(client: ::redis::Client) -> Self {
+        RedisStore { client }
+    }
+
+    async fn connection(&self) -> Result<MultiplexedConnection, TaskQueueError> {
+        Ok(self.client.get_multiplexed_async_connection().await?)
+    }
+
+    /// Moves tasks left in the original layout, which queued whole task payloads in the
+    /// `task_queue` sorted set and dead-lettered them onto the `dead_letter_queue` list, into the
+    /// current one. Each task is enqueued like a new one, keeping a task already stored under its
+    /// id, and only then removed from `task_queue`, so an interrupted migration can simply be run
+    /// again. Dead letters move into the dead-letter index, newest first, keeping a task already
+    /// dead-lettered under the id; their last `scheduled_at` stands in for when they failed.
+    /// Entries that are not task payloads are left in place and counted as skipped. Finally every
+    /// queued task missing from its category queue is added to it.
+    pub async fn migrate_legacy_layout(&self) -> Result<LegacyMigration, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let mut migration = LegacyMigration::default();
+
+        let payloads: Vec<String> = conn.zrange(LEGACY_QUEUE_KEY, 0, -1).await?;
+        for payload in payloads {
+            let task = match parse_legacy_task(&payload) {
+                Ok(task) => task,
+                Err(e) => {
+                    warn!(error = %e, "leaving an entry of the legacy task queue that is not a task");
+                    migration.skipped += 1;
+                    continue;
+                }
+            };
+            self.enqueue(&task, DuplicatePolicy::KeepExisting).await?;
+            let _: () = conn.zrem(LEGACY_QUEUE_KEY, &payload).await?;
+            migration.tasks += 1;
+        }
+
+        let payloads: Vec<String> = conn.lrange(LEGACY_DEAD_LETTER_KEY, 0, -1).await?;
+        for payload in payloads.into_iter().rev() {
+            let task = match parse_legacy_task(&payload) {
+                Ok(task) => task,
+                Err(e) => {
+                    warn!(error = %e, "leaving an entry of the legacy dead-letter queue that is not a task");
+                    migration.skipped += 1;
+                    continue;
+                }
+            };
+            let _: usize = ::redis::Script::new(MIGRATE_DEAD_LETTER_SCRIPT)
+                .key(LEGACY_DEAD_LETTER_KEY)
+                .key(DEAD_LETTER_INDEX_KEY)
+                .key(DEAD_LETTER_TASKS_KEY)
+                .arg(&payload)
+                .arg(&task.id)
+                .arg(task.scheduled_at)
+                .arg(serde_json::to_string(&task)?)
+                .invoke_async(&mut conn)
+                .await?;
+            migration.dead_letters += 1;
+        }
+
+        for priority in TaskPriority::ALL {
+            let mut start = 0;
+            loop {
+                let ids: Vec<String> = conn.zrange(queue_key(priority), start, start + 499).await?;
+                if ids.is_empty() {
+                    break;
+                }
+                let indexed: usize = queue_script(INDEX_CATEGORIES_SCRIPT)
+                    .key(queue_key(priority))
+                    .key(TASKS_KEY)
+                    .arg(&ids)
+                    .invoke_async(&mut conn)
+                    .await?;
+                migration.categorized += indexed;
+                start += 500;
+            }
+        }
+        Ok(migration)
+    }
+
+    // Reads the dead-lettered payloads for `ids`, returning each raw payload alongside the parsed
+    // task. Ids without a payload are skipped.
+    async fn get_dead_letter_payloads(
+        conn: &mut MultiplexedConnection,
+        ids: &[String],
+    ) -> Result<Vec<(String, BaseTask)>, TaskQueueError> {
+        let mut entries = Vec::with_capacity(ids.len());
+
+        for ids in ids.chunks(500) {
+            let payloads: Vec<Option<String>> = ::redis::cmd("HMGET")
+                .arg(DEAD_LETTER_TASKS_KEY)
+                .arg(ids)
+                .query_async(conn)
+                .await?;
+
+            for payload in payloads.into_iter().flatten() {
+                let task = serde_json::from_str(&payload)?;
+                entries.push((payload, task));
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+#[async_trait]
+impl TaskStore for RedisStore {
+    async fn enqueue(&self, task: &BaseTask, policy: DuplicatePolicy) -> Result<EnqueueOutcome, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        let result: String = queue_script(ENQUEUE_TASK_SCRIPT)
+            .key(TASKS_KEY)
+            .key(queue_key(task.priority))
+            .key(queue_key(TaskPriority::High))
+            .key(queue_key(TaskPriority::Normal))
+            .key(queue_key(TaskPriority::Low))
+            .key(IN_FLIGHT_KEY)
+            .arg(&task.id)
+            .arg(serde_json::to_string(task)?)
+            .arg(task.scheduled_at)
+            .arg(policy.as_str())
+            .invoke_async(&mut conn)
+            .await?;
+
+        match result.as_str() {
+            "enqueued" => Ok(EnqueueOutcome::Enqueued),
+            "replaced" => Ok(EnqueueOutcome::Replaced),
+            "kept" => Ok(EnqueueOutcome::AlreadyExists),
+            "duplicate" => Err(TaskQueueError::DuplicateTask(task.id.clone())),
+            "in_flight" => Err(TaskQueueError::TaskInFlight(task.id.clone())),
+            other => Err(TaskQueueError::RedisError(format!("Unexpected enqueue result '{other}'"))),
+        }
+    }
+
+    async fn claim(&self, now: i64, lease_deadline: i64, order: QueueOrder) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let token = LeaseToken::new();
+
+        let task_json: Option<String> = queue_script(CLAIM_DUE_TASK_SCRIPT)
+            .key(queue_key(TaskPriority::High))
+            .key(queue_key(TaskPriority::Normal))
+            .key(queue_key(TaskPriority::Low))
+            .key(TASKS_KEY)
+            .key(IN_FLIGHT_KEY)
+            .key(LEASE_TOKENS_KEY)
+            .arg(now)
+            .arg(lease_deadline)
+            .arg(order.as_str())
+            .arg(token.raw())
+            .invoke_async(&mut conn)
+            .await?;
+
+        let Some(task_json) = task_json else {
+            return Ok(None);
+        };
+        let task: BaseTask = serde_json::from_str(&task_json)?;
+        Ok(Some(BaseTask { lease: Some(token), ..task }))
+    }
+
+    async fn extend_lease(&self, task_id: &str, lease: LeaseToken, lease_deadline: i64) -> Result<bool, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        let leased: bool = ::redis::Script::new(EXTEND_LEASE_SCRIPT)
+            .key(IN_FLIGHT_KEY)
+            .key(LEASE_TOKENS_KEY)
+            .arg(task_id)
+            .arg(lease_deadline)
+            .arg(lease.raw())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(leased)
+    }
+
+    async fn reschedule_series(
+        &self,
+        next_run: &BaseTask,
+        occurrence: Option<&BaseTask>,
+        lease_deadline: i64,
+    ) -> Result<SeriesOutcome, TaskQueueError> {
+        let Some(lease) = next_run.lease else {
+            return Ok(SeriesOutcome::Cancelled);
+        };
+        let mut conn = self.connection().await?;
+        let token = LeaseToken::new();
+        let (occurrence_id, occurrence_json, occurrence_scheduled_at) = match occurrence {
+            Some(occurrence) => (occurrence.id.as_str(), serde_json::to_string(occurrence)?, occurrence.scheduled_at),
+            None => ("", String::new(), 0),
+        };
+
+        let result: String = queue_script(SPAWN_OCCURRENCE_SCRIPT)
+            .key(TASKS_KEY)
+            .key(IN_FLIGHT_KEY)
+            .key(queue_key(next_run.priority))
+            .key(occurrence_index_key(&next_run.id))
+            .key(LEASE_TOKENS_KEY)
+            .arg(&next_run.id)
+            .arg(serde_json::to_string(next_run)?)
+            .arg(next_run.scheduled_at)
+            .arg(occurrence_id)
+            .arg(occurrence_json)
+            .arg(lease_deadline)
+            .arg(occurrence_scheduled_at)
+            .arg(MAX_INDEXED_OCCURRENCES)
+            .arg(OCCURRENCE_INDEX_TTL_SECS)
+            .arg(lease.raw())
+            .arg(token.raw())
+            .invoke_async(&mut conn)
+            .await?;
+
+        Ok(match result.as_str() {
+            "spawned" => SeriesOutcome::Spawned(token),
+            "rescheduled" => SeriesOutcome::Rescheduled,
+            _ => SeriesOutcome::Cancelled,
+        })
+    }
+
+    async fn settle(
+        &self,
+        task_id: &str,
+        lease: LeaseToken,
+        requeue: Option<&BaseTask>,
+        dead_letter: Option<&BaseTask>,
+        now: i64,
+    ) -> Result<bool, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let (requeue_json, requeue_key, requeue_score) = match requeue {
+            Some(task) => (serde_json::to_string(task)?, queue_key(task.priority), task.scheduled_at),
+            None => (String::new(), queue_key(TaskPriority::default()), 0),
+        };
+        let dead_letter_json = match dead_letter {
+            Some(task) => serde_json::to_string(task)?,
+            None => String::new(),
+        };
+
+        let settled: bool = queue_script(SETTLE_TASK_SCRIPT)
+            .key(TASKS_KEY)
+            .key(IN_FLIGHT_KEY)
+            .key(requeue_key)
+            .key(DEAD_LETTER_INDEX_KEY)
+            .key(DEAD_LETTER_TASKS_KEY)
+            .key(LEASE_TOKENS_KEY)
+            .arg(task_id)
+            .arg(requeue_json)
+            .arg(requeue_score)
+            .arg(dead_letter_json)
+            .arg(now)
+            .arg(lease.raw())
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(settled)
+    }
+
+    async fn requeue_expired_leases(&self, now: i64, limit: usize) -> Result<usize, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        let requeued: usize = queue_script(REQUEUE_EXPIRED_LEASES_SCRIPT)
+            .key(IN_FLIGHT_KEY)
+            .key(TASKS_KEY)
+            .key(queue_key(TaskPriority::High))
+            .key(queue_key(TaskPriority::Normal))
+            .key(queue_key(TaskPriority::Low))
+            .key(LEASE_TOKENS_KEY)
+            .arg(now)
+            .arg(limit)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(requeued)
+    }
+
+    async fn get(&self, task_id: &str) -> Result<Option<(BaseTask, TaskState)>, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        let task_json: Option<String> = conn.hget(TASKS_KEY, task_id).await?;
+        let Some(task_json) = task_json else {
+            return Ok(None);
+        };
+        let lease: Option<f64> = conn.zscore(IN_FLIGHT_KEY, task_id).await?;
+        let state = if lease.is_some() { TaskState::InFlight } else { TaskState::Queued };
+
+        Ok(Some((serde_json::from_str(&task_json)?, state)))
+    }
+
+    async fn list(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let min_score = filter.scheduled_after.map_or("-inf".to_string(), |after| after.to_string());
+        let max_score = filter.scheduled_before.map_or("+inf".to_string(), |before| before.to_string());
+        let priorities = match filter.priority {
+            Some(priority) => vec![priority],
+            None => TaskPriority::ALL.to_vec(),
+        };
+
+        // Every filter is answered by the queues alone (the category ones when filtering by
+        // category), so each queue is only read up to the end of the page, and only the page's
+        // payloads are fetched. The schedule is the queue score, which is `scheduled_at` unless an
+        // expired lease made the task due again.
+        let queues: Vec<String> = priorities
+            .into_iter()
+            .map(|priority| match &filter.category {
+                Some(category) => category_queue_key(category, priority),
+                None => queue_key(priority).to_string(),
+            })
+            .collect();
+        let window = offset.saturating_add(limit);
+        let mut total = 0;
+        let mut entries: Vec<(String, f64)> = Vec::new();
+        for queue in &queues {
+            let queued: usize = conn.zcount(queue, &min_score, &max_score).await?;
+            total += queued;
+            if window > 0 && queued > 0 {
+                let count = isize::try_from(window).unwrap_or(isize::MAX);
+                let page: Vec<(String, f64)> =
+                    conn.zrangebyscore_limit_withscores(queue, &min_score, &max_score, 0, count).await?;
+                entries.extend(page);
+            }
+        }
+        entries.sort_by(|a, b| a.1.total_cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+
+        let ids: Vec<String> = entries.into_iter().skip(offset).take(limit).map(|(id, _)| id).collect();
+        if ids.is_empty() {
+            return Ok((Vec::new(), total));
+        }
+        let payloads: Vec<Option<String>> =
+            ::redis::cmd("HMGET").arg(TASKS_KEY).arg(&ids).query_async(&mut conn).await?;
+        let tasks = payloads
+            .into_iter()
+            .flatten()
+            .map(|payload| serde_json::from_str(&payload))
+            .collect::<Result<_, _>>()?;
+        Ok((tasks, total))
+    }
+
+    async fn update(&self, task_id: &str, mutation: TaskMutation<'_>) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let current_json: Option<String> = conn.hget(TASKS_KEY, task_id).await?;
+            let Some(current_json) = current_json else {
+                return Ok(None);
+            };
+
+            let mut task: BaseTask = serde_json::from_str(&current_json)?;
+            mutation(&mut task)?;
+
+            let result: String = queue_script(UPDATE_TASK_SCRIPT)
+                .key(TASKS_KEY)
+                .key(queue_key(task.priority))
+                .key(queue_key(TaskPriority::High))
+                .key(queue_key(TaskPriority::Normal))
+                .key(queue_key(TaskPriority::Low))
+                .key(IN_FLIGHT_KEY)
+                .arg(task_id)
+                .arg(&current_json)
+                .arg(serde_json::to_string(&task)?)
+                .arg(task.scheduled_at)
+                .invoke_async(&mut conn)
+                .await?;
+
+            match result.as_str() {
+                "updated" => return Ok(Some(task)),
+                "in_flight" => return Err(TaskQueueError::TaskInFlight(task_id.to_string())),
+                _ => debug!(task_id = %task_id, "task changed during update, retrying"),
+            }
+        }
+
+        Err(TaskQueueError::StateError(format!(
+            "Task '{task_id}' kept changing while it was being updated"
+        )))
+    }
+
+    async fn cancel(&self, task_id: &str) -> Result<bool, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        let removed: bool = queue_script(CANCEL_TASK_SCRIPT)
+            .key(TASKS_KEY)
+            .key(LEASE_TOKENS_KEY)
+            .key(queue_key(TaskPriority::High))
+            .key(queue_key(TaskPriority::Normal))
+            .key(queue_key(TaskPriority::Low))
+            .key(IN_FLIGHT_KEY)
+            .arg(task_id)
+            .invoke_async(&mut conn)
+            .await?;
+        Ok(removed)
+    }
+
+    async fn list_occurrences(&self, series_id: &str) -> Result<Option<Vec<OccurrenceSummary>>, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        let entries: Vec<(String, f64)> = conn.zrevrange_withscores(occurrence_index_key(series_id), 0, -1).await?;
+        if entries.is_empty() {
+            let series_exists: bool = conn.hexists(TASKS_KEY, series_id).await?;
+            return Ok(series_exists.then(Vec::new));
+        }
+
+        let mut occurrences = Vec::with_capacity(entries.len());
+        for (id, scheduled_at) in entries {
+            let stored: bool = conn.hexists(TASKS_KEY, &id).await?;
+            let state = if stored {
+                let lease: Option<f64> = conn.zscore(IN_FLIGHT_KEY, &id).await?;
+                if lease.is_some() { OccurrenceState::InFlight } else { OccurrenceState::Queued }
+            } else {
+                let dead_lettered: bool = conn.hexists(DEAD_LETTER_TASKS_KEY, &id).await?;
+                if dead_lettered { OccurrenceState::DeadLettered } else { OccurrenceState::Finished }
+            };
+            occurrences.push(OccurrenceSummary { id, scheduled_at: scheduled_at as u64, state });
+        }
+
+        Ok(Some(occurrences))
+    }
+
+    async fn ping(&self) -> Result<(), TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let _: String = ::redis::cmd("PING").query_async(&mut conn).await?;
+        Ok(())
+    }
+
+    async fn stats(&self, now: i64) -> Result<QueueStats, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        // Counted in one transaction, so a task moving between sets is never counted twice or missed.
+        let mut pipe = ::redis::pipe();
+        pipe.atomic();
+        for priority in [TaskPriority::High, TaskPriority::Normal, TaskPriority::Low] {
+            pipe.zcount(queue_key(priority), "-inf", now).zcard(queue_key(priority));
+        }
+        pipe.zcard(IN_FLIGHT_KEY).zcard(DEAD_LETTER_INDEX_KEY);
+
+        let counts: Vec<usize> = pipe.query_async(&mut conn).await?;
+        let (queues, totals) = counts.split_at(6);
+        let due: usize = queues.iter().step_by(2).sum();
+        let queued: usize = queues.iter().skip(1).step_by(2).sum();
+        Ok(QueueStats { due, scheduled: queued.saturating_sub(due), in_flight: totals[0], dead_lettered: totals[1] })
+    }
+
+    async fn record_run(&self, task_id: &str, run: &TaskRun) -> Result<(), TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let key = run_history_key(task_id);
+        let _: () = ::redis::pipe()
+            .atomic()
+            .lpush(&key, serde_json::to_string(run)?)
+            .ltrim(&key, 0, MAX_RECORDED_RUNS as isize - 1)
+            .expire(&key, RUN_HISTORY_TTL_SECS as i64)
+            .query_async(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_runs(&self, task_id: &str) -> Result<Vec<TaskRun>, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let entries: Vec<String> = conn.lrange(run_history_key(task_id), 0, -1).await?;
+        entries
+            .iter()
+            .map(|entry| serde_json::from_str(entry).map_err(TaskQueueError::from))
+            .collect()
+    }
+
+    async fn list_dead_letters(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        if filter.is_empty() {
+            // Without a filter the index alone can be paged, so only one page of payloads is read.
+            let total: usize = conn.zcard(DEAD_LETTER_INDEX_KEY).await?;
+            if limit == 0 || offset >= total {
+                return Ok((Vec::new(), total));
+            }
+            let last = offset.saturating_add(limit).min(total) - 1;
+            let ids: Vec<String> = conn.zrange(DEAD_LETTER_INDEX_KEY, offset as isize, last as isize).await?;
+            let tasks = Self::get_dead_letter_payloads(&mut conn, &ids)
+                .await?
+                .into_iter()
+                .map(|(_, task)| task)
+                .collect();
+            return Ok((tasks, total));
+        }
+
+        // A filter needs the payloads, so the index is read a chunk at a time and reading stops once
+        // the page is full and DEAD_LETTER_COUNT_AHEAD more matches were counted. `total` is exact
+        // up to that point and capped there beyond it.
+        let window = offset.saturating_add(limit);
+        let count_limit = window.saturating_add(DEAD_LETTER_COUNT_AHEAD);
+        let mut total = 0;
+        let mut page = Vec::new();
+        let mut start = 0;
+        while total < count_limit {
+            let last = start + DEAD_LETTER_SCAN_CHUNK - 1;
+            let ids: Vec<String> = conn.zrange(DEAD_LETTER_INDEX_KEY, start as isize, last as isize).await?;
+            if ids.is_empty() {
+                break;
+            }
+            start += ids.len();
+
+            for (_, task) in Self::get_dead_letter_payloads(&mut conn, &ids).await? {
+                if !filter.matches(&task) {
+                    continue;
+                }
+                if total >= offset && total < window {
+                    page.push(task);
+                }
+                total += 1;
+                if total == count_limit {
+                    break;
+                }
+            }
+        }
+        Ok((page, total))
+    }
+
+    async fn replay_dead_letter(
+        &self,
+        task_id: &str,
+        mutation: TaskMutation<'_>,
+    ) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut conn = self.connection().await?;
+
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let entry: Option<String> = conn.hget(DEAD_LETTER_TASKS_KEY, task_id).await?;
+            let Some(entry) = entry else {
+                return Ok(None);
+            };
+            let mut task: BaseTask = serde_json::from_str(&entry)?;
+            mutation(&mut task)?;
+
+            let result: String = queue_script(REPLAY_DEAD_LETTER_SCRIPT)
+                .key(TASKS_KEY)
+                .key(queue_key(task.priority))
+                .key(DEAD_LETTER_INDEX_KEY)
+                .key(DEAD_LETTER_TASKS_KEY)
+                .arg(task_id)
+                .arg(&entry)
+                .arg(serde_json::to_string(&task)?)
+                .arg(task.scheduled_at)
+                .invoke_async(&mut conn)
+                .await?;
+
+            match result.as_str() {
+                "replayed" => return Ok(Some(task)),
+                "duplicate" => return Err(TaskQueueError::DuplicateTask(task.id)),
+                _ => debug!(task_id = %task_id, "dead-letter entry changed during replay, retrying"),
+            }
+        }
+
+        Err(TaskQueueError::StateError(format!(
+            "Dead-lettered task '{task_id}' kept changing while it was being replayed"
+        )))
+    }
+
+    async fn delete_dead_letter(&self, task_id: &str) -> Result<bool, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let (_, removed): (usize, bool) = ::redis::pipe()
+            .atomic()
+            .zrem(DEAD_LETTER_INDEX_KEY, task_id)
+            .hdel(DEAD_LETTER_TASKS_KEY, task_id)
+            .query_async(&mut conn)
+            .await?;
+        Ok(removed)
+    }
+
+    async fn purge_dead_letters(&self) -> Result<usize, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let (purged, _): (usize, ()) = ::redis::pipe()
+            .atomic()
+            .zcard(DEAD_LETTER_INDEX_KEY)
+            .del(&[DEAD_LETTER_INDEX_KEY, DEAD_LETTER_TASKS_KEY])
+            .query_async(&mut conn)
+            .await?;
+        Ok(purged)
+    }
+
+    async fn begin_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<IdempotencyState, TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let redis_key = idempotency_key(scope, key);
+        let pending = serde_json::to_string(&IdempotencyRecord {
+            fingerprint: fingerprint.to_string(),
+            response: None,
+        })?;
+
+        let reserved: bool = ::redis::cmd("SET")
+            .arg(&redis_key)
+            .arg(pending)
+            .arg("NX")
+            .arg("EX")
+            .arg(IDEMPOTENCY_TTL_SECS)
+            .query_async::<Option<String>>(&mut conn)
+            .await?
+            .is_some();
+        if reserved {
+            return Ok(IdempotencyState::New);
+        }
+
+        let record: Option<String> = conn.get(&redis_key).await?;
+        let Some(record) = record else {
+            // The earlier reservation expired between the two commands; treat the retry as in
+            // progress so the client simply tries again.
+            return Ok(IdempotencyState::InProgress);
+        };
+        let record: IdempotencyRecord = serde_json::from_str(&record)?;
+
+        if record.fingerprint != fingerprint {
+            return Ok(IdempotencyState::Mismatch);
+        }
+        Ok(match record.response {
+            Some(response) => IdempotencyState::Completed(response),
+            None => IdempotencyState::InProgress,
+        })
+    }
+
+    async fn complete_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let record = serde_json::to_string(&IdempotencyRecord {
+            fingerprint: fingerprint.to_string(),
+            response: Some(response.clone()),
+        })?;
+
+        let _: () = conn.set_ex(idempotency_key(scope, key), record, IDEMPOTENCY_TTL_SECS).await?;
+        Ok(())
+    }
+
+    async fn release_idempotent_request(&self, scope: &str, key: &str) -> Result<(), TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let _: () = conn.del(idempotency_key(scope, key)).await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), TaskQueueError> {
+        let mut conn = self.connection().await?;
+        let _: () = conn
+            .del(&[
+                TASKS_KEY,
+                queue_key(TaskPriority::High),
+                queue_key(TaskPriority::Normal),
+                queue_key(TaskPriority::Low),
+                IN_FLIGHT_KEY,
+                LEASE_TOKENS_KEY,
+            ])
+            .await?;
+
+        for prefix in [CATEGORY_QUEUE_PREFIX, OCCURRENCE_INDEX_PREFIX, RUN_HISTORY_PREFIX] {
+            let history_keys: Vec<String> = {
+                let mut keys = conn.scan_match::<_, String>(format!("{prefix}*")).await?;
+                let mut collected = Vec::new();
+                while let Some(key) = keys.next_item().await {
+                    collected.push(key);
+                }
+                collected
+            };
+            if !history_keys.is_empty() {
+                let _: () = conn.del(history_keys).await?;
+            }
+        }
+        Ok(())
+    }
+}
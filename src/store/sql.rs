@@ -0,0 +1,771 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use sqlx::any::{AnyArguments, AnyPoolOptions, AnyRow};
+use sqlx::query::Query;
+use sqlx::{Any, AnyPool, Row, Transaction};
+
+use super::{
+    SeriesOutcome, TaskMutation, TaskStore, IDEMPOTENCY_TTL_SECS, MAX_INDEXED_OCCURRENCES, MAX_RECORDED_RUNS,
+    RUN_HISTORY_TTL_SECS,
+};
+use crate::errors::TaskQueueError;
+use crate::queue::{
+    DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, LeaseToken, OccurrenceState,
+    OccurrenceSummary, QueueOrder, QueueStats, TaskFilter, TaskRun, TaskState,
+};
+use crate::task::{BaseTask, TaskPriority};
+
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!("./migrations");
+
+// How many times a write is retried when a concurrent write of the same row keeps winning.
+const MAX_WRITE_ATTEMPTS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dialect {
+    Postgres,
+    Sqlite,
+}
+
+impl Dialect {
+    fn from_url(url: &str) -> Option<Self> {
+        let scheme = url.split(':').next()?.to_ascii_lowercase();
+        match scheme.as_str() {
+            "postgres" | "postgresql" => Some(Dialect::Postgres),
+            "sqlite" => Some(Dialect::Sqlite),
+            _ => None,
+        }
+    }
+
+    // Row locks for read-modify-write transactions. SQLite has none; its store uses a single
+    // connection, which serializes every transaction instead.
+    fn lock_rows(&self) -> &'static str {
+        match self {
+            Dialect::Postgres => " FOR UPDATE",
+            Dialect::Sqlite => "",
+        }
+    }
+
+    // Lets concurrent claimers pass over rows another claimer already locked.
+    fn skip_locked(&self) -> &'static str {
+        match self {
+            Dialect::Postgres => " FOR UPDATE SKIP LOCKED",
+            Dialect::Sqlite => "",
+        }
+    }
+}
+
+fn priority_rank(priority: TaskPriority) -> i64 {
+    match priority {
+        TaskPriority::High => 0,
+        TaskPriority::Normal => 1,
+        TaskPriority::Low => 2,
+    }
+}
+
+fn to_i64(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+// The WHERE clause selecting the queued tasks `filter` matches, and how many parameters it takes.
+// `bind_task_filter` binds them in the same order. Like the Redis queues, the schedule filters
+// compare the queue score, which is `scheduled_at` unless an expired lease made the task due again.
+fn task_filter_clause(filter: &TaskFilter) -> (String, usize) {
+    let columns = [
+        filter.category.as_ref().map(|_| "category ="),
+        filter.priority.map(|_| "priority ="),
+        filter.scheduled_after.map(|_| "score >="),
+        filter.scheduled_before.map(|_| "score <="),
+    ];
+    let mut conditions = vec!["score IS NOT NULL".to_string()];
+    for (index, column) in columns.into_iter().flatten().enumerate() {
+        conditions.push(format!("{column} ${}", index + 1));
+    }
+    let parameters = conditions.len() - 1;
+    (conditions.join(" AND "), parameters)
+}
+
+fn bind_task_filter<'q>(
+    mut query: Query<'q, Any, AnyArguments<'q>>,
+    filter: &'q TaskFilter,
+) -> Query<'q, Any, AnyArguments<'q>> {
+    if let Some(category) = &filter.category {
+        query = query.bind(category.as_str());
+    }
+    if let Some(priority) = filter.priority {
+        query = query.bind(priority_rank(priority));
+    }
+    if let Some(after) = filter.scheduled_after {
+        query = query.bind(to_i64(after));
+    }
+    if let Some(before) = filter.scheduled_before {
+        query = query.bind(to_i64(before));
+    }
+    query
+}
+
+fn parse_task(row: &AnyRow, column: &str) -> Result<BaseTask, TaskQueueError> {
+    let payload: String = row.try_get(column)?;
+    Ok(serde_json::from_str(&payload)?)
+}
+
+/// Keeps tasks in Postgres or SQLite (behind the `sql` feature), for deployments without Redis or
+/// that want the queue in a relational database. The schema is migrated on connect.
+///
+/// On Postgres claims use `SELECT ... FOR UPDATE SKIP LOCKED`, so several replicas can share one
+/// database. SQLite allows a single writer, so the store uses one connection and every operation
+/// runs in turn; it suits a single Thermite process.
+#[derive(Debug, Clone)]
+pub struct SqlStore {
+    pool: AnyPool,
+    dialect: Dialect,
+}
+
+impl SqlStore {
+    /// Connects to a `postgres://`, `postgresql://` or `sqlite:` URL and applies pending migrations.
+    pub async fn connect(url: &str) -> Result<Self, TaskQueueError> {
+        let dialect = Dialect::from_url(url).ok_or_else(|| {
+            TaskQueueError::InvalidConfiguration(format!("'{url}' is not a postgres:// or sqlite: URL"))
+        })?;
+        sqlx::any::install_default_drivers();
+
+        let options = match dialect {
+            Dialect::Postgres => AnyPoolOptions::new().max_connections(10),
+            // An in-memory SQLite database lives as long as its connection, so it is never recycled.
+            Dialect::Sqlite => AnyPoolOptions::new().max_connections(1).idle_timeout(None).max_lifetime(None),
+        };
+        let pool = options.connect(url).await?;
+        MIGRATOR.run(&pool).await.map_err(sqlx::Error::from)?;
+
+        Ok(SqlStore { pool, dialect })
+    }
+
+    async fn begin(&self) -> Result<Transaction<'static, Any>, TaskQueueError> {
+        Ok(self.pool.begin().await?)
+    }
+
+    // Writes `task` into its row as queued at `score`, replacing whatever the row held.
+    async fn write_queued(
+        tx: &mut Transaction<'static, Any>,
+        task: &BaseTask,
+        score: i64,
+    ) -> Result<(), TaskQueueError> {
+        sqlx::query(
+            "UPDATE thermite_tasks SET category = $2, priority = $3, scheduled_at = $4, score = $5, \
+             lease_deadline = NULL, lease_token = NULL, payload = $6 WHERE id = $1",
+        )
+        .bind(&task.id)
+        .bind(&task.category)
+        .bind(priority_rank(task.priority))
+        .bind(to_i64(task.scheduled_at))
+        .bind(score)
+        .bind(serde_json::to_string(task)?)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    // Inserts `task` unless its id is taken; returns whether it was inserted. A task inserted with a
+    // `lease` (its deadline and token) is in flight.
+    async fn insert(
+        tx: &mut Transaction<'static, Any>,
+        task: &BaseTask,
+        score: Option<i64>,
+        lease: Option<(i64, LeaseToken)>,
+    ) -> Result<bool, TaskQueueError> {
+        let inserted = sqlx::query(
+            "INSERT INTO thermite_tasks (id, category, priority, scheduled_at, score, lease_deadline, lease_token, \
+             payload) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(&task.id)
+        .bind(&task.category)
+        .bind(priority_rank(task.priority))
+        .bind(to_i64(task.scheduled_at))
+        .bind(score)
+        .bind(lease.map(|(deadline, _)| deadline))
+        .bind(lease.map(|(_, token)| token.raw()))
+        .bind(serde_json::to_string(task)?)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+        Ok(inserted > 0)
+    }
+
+    // Locks a task's row and returns its lease deadline (`Some(None)` while queued), or `None`
+    // when no such task exists.
+    async fn lock_task(
+        &self,
+        tx: &mut Transaction<'static, Any>,
+        task_id: &str,
+    ) -> Result<Option<Option<i64>>, TaskQueueError> {
+        let query = format!("SELECT lease_deadline FROM thermite_tasks WHERE id = $1{}", self.dialect.lock_rows());
+        let row = sqlx::query(&query).bind(task_id).fetch_optional(&mut **tx).await?;
+        Ok(match row {
+            Some(row) => Some(row.try_get("lease_deadline")?),
+            None => None,
+        })
+    }
+
+    // Locks a task's row and returns whether it is leased under `lease`.
+    async fn lock_lease(
+        &self,
+        tx: &mut Transaction<'static, Any>,
+        task_id: &str,
+        lease: LeaseToken,
+    ) -> Result<bool, TaskQueueError> {
+        let query = format!("SELECT lease_token FROM thermite_tasks WHERE id = $1{}", self.dialect.lock_rows());
+        let row = sqlx::query(&query).bind(task_id).fetch_optional(&mut **tx).await?;
+        Ok(match row {
+            Some(row) => row.try_get::<Option<i64>, _>("lease_token")? == Some(lease.raw()),
+            None => false,
+        })
+    }
+
+    async fn try_enqueue(&self, task: &BaseTask, policy: DuplicatePolicy) -> Result<Option<EnqueueOutcome>, TaskQueueError> {
+        let mut tx = self.begin().await?;
+        let score = to_i64(task.scheduled_at);
+
+        if Self::insert(&mut tx, task, Some(score), None).await? {
+            tx.commit().await?;
+            return Ok(Some(EnqueueOutcome::Enqueued));
+        }
+        match policy {
+            DuplicatePolicy::Reject => return Err(TaskQueueError::DuplicateTask(task.id.clone())),
+            DuplicatePolicy::KeepExisting => return Ok(Some(EnqueueOutcome::AlreadyExists)),
+            DuplicatePolicy::Replace => {}
+        }
+
+        match self.lock_task(&mut tx, &task.id).await? {
+            // Removed since the insert conflicted; try again.
+            None => Ok(None),
+            Some(Some(_)) => Err(TaskQueueError::TaskInFlight(task.id.clone())),
+            Some(None) => {
+                Self::write_queued(&mut tx, task, score).await?;
+                tx.commit().await?;
+                Ok(Some(EnqueueOutcome::Replaced))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TaskStore for SqlStore {
+    async fn enqueue(&self, task: &BaseTask, policy: DuplicatePolicy) -> Result<EnqueueOutcome, TaskQueueError> {
+        for _ in 0..MAX_WRITE_ATTEMPTS {
+            if let Some(outcome) = self.try_enqueue(task, policy).await? {
+                return Ok(outcome);
+            }
+        }
+
+        Err(TaskQueueError::StateError(format!(
+            "Task '{}' kept changing while it was being enqueued",
+            task.id
+        )))
+    }
+
+    async fn claim(&self, now: i64, lease_deadline: i64, order: QueueOrder) -> Result<Option<BaseTask>, TaskQueueError> {
+        let direction = match order {
+            QueueOrder::Fifo => "ASC",
+            QueueOrder::Lifo => "DESC",
+        };
+        let token = LeaseToken::new();
+        let query = format!(
+            "UPDATE thermite_tasks SET score = NULL, lease_deadline = $2, lease_token = $3 WHERE id = (\
+                 SELECT id FROM thermite_tasks WHERE score <= $1 \
+                 ORDER BY priority ASC, score {direction}, id {direction} LIMIT 1{}\
+             ) RETURNING payload",
+            self.dialect.skip_locked()
+        );
+
+        let row = sqlx::query(&query)
+            .bind(now)
+            .bind(lease_deadline)
+            .bind(token.raw())
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        Ok(Some(BaseTask { lease: Some(token), ..parse_task(&row, "payload")? }))
+    }
+
+    async fn extend_lease(&self, task_id: &str, lease: LeaseToken, lease_deadline: i64) -> Result<bool, TaskQueueError> {
+        let leased = sqlx::query(
+            "UPDATE thermite_tasks SET lease_deadline = \
+             CASE WHEN lease_deadline < $2 THEN $2 ELSE lease_deadline END \
+             WHERE id = $1 AND lease_deadline IS NOT NULL AND lease_token = $3",
+        )
+        .bind(task_id)
+        .bind(lease_deadline)
+        .bind(lease.raw())
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(leased > 0)
+    }
+
+    async fn reschedule_series(
+        &self,
+        next_run: &BaseTask,
+        occurrence: Option<&BaseTask>,
+        lease_deadline: i64,
+    ) -> Result<SeriesOutcome, TaskQueueError> {
+        let Some(lease) = next_run.lease else {
+            return Ok(SeriesOutcome::Cancelled);
+        };
+        let mut tx = self.begin().await?;
+
+        if !self.lock_lease(&mut tx, &next_run.id, lease).await? {
+            return Ok(SeriesOutcome::Cancelled);
+        }
+        Self::write_queued(&mut tx, next_run, to_i64(next_run.scheduled_at)).await?;
+
+        let token = LeaseToken::new();
+        let spawned = match occurrence {
+            Some(occurrence) => Self::insert(&mut tx, occurrence, None, Some((lease_deadline, token))).await?,
+            None => false,
+        };
+        if let (true, Some(occurrence)) = (spawned, occurrence) {
+            sqlx::query(
+                "INSERT INTO thermite_occurrences (series_id, id, scheduled_at) VALUES ($1, $2, $3) \
+                 ON CONFLICT (series_id, id) DO NOTHING",
+            )
+            .bind(&next_run.id)
+            .bind(&occurrence.id)
+            .bind(to_i64(occurrence.scheduled_at))
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query(
+                "DELETE FROM thermite_occurrences WHERE series_id = $1 AND id NOT IN (\
+                     SELECT id FROM thermite_occurrences WHERE series_id = $1 \
+                     ORDER BY scheduled_at DESC, id DESC LIMIT $2\
+                 )",
+            )
+            .bind(&next_run.id)
+            .bind(MAX_INDEXED_OCCURRENCES as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(if spawned { SeriesOutcome::Spawned(token) } else { SeriesOutcome::Rescheduled })
+    }
+
+    async fn settle(
+        &self,
+        task_id: &str,
+        lease: LeaseToken,
+        requeue: Option<&BaseTask>,
+        dead_letter: Option<&BaseTask>,
+        now: i64,
+    ) -> Result<bool, TaskQueueError> {
+        let mut tx = self.begin().await?;
+
+        if !self.lock_lease(&mut tx, task_id, lease).await? {
+            return Ok(false);
+        }
+        if let Some(task) = dead_letter {
+            sqlx::query(
+                "INSERT INTO thermite_dead_letters (id, category, dead_lettered_at, payload) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT (id) DO UPDATE SET category = excluded.category, \
+                 dead_lettered_at = excluded.dead_lettered_at, payload = excluded.payload",
+            )
+            .bind(task_id)
+            .bind(&task.category)
+            .bind(now)
+            .bind(serde_json::to_string(task)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        match requeue {
+            Some(task) => Self::write_queued(&mut tx, task, to_i64(task.scheduled_at)).await?,
+            None => {
+                sqlx::query("DELETE FROM thermite_tasks WHERE id = $1").bind(task_id).execute(&mut *tx).await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn requeue_expired_leases(&self, now: i64, limit: usize) -> Result<usize, TaskQueueError> {
+        let query = format!(
+            "UPDATE thermite_tasks SET score = $1, lease_deadline = NULL, lease_token = NULL WHERE id IN (\
+                 SELECT id FROM thermite_tasks WHERE lease_deadline <= $1 ORDER BY lease_deadline LIMIT $2{}\
+             )",
+            self.dialect.skip_locked()
+        );
+
+        let requeued = sqlx::query(&query)
+            .bind(now)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(requeued as usize)
+    }
+
+    async fn get(&self, task_id: &str) -> Result<Option<(BaseTask, TaskState)>, TaskQueueError> {
+        let row = sqlx::query("SELECT lease_deadline, payload FROM thermite_tasks WHERE id = $1")
+            .bind(task_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let lease: Option<i64> = row.try_get("lease_deadline")?;
+        let state = if lease.is_some() { TaskState::InFlight } else { TaskState::Queued };
+        Ok(Some((parse_task(&row, "payload")?, state)))
+    }
+
+    async fn list(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+        // Every filter has a column, so the database counts the matches and reads only one page.
+        let (clause, parameters) = task_filter_clause(filter);
+        let count_query = format!("SELECT COUNT(*) AS total FROM thermite_tasks WHERE {clause}");
+        let total: i64 = bind_task_filter(sqlx::query(&count_query), filter)
+            .fetch_one(&self.pool)
+            .await?
+            .try_get("total")?;
+        let page_query = format!(
+            "SELECT payload FROM thermite_tasks WHERE {clause} ORDER BY score, id LIMIT ${} OFFSET ${}",
+            parameters + 1,
+            parameters + 2
+        );
+        let rows = bind_task_filter(sqlx::query(&page_query), filter)
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+
+        let tasks = rows.iter().map(|row| parse_task(row, "payload")).collect::<Result<_, _>>()?;
+        Ok((tasks, total as usize))
+    }
+
+    async fn update(&self, task_id: &str, mutation: TaskMutation<'_>) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut tx = self.begin().await?;
+
+        let query = format!(
+            "SELECT lease_deadline, payload FROM thermite_tasks WHERE id = $1{}",
+            self.dialect.lock_rows()
+        );
+        let Some(row) = sqlx::query(&query).bind(task_id).fetch_optional(&mut *tx).await? else {
+            return Ok(None);
+        };
+        let mut task = parse_task(&row, "payload")?;
+        mutation(&mut task)?;
+        if row.try_get::<Option<i64>, _>("lease_deadline")?.is_some() {
+            return Err(TaskQueueError::TaskInFlight(task_id.to_string()));
+        }
+
+        Self::write_queued(&mut tx, &task, to_i64(task.scheduled_at)).await?;
+        tx.commit().await?;
+        Ok(Some(task))
+    }
+
+    async fn cancel(&self, task_id: &str) -> Result<bool, TaskQueueError> {
+        let removed = sqlx::query("DELETE FROM thermite_tasks WHERE id = $1")
+            .bind(task_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(removed > 0)
+    }
+
+    async fn list_occurrences(&self, series_id: &str) -> Result<Option<Vec<OccurrenceSummary>>, TaskQueueError> {
+        let rows = sqlx::query(
+            "SELECT o.id, o.scheduled_at, t.id AS task_id, t.lease_deadline, d.id AS dead_letter_id \
+             FROM thermite_occurrences o \
+             LEFT JOIN thermite_tasks t ON t.id = o.id \
+             LEFT JOIN thermite_dead_letters d ON d.id = o.id \
+             WHERE o.series_id = $1 ORDER BY o.scheduled_at DESC, o.id DESC",
+        )
+        .bind(series_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.is_empty() {
+            let series = sqlx::query("SELECT id FROM thermite_tasks WHERE id = $1")
+                .bind(series_id)
+                .fetch_optional(&self.pool)
+                .await?;
+            return Ok(series.map(|_| Vec::new()));
+        }
+
+        let mut occurrences = Vec::with_capacity(rows.len());
+        for row in rows {
+            let stored: Option<String> = row.try_get("task_id")?;
+            let lease: Option<i64> = row.try_get("lease_deadline")?;
+            let dead_letter: Option<String> = row.try_get("dead_letter_id")?;
+            let state = match (stored, lease, dead_letter) {
+                (Some(_), Some(_), _) => OccurrenceState::InFlight,
+                (Some(_), None, _) => OccurrenceState::Queued,
+                (None, _, Some(_)) => OccurrenceState::DeadLettered,
+                (None, _, None) => OccurrenceState::Finished,
+            };
+            let scheduled_at: i64 = row.try_get("scheduled_at")?;
+            occurrences.push(OccurrenceSummary { id: row.try_get("id")?, scheduled_at: scheduled_at.max(0) as u64, state });
+        }
+        Ok(Some(occurrences))
+    }
+
+    async fn ping(&self) -> Result<(), TaskQueueError> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    async fn stats(&self, now: i64) -> Result<QueueStats, TaskQueueError> {
+        let row = sqlx::query(
+            "SELECT \
+                 COALESCE(SUM(CASE WHEN score <= $1 THEN 1 ELSE 0 END), 0) AS due, \
+                 COALESCE(SUM(CASE WHEN score > $1 THEN 1 ELSE 0 END), 0) AS scheduled, \
+                 COALESCE(SUM(CASE WHEN lease_deadline IS NOT NULL THEN 1 ELSE 0 END), 0) AS in_flight, \
+                 (SELECT COUNT(*) FROM thermite_dead_letters) AS dead_lettered \
+             FROM thermite_tasks",
+        )
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        let count = |column: &str| -> Result<usize, TaskQueueError> {
+            let value: i64 = row.try_get(column)?;
+            Ok(value.max(0) as usize)
+        };
+        Ok(QueueStats {
+            due: count("due")?,
+            scheduled: count("scheduled")?,
+            in_flight: count("in_flight")?,
+            dead_lettered: count("dead_lettered")?,
+        })
+    }
+
+    async fn record_run(&self, task_id: &str, run: &TaskRun) -> Result<(), TaskQueueError> {
+        let payload = serde_json::to_string(run)?;
+        let recorded_at = to_i64(run.finished_at);
+
+        // Two runs of one task recorded at once can pick the same `seq`; the loser tries again.
+        let mut recorded = false;
+        for _ in 0..MAX_WRITE_ATTEMPTS {
+            let inserted = sqlx::query(
+                "INSERT INTO thermite_task_runs (task_id, seq, recorded_at, payload) \
+                 SELECT $1, COALESCE(MAX(seq), 0) + 1, $2, $3 FROM thermite_task_runs WHERE task_id = $1 \
+                 ON CONFLICT (task_id, seq) DO NOTHING",
+            )
+            .bind(task_id)
+            .bind(recorded_at)
+            .bind(&payload)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+            if inserted > 0 {
+                recorded = true;
+                break;
+            }
+        }
+        if !recorded {
+            return Err(TaskQueueError::StateError(format!(
+                "Run history of task '{task_id}' kept changing while a run was being recorded"
+            )));
+        }
+
+        sqlx::query(
+            "DELETE FROM thermite_task_runs WHERE task_id = $1 AND seq <= (\
+                 SELECT MAX(seq) FROM thermite_task_runs WHERE task_id = $1\
+             ) - $2",
+        )
+        .bind(task_id)
+        .bind(MAX_RECORDED_RUNS as i64)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("DELETE FROM thermite_task_runs WHERE recorded_at <= $1")
+            .bind(recorded_at - RUN_HISTORY_TTL_SECS as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_runs(&self, task_id: &str) -> Result<Vec<TaskRun>, TaskQueueError> {
+        let rows = sqlx::query("SELECT payload FROM thermite_task_runs WHERE task_id = $1 ORDER BY seq DESC")
+            .bind(task_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let payload: String = row.try_get("payload")?;
+                Ok(serde_json::from_str(&payload)?)
+            })
+            .collect()
+    }
+
+    async fn list_dead_letters(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> Result<(Vec<BaseTask>, usize), TaskQueueError> {
+        if filter.is_empty() {
+            // Without a filter the database can page, so only one page of payloads is read.
+            let total: i64 = sqlx::query("SELECT COUNT(*) AS total FROM thermite_dead_letters")
+                .fetch_one(&self.pool)
+                .await?
+                .try_get("total")?;
+            let rows = sqlx::query(
+                "SELECT payload FROM thermite_dead_letters ORDER BY dead_lettered_at, id LIMIT $1 OFFSET $2",
+            )
+            .bind(i64::try_from(limit).unwrap_or(i64::MAX))
+            .bind(i64::try_from(offset).unwrap_or(i64::MAX))
+            .fetch_all(&self.pool)
+            .await?;
+
+            let tasks = rows.iter().map(|row| parse_task(row, "payload")).collect::<Result<_, _>>()?;
+            return Ok((tasks, total as usize));
+        }
+
+        let rows = sqlx::query("SELECT payload FROM thermite_dead_letters ORDER BY dead_lettered_at, id")
+            .fetch_all(&self.pool)
+            .await?;
+        let mut matching = Vec::new();
+        for row in rows {
+            let task = parse_task(&row, "payload")?;
+            if filter.matches(&task) {
+                matching.push(task);
+            }
+        }
+
+        let total = matching.len();
+        Ok((matching.into_iter().skip(offset).take(limit).collect(), total))
+    }
+
+    async fn replay_dead_letter(
+        &self,
+        task_id: &str,
+        mutation: TaskMutation<'_>,
+    ) -> Result<Option<BaseTask>, TaskQueueError> {
+        let mut tx = self.begin().await?;
+
+        let query = format!("SELECT payload FROM thermite_dead_letters WHERE id = $1{}", self.dialect.lock_rows());
+        let Some(row) = sqlx::query(&query).bind(task_id).fetch_optional(&mut *tx).await? else {
+            return Ok(None);
+        };
+        let mut task = parse_task(&row, "payload")?;
+        mutation(&mut task)?;
+
+        if !Self::insert(&mut tx, &task, Some(to_i64(task.scheduled_at)), None).await? {
+            return Err(TaskQueueError::DuplicateTask(task.id));
+        }
+        sqlx::query("DELETE FROM thermite_dead_letters WHERE id = $1").bind(task_id).execute(&mut *tx).await?;
+
+        tx.commit().await?;
+        Ok(Some(task))
+    }
+
+    async fn delete_dead_letter(&self, task_id: &str) -> Result<bool, TaskQueueError> {
+        let removed = sqlx::query("DELETE FROM thermite_dead_letters WHERE id = $1")
+            .bind(task_id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(removed > 0)
+    }
+
+    async fn purge_dead_letters(&self) -> Result<usize, TaskQueueError> {
+        let purged = sqlx::query("DELETE FROM thermite_dead_letters").execute(&self.pool).await?.rows_affected();
+        Ok(purged as usize)
+    }
+
+    async fn begin_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<IdempotencyState, TaskQueueError> {
+        let idempotency_key = format!("{scope}:{key}");
+        let now = Utc::now().timestamp();
+
+        // Forgets expired keys, which would otherwise pile up for good.
+        sqlx::query("DELETE FROM thermite_idempotency_keys WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+
+        // Reserves the key, taking over a reservation that has expired.
+        let reserved = sqlx::query(
+            "INSERT INTO thermite_idempotency_keys (idempotency_key, fingerprint, response, expires_at) \
+             VALUES ($1, $2, NULL, $3) \
+             ON CONFLICT (idempotency_key) DO UPDATE SET fingerprint = excluded.fingerprint, response = NULL, \
+             expires_at = excluded.expires_at WHERE thermite_idempotency_keys.expires_at <= $4",
+        )
+        .bind(&idempotency_key)
+        .bind(fingerprint)
+        .bind(now + IDEMPOTENCY_TTL_SECS as i64)
+        .bind(now)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if reserved > 0 {
+            return Ok(IdempotencyState::New);
+        }
+
+        let row = sqlx::query("SELECT fingerprint, response FROM thermite_idempotency_keys WHERE idempotency_key = $1")
+            .bind(&idempotency_key)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(row) = row else {
+            // Released between the two statements; treat the retry as in progress so the client
+            // simply tries again.
+            return Ok(IdempotencyState::InProgress);
+        };
+
+        if row.try_get::<String, _>("fingerprint")? != fingerprint {
+            return Ok(IdempotencyState::Mismatch);
+        }
+        Ok(match row.try_get::<Option<String>, _>("response")? {
+            Some(response) => IdempotencyState::Completed(serde_json::from_str(&response)?),
+            None => IdempotencyState::InProgress,
+        })
+    }
+
+    async fn complete_idempotent_request(
+        &self,
+        scope: &str,
+        key: &str,
+        fingerprint: &str,
+        response: &IdempotentResponse,
+    ) -> Result<(), TaskQueueError> {
+        sqlx::query(
+            "INSERT INTO thermite_idempotency_keys (idempotency_key, fingerprint, response, expires_at) \
+             VALUES ($1, $2, $3, $4) \
+             ON CONFLICT (idempotency_key) DO UPDATE SET fingerprint = excluded.fingerprint, \
+             response = excluded.response, expires_at = excluded.expires_at",
+        )
+        .bind(format!("{scope}:{key}"))
+        .bind(fingerprint)
+        .bind(serde_json::to_string(response)?)
+        .bind(Utc::now().timestamp() + IDEMPOTENCY_TTL_SECS as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release_idempotent_request(&self, scope: &str, key: &str) -> Result<(), TaskQueueError> {
+        sqlx::query("DELETE FROM thermite_idempotency_keys WHERE idempotency_key = $1")
+            .bind(format!("{scope}:{key}"))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn clear(&self) -> Result<(), TaskQueueError> {
+        let mut tx = self.begin().await?;
+        sqlx::query("DELETE FROM thermite_tasks").execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM thermite_occurrences").execute(&mut *tx).await?;
+        sqlx::query("DELETE FROM thermite_task_runs").execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
@@ -1,26 +1,267 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
 use std::net::IpAddr;
 use std::str::FromStr;
 
-use chrono::Utc;
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, Offset, TimeZone, Utc};
+use chrono_tz::Tz;
 use cron::Schedule;
-use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Deserializer, Serialize};
 use tracing::debug;
-use url::Url;
+use url::{Host, Url};
 
+use crate::config::RetryPolicy;
 use crate::errors::TaskQueueError;
+use crate::queue::LeaseToken;
+use crate::ssrf::is_blocked_ip;
+
+/// The dispatch priority of a task. Due `High` tasks are claimed before `Normal` ones,
+/// and `Normal` before `Low`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskPriority {
+    High,
+    #[default]
+    Normal,
+    Low,
+}
+
+impl TaskPriority {
+    /// Every priority level, in the order due tasks are claimed.
+    pub const ALL: [TaskPriority; 3] = [TaskPriority::High, TaskPriority::Normal, TaskPriority::Low];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskPriority::High => "high",
+            TaskPriority::Normal => "normal",
+            TaskPriority::Low => "low",
+        }
+    }
+}
+
+impl fmt::Display for TaskPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TaskPriority {
+    type Err = TaskQueueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "high" => Ok(TaskPriority::High),
+            "normal" => Ok(TaskPriority::Normal),
+            "low" => Ok(TaskPriority::Low),
+            other => Err(TaskQueueError::InvalidPriority(other.to_string())),
+        }
+    }
+}
+
+/// What happens to the occurrences of a periodic task that passed while it could not run, for
+/// example because Thermite was down.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MisfirePolicy {
+    /// Drop the missed occurrences, including the stale run itself, and wait for the next one.
+    Skip,
+    /// Run once for all missed occurrences, then wait for the next one.
+    #[default]
+    RunOnce,
+    /// Run every missed occurrence, oldest first, up to `max_catch_up` of them.
+    RunAll,
+}
+
+/// How many missed occurrences `run_all` catches up on when a task does not say.
+pub const DEFAULT_MAX_CATCH_UP: u32 = 10;
+
+/// The largest `max_catch_up` a task may ask for.
+pub const MAX_CATCH_UP_LIMIT: u32 = 1000;
 
-fn default_max_retries() -> u32 {
-    std::env::var("THERMITE_MAX_RETRIES")
-        .ok()
-        .and_then(|value| value.parse::<u32>().ok())
-        .unwrap_or(3)
+// Bounds the walk over missed occurrences for very frequent schedules after a long outage.
+const MAX_COUNTED_MISSED_RUNS: usize = 100_000;
+
+fn default_max_catch_up() -> u32 {
+    DEFAULT_MAX_CATCH_UP
+}
+
+/// The longest per-task delivery timeout that may be requested.
+pub const MAX_TIMEOUT_SECS: u64 = 3600;
+
+/// The delivery timeout of tasks that do not set `timeout_secs`.
+pub const DEFAULT_TIMEOUT_SECS: u64 = 15;
+
+// Headers that describe the connection rather than the request, or that Thermite sets itself.
+const FORBIDDEN_HEADERS: [&str; 12] = [
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "thermite-signature",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+const SENSITIVE_HEADER_MARKERS: [&str; 6] = ["auth", "cookie", "key", "password", "secret", "token"];
+
+const REDACTED: &str = "[redacted]";
+
+/// The HTTP method a task is delivered with. Accepted case-insensitively, serialized uppercase.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum DeliveryMethod {
+    Get,
+    #[default]
+    Post,
+    Put,
+    Patch,
+    Delete,
 }
 
-fn default_retry_base_delay_secs() -> u64 {
-    std::env::var("THERMITE_RETRY_BASE_DELAY_SECS")
-        .ok()
-        .and_then(|value| value.parse::<u64>().ok())
-        .unwrap_or(30)
+impl DeliveryMethod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryMethod::Get => "GET",
+            DeliveryMethod::Post => "POST",
+            DeliveryMethod::Put => "PUT",
+            DeliveryMethod::Patch => "PATCH",
+            DeliveryMethod::Delete => "DELETE",
+        }
+    }
+}
+
+impl fmt::Display for DeliveryMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DeliveryMethod {
+    type Err = TaskQueueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_uppercase().as_str() {
+            "GET" => Ok(DeliveryMethod::Get),
+            "POST" => Ok(DeliveryMethod::Post),
+            "PUT" => Ok(DeliveryMethod::Put),
+            "PATCH" => Ok(DeliveryMethod::Patch),
+            "DELETE" => Ok(DeliveryMethod::Delete),
+            _ => Err(TaskQueueError::InvalidDeliveryOptions(format!(
+                "Unsupported method '{value}'; expected GET, POST, PUT, PATCH or DELETE"
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DeliveryMethod {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Extra headers sent with every delivery of a task.
+///
+/// Values of headers that look like credentials (`Authorization`, `X-Api-Key`, `Cookie`, anything
+/// mentioning a token or secret, ...) never appear in `Debug` output and are masked by
+/// [`BaseTask::redacted`], which the HTTP API uses for every task it returns. The queued task keeps
+/// the real values so retries still authenticate; the dead-letter copy is redacted, so replaying it
+/// needs those headers supplied again.
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(transparent)]
+pub struct DeliveryHeaders(BTreeMap<String, String>);
+
+impl DeliveryHeaders {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn is_sensitive(name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        SENSITIVE_HEADER_MARKERS.iter().any(|marker| name.contains(marker))
+    }
+
+    /// Returns a copy with the values of sensitive headers masked.
+    pub fn redacted(&self) -> Self {
+        self.0
+            .iter()
+            .map(|(name, value)| {
+                let value = if Self::is_sensitive(name) { REDACTED.to_string() } else { value.clone() };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Names of the headers whose value was masked by [`DeliveryHeaders::redacted`].
+    pub fn redacted_names(&self) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter(|(_, value)| value.as_str() == REDACTED)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Sets every header of `over`, replacing existing ones whose name matches regardless of case.
+    pub fn merge(&mut self, over: &DeliveryHeaders) {
+        for (name, value) in &over.0 {
+            self.0.retain(|existing, _| !existing.eq_ignore_ascii_case(name));
+            self.0.insert(name.clone(), value.clone());
+        }
+    }
+
+    fn validate(&self) -> Result<(), TaskQueueError> {
+        for (name, value) in &self.0 {
+            HeaderName::from_bytes(name.as_bytes()).map_err(|_| {
+                TaskQueueError::InvalidDeliveryOptions(format!("Invalid header name '{name}'"))
+            })?;
+            if FORBIDDEN_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                return Err(TaskQueueError::InvalidDeliveryOptions(format!(
+                    "Header '{name}' cannot be set on a task"
+                )));
+            }
+            HeaderValue::from_str(value).map_err(|_| {
+                TaskQueueError::InvalidDeliveryOptions(format!("Invalid value for header '{name}'"))
+            })?;
+        }
+
+        let mut names: Vec<String> = self.0.keys().map(|name| name.to_ascii_lowercase()).collect();
+        names.sort();
+        if names.windows(2).any(|pair| pair[0] == pair[1]) {
+            return Err(TaskQueueError::InvalidDeliveryOptions(
+                "Header names must be unique regardless of case".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DeliveryHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.redacted().0.iter()).finish()
+    }
+}
+
+impl FromIterator<(String, String)> for DeliveryHeaders {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        DeliveryHeaders(iter.into_iter().collect())
+    }
 }
 
 /// A structure holding two public integers.
@@ -28,22 +269,31 @@ fn default_retry_base_delay_secs() -> u64 {
 /// Example:
 ///
 /// ```
-/// use thermite::task::BaseTask;
+/// use thermite::task::{BaseTask, DeliveryHeaders, DeliveryMethod, MisfirePolicy, TaskPriority};
 ///
 /// let task = BaseTask {
 ///    id: "1".to_string(),
 ///    name: "Task 1".to_string(),
 ///    description: "Task 1 description".to_string(),
 ///    category: "non_periodic".to_string(),
-///    priority: "high".to_string(),
+///    priority: TaskPriority::High,
 ///    task: "http://localhost:8080/task".to_string(),
 ///    scheduled_at: 1628764800,
 ///    cron_scheduled_at: "* 0 0 * * *".to_string(),
+///    timezone: None,
 ///    args: None,
-///    max_retries: 3,
+///    misfire_policy: MisfirePolicy::RunOnce,
+///    max_catch_up: 10,
+///    method: DeliveryMethod::Post,
+///    headers: DeliveryHeaders::default(),
+///    timeout_secs: None,
+///    max_retries: Some(3),
 ///    retry_count: 0,
 ///    last_error: None,
 ///    is_retry: false,
+///    missed_runs: 0,
+///    series_id: None,
+///    lease: None,
 /// };
 ///
 /// assert_eq!(task.id, "1");
@@ -54,19 +304,44 @@ pub struct BaseTask {
     pub name: String,
     pub description: String,
     pub category: String,
-    pub priority: String,
+    #[serde(default)]
+    pub priority: TaskPriority,
     pub task: String,
     pub scheduled_at: u64,
     pub cron_scheduled_at: String,
+    /// IANA timezone the cron schedule is evaluated in, e.g. `Europe/Berlin`. Defaults to UTC.
+    #[serde(default)]
+    pub timezone: Option<String>,
     pub args: Option<std::collections::HashMap<String, serde_json::Value>>,
-    #[serde(default = "default_max_retries")]
-    pub max_retries: u32,
+    #[serde(default)]
+    pub misfire_policy: MisfirePolicy,
+    #[serde(default = "default_max_catch_up")]
+    pub max_catch_up: u32,
+    #[serde(default)]
+    pub method: DeliveryMethod,
+    #[serde(default)]
+    pub headers: DeliveryHeaders,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Retries before the task is dead-lettered; unset uses the configured `retry.max_retries`.
+    #[serde(default)]
+    pub max_retries: Option<u32>,
     #[serde(default)]
     pub retry_count: u32,
     #[serde(default)]
     pub last_error: Option<String>,
     #[serde(default)]
     pub is_retry: bool,
+    /// Occurrences that had already passed when this run was claimed; tracked by Thermite.
+    #[serde(default)]
+    pub missed_runs: u32,
+    /// For an occurrence of a periodic task, the id of the periodic task it was created from.
+    #[serde(default)]
+    pub series_id: Option<String>,
+    /// The lease a claimed task is held under, set by the store that claimed it. Only the claim
+    /// holding it can settle the task; never serialized.
+    #[serde(skip)]
+    pub lease: Option<LeaseToken>,
 }
 
 
@@ -77,19 +352,40 @@ pub struct BaseTaskPayload {
     pub name: String,
     pub description: String,
     pub category: String,
-    pub priority: String,
+    #[serde(default)]
+    pub priority: TaskPriority,
     pub task: String,
     pub scheduled_at: u64,
     pub cron_scheduled_at: String,
+    /// IANA timezone the cron schedule is evaluated in, e.g. `Europe/Berlin`. Defaults to UTC.
+    #[serde(default)]
+    pub timezone: Option<String>,
     pub args: Option<std::collections::HashMap<String, serde_json::Value>>,
-    #[serde(default = "default_max_retries")]
-    pub max_retries: u32,
+    #[serde(default)]
+    pub misfire_policy: MisfirePolicy,
+    #[serde(default = "default_max_catch_up")]
+    pub max_catch_up: u32,
+    #[serde(default)]
+    pub method: DeliveryMethod,
+    #[serde(default)]
+    pub headers: DeliveryHeaders,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Retries before the task is dead-lettered; unset uses the configured `retry.max_retries`.
+    #[serde(default)]
+    pub max_retries: Option<u32>,
     #[serde(default)]
     pub retry_count: u32,
     #[serde(default)]
     pub last_error: Option<String>,
     #[serde(default)]
     pub is_retry: bool,
+    /// Occurrences that had already passed when this run was claimed; tracked by Thermite.
+    #[serde(default)]
+    pub missed_runs: u32,
+    /// For an occurrence of a periodic task, the id of the periodic task it was created from.
+    #[serde(default)]
+    pub series_id: Option<String>,
 }
 
 impl From<BaseTaskPayload> for BaseTask {
@@ -103,15 +399,105 @@ impl From<BaseTaskPayload> for BaseTask {
             task: payload.task,
             scheduled_at: payload.scheduled_at,
             cron_scheduled_at: payload.cron_scheduled_at,
+            timezone: payload.timezone,
             args: payload.args,
+            misfire_policy: payload.misfire_policy,
+            max_catch_up: payload.max_catch_up,
+            method: payload.method,
+            headers: payload.headers,
+            timeout_secs: payload.timeout_secs,
             max_retries: payload.max_retries,
             retry_count: payload.retry_count,
             last_error: payload.last_error,
             is_retry: payload.is_retry,
+            missed_runs: payload.missed_runs,
+            series_id: payload.series_id,
+            lease: None,
         }
     }
 }
 
+// Distinguishes an explicit `null` (Some(None)) from a missing field (None) in partial updates.
+fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// A partial update of a queued task. Fields that are absent are left unchanged.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TaskUpdate {
+    pub scheduled_at: Option<u64>,
+    pub cron_scheduled_at: Option<String>,
+    /// `null` goes back to UTC.
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub timezone: Option<Option<String>>,
+    /// `null` clears the arguments.
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub args: Option<Option<std::collections::HashMap<String, serde_json::Value>>>,
+    pub priority: Option<TaskPriority>,
+    pub max_retries: Option<u32>,
+    pub misfire_policy: Option<MisfirePolicy>,
+    pub max_catch_up: Option<u32>,
+    pub method: Option<DeliveryMethod>,
+    /// Replaces the whole header set.
+    pub headers: Option<DeliveryHeaders>,
+    /// `null` falls back to the default client timeout.
+    #[serde(default, deserialize_with = "deserialize_present")]
+    pub timeout_secs: Option<Option<u64>>,
+}
+
+impl TaskUpdate {
+    /// Applies the update to `task` and re-validates the result. Changing the cron schedule or
+    /// timezone of a periodic task without an explicit `scheduled_at` moves it to the next
+    /// occurrence of the new schedule.
+    pub fn apply(&self, task: &mut BaseTask) -> Result<(), TaskQueueError> {
+        if let Some(cron_scheduled_at) = &self.cron_scheduled_at {
+            task.cron_scheduled_at = cron_scheduled_at.clone();
+        }
+        if let Some(timezone) = &self.timezone {
+            task.timezone = timezone.clone();
+        }
+        if let Some(args) = &self.args {
+            task.args = args.clone();
+        }
+        if let Some(priority) = self.priority {
+            task.priority = priority;
+        }
+        if let Some(max_retries) = self.max_retries {
+            task.max_retries = Some(max_retries);
+        }
+        if let Some(misfire_policy) = self.misfire_policy {
+            task.misfire_policy = misfire_policy;
+        }
+        if let Some(max_catch_up) = self.max_catch_up {
+            task.max_catch_up = max_catch_up;
+        }
+        if let Some(method) = self.method {
+            task.method = method;
+        }
+        if let Some(headers) = &self.headers {
+            task.headers = headers.clone();
+        }
+        if let Some(timeout_secs) = self.timeout_secs {
+            task.timeout_secs = timeout_secs;
+        }
+
+        match self.scheduled_at {
+            Some(scheduled_at) => task.scheduled_at = scheduled_at,
+            None if (self.cron_scheduled_at.is_some() || self.timezone.is_some()) && task.category == "periodic" => {
+                task.set_next_unix_datetime()?;
+            }
+            None => {}
+        }
+
+        task.validate()
+    }
+}
+
 impl Default for BaseTask {
     fn default() -> Self {
         BaseTask {
@@ -119,111 +505,165 @@ impl Default for BaseTask {
             name: "".to_string(),
             description: "".to_string(),
             category: "".to_string(),
-            priority: "".to_string(),
+            priority: TaskPriority::default(),
             task: "".to_string(),
             scheduled_at: 0,
             cron_scheduled_at: "".to_string(),
+            timezone: None,
             args: None,
-            max_retries: default_max_retries(),
+            misfire_policy: MisfirePolicy::default(),
+            max_catch_up: DEFAULT_MAX_CATCH_UP,
+            method: DeliveryMethod::default(),
+            headers: DeliveryHeaders::default(),
+            timeout_secs: None,
+            max_retries: None,
             retry_count: 0,
             last_error: None,
             is_retry: false,
+            missed_runs: 0,
+            series_id: None,
+            lease: None,
         }
     }
 }
 
 
-impl BaseTask {
-    fn env_flag(name: &str) -> bool {
-        std::env::var(name)
-            .map(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes" | "on"))
-            .unwrap_or(false)
-    }
-
-    fn is_disallowed_ip_address(ip: IpAddr) -> bool {
-        match ip {
-            IpAddr::V4(address) => {
-                address.is_private()
-                    || address.is_loopback()
-                    || address.is_link_local()
-                    || address.is_broadcast()
-                    || address.is_documentation()
-                    || address.is_unspecified()
-                    || address.is_multicast()
-            }
-            IpAddr::V6(address) => {
-                let first_segment = address.segments()[0];
-
-                address.is_loopback()
-                    || address.is_unspecified()
-                    || (first_segment & 0xfe00) == 0xfc00
-                    || (first_segment & 0xffc0) == 0xfe80
-                    || address.is_multicast()
-            }
+/// Checks a delivery target URL: scheme, host and blocked literal IPs. Used for submitted targets
+/// and for every redirect a delivery follows; the operator's `config::TargetPolicy` is checked
+/// alongside it.
+pub fn validate_target(url: &str) -> Result<(), TaskQueueError> {
+    let parsed_url = Url::parse(url).map_err(|e| {
+        TaskQueueError::InvalidTaskTarget(format!("Invalid task URL '{url}': {e}"))
+    })?;
+
+    match parsed_url.scheme() {
+        "https" | "http" => {}
+        scheme => {
+            return Err(TaskQueueError::InvalidTaskTarget(format!(
+                "Unsupported task URL scheme: {scheme}"
+            )));
         }
     }
 
-    fn is_host_allowed(host: &str, allowed_hosts: &str) -> bool {
-        allowed_hosts
-            .split(',')
-            .map(|entry| entry.trim().to_ascii_lowercase())
-            .filter(|entry| !entry.is_empty())
-            .any(|entry| host == entry || host.ends_with(&format!(".{entry}")))
+    let ip_address = match parsed_url.host() {
+        Some(Host::Ipv4(address)) => Some(IpAddr::V4(address)),
+        Some(Host::Ipv6(address)) => Some(IpAddr::V6(address)),
+        Some(Host::Domain(_)) => None,
+        None => {
+            return Err(TaskQueueError::InvalidTaskTarget("Task URL must include a host".to_string()));
+        }
+    };
+    let host = parsed_url.host_str().unwrap_or_default().to_ascii_lowercase();
+
+    if host == "localhost" || host.ends_with(".localhost") {
+        return Err(TaskQueueError::InvalidTaskTarget(format!(
+            "Task host '{host}' is not allowed"
+        )));
     }
 
-    pub fn validate_target_url(&self) -> Result<(), TaskQueueError> {
-        let parsed_url = Url::parse(&self.task).map_err(|e| {
-            TaskQueueError::InvalidTaskTarget(format!("Invalid task URL '{}': {e}", self.task))
-        })?;
-
-        match parsed_url.scheme() {
-            "https" => {}
-            "http" if !Self::env_flag("THERMITE_REQUIRE_HTTPS") => {}
-            "http" => {
-                return Err(TaskQueueError::InvalidTaskTarget(
-                    "Only HTTPS task URLs are allowed when THERMITE_REQUIRE_HTTPS is enabled".to_string(),
-                ));
-            }
-            scheme => {
-                return Err(TaskQueueError::InvalidTaskTarget(format!(
-                    "Unsupported task URL scheme: {scheme}"
-                )));
-            }
-        }
+    if ip_address.is_some_and(is_blocked_ip) {
+        return Err(TaskQueueError::InvalidTaskTarget(format!(
+            "Task host '{host}' resolves to a blocked IP range"
+        )));
+    }
 
-        let host = parsed_url
-            .host_str()
-            .ok_or_else(|| TaskQueueError::InvalidTaskTarget("Task URL must include a host".to_string()))?
-            .to_ascii_lowercase();
+    Ok(())
+}
 
-        if host == "localhost" || host.ends_with(".localhost") {
-            return Err(TaskQueueError::InvalidTaskTarget(format!(
-                "Task host '{host}' is not allowed"
-            )));
+// The first occurrence of `schedule` on the wall clock of `tz` strictly after `after`.
+fn next_occurrence(schedule: &Schedule, tz: &Tz, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    // Candidates are local wall-clock times, carried as naive datetimes in `Utc`.
+    let local_after = Utc.from_utc_datetime(&after.with_timezone(tz).naive_local());
+    schedule
+        .after(&local_after)
+        .filter_map(|candidate| local_to_utc(tz, candidate.naive_utc()))
+        .find(|occurrence| *occurrence > after)
+}
+
+// Maps a local wall-clock time in `tz` to the instant it denotes. Ambiguous times resolve to their
+// first occurrence; times inside a forward gap are shifted forward by the length of the gap.
+fn local_to_utc(tz: &Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(datetime) => Some(datetime.with_timezone(&Utc)),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest.with_timezone(&Utc)),
+        LocalResult::None => {
+            // Reading the time with the offset from before the jump lands it past the gap.
+            let offset_before_gap = tz.offset_from_local_datetime(&(local - Duration::hours(3))).earliest()?;
+            let utc = local - Duration::seconds(offset_before_gap.fix().local_minus_utc().into());
+            Some(Utc.from_utc_datetime(&utc))
         }
+    }
+}
 
-        if let Ok(ip_address) = host.parse::<IpAddr>() {
-            if Self::is_disallowed_ip_address(ip_address) {
-                return Err(TaskQueueError::InvalidTaskTarget(format!(
-                    "Task host '{host}' resolves to a blocked IP range"
-                )));
+impl BaseTask {
+    /// Checks the task's target URL with [`validate_target`]. Hostnames are checked against their
+    /// resolved addresses again at delivery time by the delivery client (see `ssrf`).
+    pub fn validate_target_url(&self) -> Result<(), TaskQueueError> {
+        validate_target(&self.task)
+    }
+
+    pub fn validate_delivery_options(&self) -> Result<(), TaskQueueError> {
+        self.headers.validate()?;
+
+        match self.timeout_secs {
+            Some(timeout_secs) if timeout_secs == 0 || timeout_secs > MAX_TIMEOUT_SECS => {
+                Err(TaskQueueError::InvalidDeliveryOptions(format!(
+                    "timeout_secs must be between 1 and {MAX_TIMEOUT_SECS}"
+                )))
             }
+            _ => Ok(()),
         }
+    }
 
-        if let Ok(allowed_hosts) = std::env::var("THERMITE_ALLOWED_HOSTS") {
-            if !allowed_hosts.trim().is_empty() && !Self::is_host_allowed(&host, &allowed_hosts) {
-                return Err(TaskQueueError::InvalidTaskTarget(format!(
-                    "Task host '{host}' is not in THERMITE_ALLOWED_HOSTS"
-                )));
-            }
+    /// Checks that an occurrence's id is `{series_id}:{scheduled_at}`.
+    ///
+    /// Ids without a `series_id` are not checked here: tasks stored before `:` was reserved for
+    /// occurrences may contain it, and must stay readable, updatable and replayable. New ids are
+    /// checked by [`BaseTask::validate_submission`].
+    pub fn validate_id(&self) -> Result<(), TaskQueueError> {
+        let is_occurrence_of = |series_id: &str| {
+            self.id
+                .strip_prefix(series_id)
+                .and_then(|rest| rest.strip_prefix(':'))
+                .is_some_and(|at| !at.is_empty() && at.bytes().all(|b| b.is_ascii_digit()))
+        };
+        match self.series_id.as_deref() {
+            Some(series_id) if !is_occurrence_of(series_id) => Err(TaskQueueError::InvalidTaskId(format!(
+                "'{}' is not an occurrence of '{series_id}'",
+                self.id
+            ))),
+            _ => Ok(()),
         }
+    }
 
-        Ok(())
+    /// Checks what only Thermite may set on a task: submitted tasks cannot claim to be occurrences,
+    /// and their ids cannot contain `:`, which is reserved for occurrence ids so a new task never
+    /// collides with an occurrence of another task.
+    pub fn validate_submission(&self) -> Result<(), TaskQueueError> {
+        match &self.series_id {
+            Some(_) => Err(TaskQueueError::InvalidTaskId(format!(
+                "'{}' sets series_id, which Thermite sets on occurrences of periodic tasks",
+                self.id
+            ))),
+            None if self.id.contains(':') => Err(TaskQueueError::InvalidTaskId(format!(
+                "'{}' contains ':', which is reserved for occurrences of periodic tasks",
+                self.id
+            ))),
+            None => Ok(()),
+        }
     }
 
     pub fn validate(&self) -> Result<(), TaskQueueError> {
+        self.validate_id()?;
         self.validate_target_url()?;
+        self.validate_delivery_options()?;
 
+        self.tz()?;
+        if self.max_catch_up == 0 || self.max_catch_up > MAX_CATCH_UP_LIMIT {
+            return Err(TaskQueueError::InvalidSchedule(format!(
+                "max_catch_up must be between 1 and {MAX_CATCH_UP_LIMIT}"
+            )));
+        }
         if self.category == "periodic" {
             let _ = self.get_next_unix_datetime()?;
         }
@@ -231,10 +671,37 @@ impl BaseTask {
         Ok(())
     }
 
-    pub fn schedule_retry(&mut self, error_message: &str) -> bool {
+    /// Returns a copy that is safe to hand out over the API, with sensitive header values masked.
+    pub fn redacted(&self) -> BaseTask {
+        BaseTask {
+            headers: self.headers.redacted(),
+            ..self.clone()
+        }
+    }
+
+    /// Builds the occurrence record for this periodic task's run at its current `scheduled_at`.
+    ///
+    /// The occurrence is a one-off task with id `{series_id}:{scheduled_at}` that is delivered,
+    /// retried and dead-lettered on its own, so a failing run never holds up or ends the series.
+    pub fn occurrence(&self) -> BaseTask {
+        BaseTask {
+            id: format!("{}:{}", self.id, self.scheduled_at),
+            category: "non_periodic".to_string(),
+            retry_count: 0,
+            is_retry: false,
+            last_error: None,
+            series_id: Some(self.id.clone()),
+            lease: None,
+            ..self.clone()
+        }
+    }
+
+    /// Records the failure and, unless the task is out of retries, schedules its next attempt
+    /// with exponential backoff from `retry`. Returns whether the task will be retried.
+    pub fn schedule_retry(&mut self, error_message: &str, retry: &RetryPolicy) -> bool {
         self.last_error = Some(error_message.to_string());
 
-        if self.retry_count >= self.max_retries {
+        if self.retry_count >= self.max_retries.unwrap_or(retry.max_retries) {
             return false;
         }
 
@@ -244,7 +711,7 @@ impl BaseTask {
         let retry_multiplier = 1_u64
             .checked_shl(self.retry_count.saturating_sub(1))
             .unwrap_or(u64::MAX);
-        let retry_delay = default_retry_base_delay_secs().saturating_mul(retry_multiplier);
+        let retry_delay = retry.base_delay_secs.saturating_mul(retry_multiplier);
         self.scheduled_at = (Utc::now().timestamp().max(0) as u64).saturating_add(retry_delay);
 
         true
@@ -262,22 +729,31 @@ impl BaseTask {
     /// # Examples
     ///
     /// ```
-    /// use thermite::task::BaseTask;
+    /// use thermite::task::{BaseTask, DeliveryHeaders, DeliveryMethod, MisfirePolicy, TaskPriority};
     ///
     /// let task = BaseTask {
     ///   id: "1".to_string(),
     ///   name: "Task 1".to_string(),
     ///   description: "Task 1 description".to_string(),
     ///   category: "periodic".to_string(),
-    ///   priority: "high".to_string(),
+    ///   priority: TaskPriority::High,
     ///   task: "http://localhost:8080/task".to_string(),
     ///   scheduled_at: 1628764800,
     ///   cron_scheduled_at: "0 0 * * *".to_string(),
+    ///   timezone: None,
     ///   args: None,
-    ///   max_retries: 3,
+    ///   misfire_policy: MisfirePolicy::RunOnce,
+    ///   max_catch_up: 10,
+    ///   method: DeliveryMethod::Post,
+    ///   headers: DeliveryHeaders::default(),
+    ///   timeout_secs: None,
+    ///   max_retries: Some(3),
     ///   retry_count: 0,
     ///   last_error: None,
     ///   is_retry: false,
+    ///   missed_runs: 0,
+    ///   series_id: None,
+    ///   lease: None,
     /// };
     ///
     /// let next_datetime = task.get_next_unix_datetime().unwrap();
@@ -289,6 +765,39 @@ impl BaseTask {
         if self.category != "periodic" {
             return Ok(self.scheduled_at as i64);
         }
+
+        self.next_run_after(Utc::now())
+    }
+
+    /// Returns the task's timezone, UTC when none is set.
+    pub fn tz(&self) -> Result<Tz, TaskQueueError> {
+        match self.timezone.as_deref().map(str::trim) {
+            None | Some("") => Ok(Tz::UTC),
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|_| TaskQueueError::InvalidTimezone(name.to_string())),
+        }
+    }
+
+    /// Returns the first occurrence of the cron schedule strictly after `after`, as a Unix timestamp.
+    ///
+    /// The schedule is evaluated on the wall clock of the task's `timezone`. Across DST changes a
+    /// local time that occurs twice runs once, at its first occurrence, and a local time skipped by
+    /// the clock moving forward runs as far after the change as it was into the gap (02:30 on a
+    /// spring-forward night at 02:00 runs at 03:30).
+    pub fn next_run_after(&self, after: DateTime<Utc>) -> Result<i64, TaskQueueError> {
+        let next_occurrence = self
+            .occurrences_after(after)?
+            .next()
+            .ok_or_else(|| TaskQueueError::InvalidCronExpression("No upcoming dates found".to_string()))?;
+
+        debug!(next_occurrence = %next_occurrence, timezone = ?self.timezone, "computed next periodic execution time");
+
+        Ok(next_occurrence.timestamp())
+    }
+
+    // The cron occurrences strictly after `after`, in order.
+    fn occurrences_after(&self, after: DateTime<Utc>) -> Result<impl Iterator<Item = DateTime<Utc>>, TaskQueueError> {
         let cron_expression = self.cron_scheduled_at.trim();
         let cron_schedule = if cron_expression.split_whitespace().count() == 5 {
             format!("0 {}", cron_expression)
@@ -300,17 +809,63 @@ impl BaseTask {
 
         let schedule = Schedule::from_str(&cron_schedule)
             .map_err(|e| TaskQueueError::InvalidCronExpression(e.to_string()))?;
+        let tz = self.tz()?;
 
-        let next_occurrence = schedule
-            .upcoming(Utc)
-            .next()
-            .ok_or_else(|| TaskQueueError::InvalidCronExpression("No upcoming dates found".to_string()))?;
+        let first = next_occurrence(&schedule, &tz, after);
+        Ok(std::iter::successors(first, move |previous| next_occurrence(&schedule, &tz, *previous)))
+    }
 
-        debug!(next_occurrence = %next_occurrence, "computed next periodic execution time");
+    /// Counts the cron occurrences after this run's `scheduled_at` that had already passed at `now`,
+    /// i.e. how many later runs were missed while this one waited. Always 0 for non-periodic tasks.
+    pub fn count_missed_runs(&self, now: DateTime<Utc>) -> Result<u32, TaskQueueError> {
+        if self.category != "periodic" {
+            return Ok(0);
+        }
 
-        Ok(next_occurrence.timestamp())
+        let missed = self
+            .occurrences_after(self.scheduled_at_datetime())?
+            .take_while(|occurrence| *occurrence <= now)
+            .take(MAX_COUNTED_MISSED_RUNS)
+            .count();
+        Ok(missed as u32)
     }
 
+    /// Moves a periodic task on to the run after the one that just settled, following its
+    /// `misfire_policy`: `run_all` continues with the oldest missed occurrence still within
+    /// `max_catch_up`, the other policies with the first occurrence after `now`. Retries do not keep
+    /// the time of the original occurrence, so a retried run always continues from `now`.
+    pub fn advance_schedule(&mut self, now: DateTime<Utc>) -> Result<(), TaskQueueError> {
+        if self.category != "periodic" {
+            return Ok(());
+        }
+
+        if self.misfire_policy == MisfirePolicy::RunAll && !self.is_retry {
+            let keep = self.max_catch_up.max(1) as usize;
+            let mut pending = VecDeque::with_capacity(keep);
+            for occurrence in self
+                .occurrences_after(self.scheduled_at_datetime())?
+                .take_while(|occurrence| *occurrence <= now)
+                .take(MAX_COUNTED_MISSED_RUNS)
+            {
+                if pending.len() == keep {
+                    pending.pop_front();
+                }
+                pending.push_back(occurrence);
+            }
+
+            if let Some(oldest_pending) = pending.front() {
+                self.scheduled_at = oldest_pending.timestamp() as u64;
+                return Ok(());
+            }
+        }
+
+        self.scheduled_at = self.next_run_after(now)? as u64;
+        Ok(())
+    }
+
+    fn scheduled_at_datetime(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.scheduled_at.min(i64::MAX as u64) as i64, 0).unwrap_or_default()
+    }
 
     /// Set the next scheduled Unix datetime based on the task's cron schedule.
     /// If the task's category is not "periodic", the method sets the scheduled_at value to the current value.
@@ -320,22 +875,31 @@ impl BaseTask {
     /// # Examples
     ///
     /// ```
-    /// use thermite::task::BaseTask;
+    /// use thermite::task::{BaseTask, DeliveryHeaders, DeliveryMethod, MisfirePolicy, TaskPriority};
     ///
     /// let mut task = BaseTask {
     ///     id: "1".to_string(),
     ///     name: "Task 1".to_string(),
     ///     description: "Task 1 description".to_string(),
     ///     category: "periodic".to_string(),
-    ///     priority: "high".to_string(),
+    ///     priority: TaskPriority::High,
     ///     task: "http://localhost:8080/task".to_string(),
     ///     scheduled_at: 1628764800,
     ///     cron_scheduled_at: "* 0 0 * * *".to_string(),
+    ///     timezone: None,
     ///     args: None,
-    ///     max_retries: 3,
+    ///     misfire_policy: MisfirePolicy::RunOnce,
+    ///     max_catch_up: 10,
+    ///     method: DeliveryMethod::Post,
+    ///     headers: DeliveryHeaders::default(),
+    ///     timeout_secs: None,
+    ///     max_retries: Some(3),
     ///     retry_count: 0,
     ///     last_error: None,
     ///     is_retry: false,
+    ///     missed_runs: 0,
+    ///     series_id: None,
+    ///     lease: None,
     /// };
     ///
     /// task.set_next_unix_datetime().unwrap();
@@ -1,21 +1,80 @@
-use reqwest::{Client, Error, Response};
+use chrono::Utc;
+use reqwest::{Client, Error, Method, Response};
 use serde_json::json;
 use std::sync::Arc;
-use tracing::{error, info};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
 
-use crate::task::BaseTask;
+use crate::config::{RetryPolicy, TargetPolicy};
+use crate::metrics;
+use crate::queue::{self, TaskRun};
+use crate::shutdown::Shutdown;
+use crate::signing::{WebhookSigner, SIGNATURE_HEADER};
+use crate::store::TaskStore;
+use crate::task::{validate_target, BaseTask, DeliveryMethod};
 
-pub async fn execute_task(client: Arc<Client>, task: BaseTask) -> Result<Response, Error> {
+/// How the worker pool treats tasks: which targets it may contact and how failures are retried.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeliveryPolicy {
+    pub retry: RetryPolicy,
+    pub targets: TargetPolicy,
+}
+
+fn http_method(method: DeliveryMethod) -> Method {
+    match method {
+        DeliveryMethod::Get => Method::GET,
+        DeliveryMethod::Post => Method::POST,
+        DeliveryMethod::Put => Method::PUT,
+        DeliveryMethod::Patch => Method::PATCH,
+        DeliveryMethod::Delete => Method::DELETE,
+    }
+}
+
+pub async fn execute_task(
+    client: Arc<Client>,
+    signer: Option<Arc<WebhookSigner>>,
+    task: BaseTask,
+) -> Result<Response, Error> {
     let task_id = task.id.clone();
     let task_name = task.name.clone();
 
-    info!(task_id = %task_id, task_name = %task_name, "executing task");
+    info!(task_id = %task_id, task_name = %task_name, method = %task.method, "executing task");
 
-    let response = client
-        .post(&task.task)
-        .json(&json!({ "task_id": task.id, "args": task.args }))
-        .send()
-        .await?;
+    // The body is serialized up front so the signature covers exactly the bytes that are sent.
+    // GET deliveries carry no body; their signature covers the empty body. Occurrences of a
+    // periodic task report the periodic task's id as `task_id` and their own as `occurrence_id`.
+    let body = match task.method {
+        DeliveryMethod::Get => String::new(),
+        _ => {
+            let mut payload = json!({
+                "task_id": task.series_id.as_deref().unwrap_or(&task.id),
+                "args": task.args,
+                "missed_runs": task.missed_runs
+            });
+            if task.series_id.is_some() {
+                payload["occurrence_id"] = json!(task.id);
+            }
+            payload.to_string()
+        }
+    };
+
+    let mut request = client.request(http_method(task.method), &task.task);
+    for (name, value) in task.headers.iter() {
+        request = request.header(name, value);
+    }
+    if task.method != DeliveryMethod::Get && task.headers.get("content-type").is_none() {
+        request = request.header(reqwest::header::CONTENT_TYPE, "application/json");
+    }
+    if let Some(timeout_secs) = task.timeout_secs {
+        request = request.timeout(Duration::from_secs(timeout_secs));
+    }
+    if let Some(signer) = signer {
+        request = request.header(SIGNATURE_HEADER, signer.sign(Utc::now().timestamp(), body.as_bytes()));
+    }
+
+    let response = request.body(body).send().await?;
 
     if response.status().is_success() {
         info!(task_id = %task_id, status = response.status().as_u16(), "task execution completed successfully");
@@ -25,3 +84,166 @@ pub async fn execute_task(client: Arc<Client>, task: BaseTask) -> Result<Respons
         response.error_for_status()
     }
 }
+
+// Dead-letters a claimed task whose target is invalid (e.g. a blocked literal IP) or not allowed by
+// the operator's `targets` policy, without contacting it. Tasks can reach the queue without passing
+// these checks, e.g. when enqueued through the library or replayed after the policy changed, so
+// they run once more before delivery, as they do on submission. Retrying would not make the target
+// allowed, so the task is not retried.
+async fn reject_disallowed_target(store: &dyn TaskStore, policy: &DeliveryPolicy, task: &BaseTask) -> bool {
+    let Err(error) = validate_target(&task.task).and_then(|()| policy.targets.check(&task.task)) else {
+        return false;
+    };
+    warn!(task_id = %task.id, error = %error, "task target is not allowed");
+    metrics::record_delivery(task, Duration::ZERO, false);
+
+    let now = Utc::now().timestamp().max(0) as u64;
+    let run = TaskRun {
+        attempt: task.retry_count + 1,
+        started_at: now,
+        finished_at: now,
+        latency_ms: 0,
+        status: None,
+        error: Some(error.to_string()),
+        occurrence_id: None,
+    };
+    if let Err(queue_error) = queue::record_task_run(store, task, &run).await {
+        error!(task_id = %task.id, error = %queue_error, "failed to record task run");
+    }
+    if let Err(queue_error) = queue::dead_letter_task(store, task, &error.to_string()).await {
+        error!(task_id = %task.id, error = %queue_error, "failed to persist dead-letter state");
+    }
+    true
+}
+
+/// Delivers a claimed task, records the attempt in its run history, then acknowledges it or records
+/// the failure for retry/dead-lettering under `policy`. A task whose target `policy.targets` does not
+/// allow is dead-lettered without being delivered.
+pub async fn deliver_task(
+    store: &dyn TaskStore,
+    client: Arc<Client>,
+    signer: Option<Arc<WebhookSigner>>,
+    policy: &DeliveryPolicy,
+    task: BaseTask,
+) {
+    if reject_disallowed_target(store, policy, &task).await {
+        return;
+    }
+    let leased_task = task.clone();
+    let started_at = Utc::now().timestamp();
+    let started = Instant::now();
+
+    let result = execute_task(client, signer, task).await;
+    metrics::record_delivery(&leased_task, started.elapsed(), result.is_ok());
+
+    let run = TaskRun {
+        attempt: leased_task.retry_count + 1,
+        started_at: started_at.max(0) as u64,
+        finished_at: Utc::now().timestamp().max(0) as u64,
+        latency_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+        status: match &result {
+            Ok(response) => Some(response.status().as_u16()),
+            Err(error) => error.status().map(|status| status.as_u16()),
+        },
+        error: result.as_ref().err().map(|error| error.to_string()),
+        occurrence_id: None,
+    };
+    if let Err(queue_error) = queue::record_task_run(store, &leased_task, &run).await {
+        error!(task_id = %leased_task.id, error = %queue_error, "failed to record task run");
+    }
+
+    match result {
+        Ok(_) => {
+            info!(task_id = %leased_task.id, "task executed successfully");
+            if let Err(queue_error) = queue::ack_task(store, &leased_task).await {
+                error!(
+                    task_id = %leased_task.id,
+                    error = %queue_error,
+                    "failed to acknowledge delivered task"
+                );
+            }
+        }
+        Err(error) => {
+            error!(task_id = %leased_task.id, error = %error, "task execution failed");
+            if let Err(queue_error) =
+                queue::handle_task_failure(store, &leased_task, &error.to_string(), &policy.retry).await
+            {
+                error!(
+                    task_id = %leased_task.id,
+                    error = %queue_error,
+                    "failed to persist retry or dead-letter state"
+                );
+            }
+        }
+    }
+}
+
+/// Spawns the worker pool that delivers tasks received from the dispatcher.
+///
+/// At most `concurrency` deliveries run at once. A new task is only taken off the channel once a
+/// delivery slot is free, so a saturated pool fills the channel and the dispatcher stops claiming
+/// tasks until a slot frees up. When `signer` is set every delivery carries a
+/// `Thermite-Signature` header. Targets are checked against `policy.targets` before delivery, and
+/// failed deliveries are retried under `policy.retry`.
+///
+/// Once `shutdown` is triggered the pool takes no new tasks: tasks still buffered in the channel
+/// are released back to their queue, and the returned handle completes when the deliveries already
+/// running have finished.
+pub fn spawn_task_processor(
+    store: Arc<dyn TaskStore>,
+    http_client: Client,
+    signer: Option<WebhookSigner>,
+    policy: DeliveryPolicy,
+    mut rx: mpsc::Receiver<BaseTask>,
+    concurrency: usize,
+    mut shutdown: Shutdown,
+) -> JoinHandle<()> {
+    let http_client = Arc::new(http_client);
+    let signer = signer.map(Arc::new);
+    let concurrency = concurrency.max(1);
+    let slots = Arc::new(Semaphore::new(concurrency));
+
+    tokio::spawn(async move {
+        loop {
+            let permit = tokio::select! {
+                biased;
+                _ = shutdown.triggered() => break,
+                permit = Arc::clone(&slots).acquire_owned() => match permit {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                },
+            };
+            let task = tokio::select! {
+                biased;
+                _ = shutdown.triggered() => break,
+                task = rx.recv() => match task {
+                    Some(task) => task,
+                    None => break,
+                },
+            };
+
+            let client = Arc::clone(&http_client);
+            let signer = signer.clone();
+            let store = Arc::clone(&store);
+            let policy = policy.clone();
+            tokio::spawn(async move {
+                deliver_task(store.as_ref(), client, signer, &policy, task).await;
+                drop(permit);
+            });
+        }
+
+        rx.close();
+        while let Some(task) = rx.recv().await {
+            match queue::release_task(store.as_ref(), &task).await {
+                Ok(()) => info!(task_id = %task.id, "released undelivered task back to its queue"),
+                Err(e) => warn!(task_id = %task.id, error = %e, "failed to release undelivered task; it is retried once its lease expires"),
+            }
+        }
+
+        let running = concurrency - slots.available_permits();
+        if running > 0 {
+            info!(running, "waiting for running deliveries to finish");
+        }
+        let _ = slots.acquire_many(concurrency as u32).await;
+    })
+}
@@ -0,0 +1,170 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use thermite::config::{Config, ConfigLayer, FetchCursor, Modes};
+    use thermite::errors::TaskQueueError;
+    use thermite::queue::QueueOrder;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> =
+            vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn file_env_and_cli_apply_in_order_over_the_defaults() {
+        let file = ConfigLayer::from_toml(
+            r#"
+            store_url = "postgres://db/thermite"
+            bind_address = "0.0.0.0:9000"
+            workers = 8
+            queue_order = "lifo"
+
+            [retry]
+            max_retries = 5
+            base_delay_secs = 10
+
+            [targets]
+            require_https = true
+            allowed_hosts = ["jobs.example.com"]
+            "#,
+        )
+        .unwrap();
+        let env = ConfigLayer::from_env(env(&[
+            ("THERMITE_WORKERS", "16"),
+            ("THERMITE_RETRY_BASE_DELAY_SECS", "2"),
+            ("THERMITE_API_KEY", "secret"),
+        ]))
+        .unwrap();
+        let cli = ConfigLayer { bind_address: Some("127.0.0.1:7000".to_string()), ..ConfigLayer::default() };
+
+        let config = Config::from_layer(file.merge(env).merge(cli)).unwrap();
+
+        assert_eq!(config.store_url, "postgres://db/thermite");
+        assert_eq!(config.bind_address, "127.0.0.1:7000");
+        assert_eq!(config.workers, 16);
+        assert_eq!(config.queue_order, QueueOrder::Lifo);
+        assert_eq!(config.retry.max_retries, 5);
+        assert_eq!(config.retry.base_delay_secs, 2);
+        assert_eq!(config.api_key.as_deref(), Some("secret"));
+        assert!(config.targets.require_https);
+        assert_eq!(config.targets.allowed_hosts, vec!["jobs.example.com".to_string()]);
+        assert_eq!(config.shutdown_timeout_secs, Config::default().shutdown_timeout_secs);
+    }
+
+    #[test]
+    fn the_store_url_falls_back_to_redis_url() {
+        let layer = ConfigLayer::from_env(env(&[("REDIS_URL", "redis://cache:6379")])).unwrap();
+        assert_eq!(Config::from_layer(layer).unwrap().store_url, "redis://cache:6379");
+
+        let layer = ConfigLayer::from_env(env(&[
+            ("REDIS_URL", "redis://cache:6379"),
+            ("THERMITE_STORE_URL", "memory://"),
+        ]))
+        .unwrap();
+        assert_eq!(Config::from_layer(layer).unwrap().store_url, "memory://");
+    }
+
+    #[test]
+    fn invalid_settings_are_rejected_with_the_offending_key() {
+        let invalid = |result: Result<Config, TaskQueueError>| match result {
+            Err(TaskQueueError::InvalidConfiguration(message)) => message,
+            other => panic!("expected an invalid configuration, got {other:?}"),
+        };
+
+        let error = ConfigLayer::from_env(env(&[("THERMITE_WORKERS", "many")])).unwrap_err();
+        assert!(error.to_string().contains("THERMITE_WORKERS"));
+        let error = ConfigLayer::from_env(env(&[("THERMITE_REQUIRE_HTTPS", "maybe")])).unwrap_err();
+        assert!(error.to_string().contains("THERMITE_REQUIRE_HTTPS"));
+        assert!(ConfigLayer::from_toml("wrokers = 4").is_err());
+
+        let zero_workers = ConfigLayer { workers: Some(0), ..ConfigLayer::default() };
+        assert!(invalid(Config::from_layer(zero_workers)).contains("workers"));
+        let error = ConfigLayer::from_env(env(&[("THERMITE_QUEUE_ORDER", "random")])).unwrap_err();
+        assert!(error.to_string().contains("THERMITE_QUEUE_ORDER"));
+        let error = ConfigLayer::from_toml("queue_order = \"random\"").unwrap_err();
+        assert!(error.to_string().contains("queue order"));
+        let bad_fetch_url = ConfigLayer { fetch_url: Some("not a url".to_string()), ..ConfigLayer::default() };
+        assert!(invalid(Config::from_layer(bad_fetch_url)).contains("fetch_url"));
+        let empty_secrets = ConfigLayer { signing_secrets: Some(vec![" ".to_string()]), ..ConfigLayer::default() };
+        assert!(invalid(Config::from_layer(empty_secrets)).contains("signing secret"));
+        let short_lease = ConfigLayer { visibility_timeout_secs: Some(10), ..ConfigLayer::default() };
+        assert!(invalid(Config::from_layer(short_lease)).contains("visibility_timeout_secs"));
+
+        for name in ["THERMITE_SIGNING_SECRETS", "THERMITE_ALLOWED_HOSTS", "THERMITE_FETCH_HEADERS"] {
+            let error = ConfigLayer::from_env(env(&[(name, "first,,second")])).unwrap_err();
+            assert!(error.to_string().contains(name), "{error}");
+            assert!(error.to_string().contains("entry 2 is empty"), "{error}");
+        }
+        let hosts =
+            ConfigLayer::from_env(env(&[("THERMITE_ALLOWED_HOSTS", "jobs.example.com, https://example.org")])).unwrap();
+        assert!(invalid(Config::from_layer(hosts)).contains("'https://example.org' is not a host name"));
+    }
+
+    #[test]
+    fn modes_combine_and_receiver_means_api_and_worker() {
+        let modes = |value: &str| value.parse::<Modes>().unwrap();
+
+        assert_eq!(modes("receiver"), Modes { api: true, worker: true, fetcher: false });
+        assert_eq!(modes("api, fetcher"), Modes { api: true, worker: false, fetcher: true });
+        assert_eq!(modes("all"), Modes { api: true, worker: true, fetcher: true });
+        assert_eq!(modes("worker").to_string(), "worker");
+        assert_eq!(Config::default().mode, modes("api,worker"));
+        assert!("api,scheduler".parse::<Modes>().is_err());
+
+        let fetcher_without_source = ConfigLayer::from_toml(r#"mode = "worker,fetcher""#).unwrap();
+        assert!(Config::from_layer(fetcher_without_source).is_err());
+        let fetcher = ConfigLayer::from_env(env(&[
+            ("THERMITE_MODE", "fetcher"),
+            ("FETCH_URL", "https://source.example.com/tasks"),
+        ]))
+        .unwrap();
+        assert_eq!(Config::from_layer(fetcher).unwrap().mode, modes("fetcher"));
+    }
+
+    #[test]
+    fn fetcher_settings_are_read_and_checked() {
+        let layer = ConfigLayer::from_env(env(&[
+            ("THERMITE_FETCH_INTERVAL_SECS", "30"),
+            ("THERMITE_FETCH_TOKEN", "source-token"),
+            ("THERMITE_FETCH_HEADERS", "X-Source-Key: abc123, X-Tenant: acme"),
+        ]))
+        .unwrap();
+        let fetcher = Config::from_layer(layer).unwrap().fetcher;
+
+        assert_eq!(fetcher.interval_secs, 30);
+        assert_eq!(fetcher.bearer_token.as_deref(), Some("source-token"));
+        assert_eq!(fetcher.headers.get("X-Tenant").map(String::as_str), Some("acme"));
+
+        assert!(ConfigLayer::from_env(env(&[("THERMITE_FETCH_HEADERS", "no separator")])).is_err());
+        let error = ConfigLayer::from_env(env(&[("THERMITE_FETCH_HEADERS", "Accept: a, b")])).unwrap_err();
+        assert!(error.to_string().contains("separated by ','"));
+        let toml_header = ConfigLayer::from_toml("[fetcher.headers]\nAccept = \"a, b\"").unwrap();
+        assert_eq!(Config::from_layer(toml_header).unwrap().fetcher.headers["Accept"], "a, b");
+        let backoff_below_interval = ConfigLayer::from_toml("[fetcher]\ninterval_secs = 60\nmax_backoff_secs = 30").unwrap();
+        assert!(Config::from_layer(backoff_below_interval).is_err());
+        let bad_header = ConfigLayer::from_toml("[fetcher.headers]\n\"Bad Header\" = \"x\"").unwrap();
+        assert!(Config::from_layer(bad_header).is_err());
+    }
+
+    #[test]
+    fn fetch_cursor_and_ack_url_are_optional_and_checked() {
+        assert_eq!(Config::default().fetcher.cursor, FetchCursor::None);
+        assert_eq!(Config::default().fetcher.ack_url, None);
+
+        let layer = ConfigLayer::from_toml("[fetcher]\ncursor = \"since\"").unwrap();
+        let env = ConfigLayer::from_env(env(&[
+            ("THERMITE_FETCH_CURSOR", "ETag"),
+            ("THERMITE_FETCH_ACK_URL", "https://source.example.com/tasks/ack"),
+        ]))
+        .unwrap();
+        let fetcher = Config::from_layer(layer.merge(env)).unwrap().fetcher;
+        assert_eq!(fetcher.cursor, FetchCursor::Etag);
+        assert_eq!(fetcher.ack_url.as_deref(), Some("https://source.example.com/tasks/ack"));
+
+        assert!(ConfigLayer::from_toml("[fetcher]\ncursor = \"offset\"").is_err());
+        let bad_ack_url = ConfigLayer::from_toml("[fetcher]\nack_url = \"ftp://source.example.com/ack\"").unwrap();
+        assert!(Config::from_layer(bad_ack_url).is_err());
+    }
+}
@@ -0,0 +1,267 @@
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use thermite::config::{Config, FetchCursor, FetcherConfig};
+    use thermite::errors::TaskQueueError;
+    use thermite::fetcher::{poll_delay, FetchReport, Fetcher};
+    use thermite::health::Health;
+    use thermite::queue;
+    use thermite::shutdown;
+    use thermite::store::{MemoryStore, RedisStore};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{headers}Connection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    // A task source that answers every request with what `respond` returns for it, and records the
+    // requests it received, lowercased.
+    async fn start_responder<F>(respond: F) -> (SocketAddr, Arc<Mutex<Vec<String>>>)
+    where
+        F: Fn(&str) -> String + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let recorder = Arc::clone(&requests);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = Vec::new();
+                let mut buffer = [0_u8; 4096];
+                loop {
+                    let text = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.strip_prefix("content-length:"))
+                            .and_then(|length| length.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        if body.len() >= length {
+                            break;
+                        }
+                    }
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+                let _ = stream.write_all(respond(&request).as_bytes()).await;
+                recorder.lock().unwrap().push(request);
+            }
+        });
+
+        (address, requests)
+    }
+
+    async fn start_source(status: &'static str, body: String) -> (SocketAddr, Arc<Mutex<Vec<String>>>) {
+        start_responder(move |_| response(status, "", &body)).await
+    }
+
+    fn config_for(address: SocketAddr) -> Config {
+        Config {
+            fetch_url: Some(format!("http://{address}/tasks")),
+            fetcher: FetcherConfig {
+                bearer_token: Some("source-token".to_string()),
+                headers: [("X-Source-Key".to_string(), "abc123".to_string())].into_iter().collect(),
+                ..FetcherConfig::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    fn fetcher_for(address: SocketAddr, store: Arc<MemoryStore>) -> Fetcher {
+        Fetcher::new(store, &config_for(address), Arc::default()).unwrap()
+    }
+
+    fn request_body(request: &str) -> serde_json::Value {
+        serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap()
+    }
+
+    fn task(id: &str, target: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "name": "Fetched Task",
+            "description": "desc",
+            "category": "non_periodic",
+            "task": target,
+            "scheduled_at": 1893456000_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        })
+    }
+
+    #[tokio::test]
+    async fn fetch_enqueues_valid_tasks_and_reports_rejected_ones_by_id() {
+        let mut bad_priority = task("bad-priority", "https://example.com/hooks/run");
+        bad_priority["priority"] = serde_json::json!("urgent");
+        let body = serde_json::json!([
+            task("good", "https://example.com/hooks/run"),
+            task("internal", "http://127.0.0.1/admin"),
+            bad_priority,
+            {"name": "no id"},
+        ]);
+        let (address, requests) = start_source("200 OK", body.to_string()).await;
+        let store = Arc::new(MemoryStore::new());
+
+        let report = fetcher_for(address, Arc::clone(&store)).fetch_once().await.unwrap();
+
+        assert_eq!(report.accepted, vec!["good".to_string()]);
+        let rejected: Vec<Option<&str>> = report.rejected.iter().map(|rejected| rejected.id.as_deref()).collect();
+        assert_eq!(rejected, vec![Some("internal"), Some("bad-priority"), None]);
+        assert!(queue::get_task(store.as_ref(), "good").await.unwrap().is_some());
+        assert!(queue::get_task(store.as_ref(), "internal").await.unwrap().is_none());
+
+        let head = requests.lock().unwrap()[0].clone();
+        assert!(head.contains("authorization: bearer source-token"));
+        assert!(head.contains("x-source-key: abc123"));
+    }
+
+    #[tokio::test]
+    async fn non_success_statuses_and_malformed_bodies_fail_the_fetch() {
+        let store = Arc::new(MemoryStore::new());
+
+        let (address, _) = start_source("503 Service Unavailable", r#"{"error":"maintenance"}"#.to_string()).await;
+        let error = fetcher_for(address, Arc::clone(&store)).fetch_once().await.unwrap_err();
+        assert!(matches!(error, TaskQueueError::FetchFailed(_)));
+        assert!(error.to_string().contains("503"));
+        assert!(error.to_string().contains("maintenance"));
+
+        let (address, _) = start_source("200 OK", r#"{"tasks": []}"#.to_string()).await;
+        let error = fetcher_for(address, Arc::clone(&store)).fetch_once().await.unwrap_err();
+        assert!(matches!(error, TaskQueueError::FetchFailed(_)));
+    }
+
+    #[tokio::test]
+    async fn etag_cursor_is_sent_back_and_not_modified_is_an_empty_fetch() {
+        let body = serde_json::json!([task("first", "https://example.com/hooks/run")]).to_string();
+        let (address, requests) = start_responder(move |request| {
+            if request.contains("if-none-match: \"v1\"") {
+                response("304 Not Modified", "", "")
+            } else {
+                response("200 OK", "ETag: \"v1\"\r\n", &body)
+            }
+        })
+        .await;
+        let mut config = config_for(address);
+        config.fetcher.cursor = FetchCursor::Etag;
+        let mut fetcher = Fetcher::new(Arc::new(MemoryStore::new()), &config, Arc::default()).unwrap();
+
+        assert_eq!(fetcher.fetch_once().await.unwrap().accepted, vec!["first".to_string()]);
+        assert_eq!(fetcher.fetch_once().await.unwrap(), FetchReport::default());
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"v1\""));
+    }
+
+    #[tokio::test]
+    async fn since_cursor_is_the_one_the_source_handed_out() {
+        let (address, requests) = start_responder(|request| {
+            if request.starts_with("get /tasks?since=41 ") {
+                response("200 OK", "", "[]")
+            } else {
+                response("200 OK", "Thermite-Cursor: 41\r\n", "[]")
+            }
+        })
+        .await;
+        let mut config = config_for(address);
+        config.fetcher.cursor = FetchCursor::Since;
+        let mut fetcher = Fetcher::new(Arc::new(MemoryStore::new()), &config, Arc::default()).unwrap();
+
+        fetcher.fetch_once().await.unwrap();
+        fetcher.fetch_once().await.unwrap();
+        fetcher.fetch_once().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("get /tasks http/1.1"));
+        assert!(requests[1].starts_with("get /tasks?since=41 "));
+        assert!(requests[2].starts_with("get /tasks?since=41 "), "a poll without a cursor must keep the last one");
+    }
+
+    #[tokio::test]
+    async fn accepted_and_rejected_ids_are_acknowledged_before_the_cursor_moves() {
+        let body = serde_json::json!([
+            task("good", "https://example.com/hooks/run"),
+            task("internal", "http://127.0.0.1/admin"),
+            {"name": "no id"},
+        ])
+        .to_string();
+        let acks_failing = Arc::new(Mutex::new(true));
+        let failing = Arc::clone(&acks_failing);
+        let (address, requests) = start_responder(move |request| {
+            if !request.starts_with("post /acks") {
+                response("200 OK", "ETag: \"v1\"\r\n", &body)
+            } else if *failing.lock().unwrap() {
+                response("500 Internal Server Error", "", "")
+            } else {
+                response("204 No Content", "", "")
+            }
+        })
+        .await;
+        let mut config = config_for(address);
+        config.fetcher.cursor = FetchCursor::Etag;
+        config.fetcher.ack_url = Some(format!("http://{address}/acks"));
+        let store = Arc::new(MemoryStore::new());
+        let mut fetcher = Fetcher::new(store.clone(), &config, Arc::default()).unwrap();
+
+        let error = fetcher.fetch_once().await.unwrap_err();
+        assert!(matches!(error, TaskQueueError::FetchFailed(_)));
+        assert!(queue::get_task(store.as_ref(), "good").await.unwrap().is_some());
+
+        *acks_failing.lock().unwrap() = false;
+        let report = fetcher.fetch_once().await.unwrap();
+        assert_eq!(report.accepted, vec!["good".to_string()]);
+        fetcher.fetch_once().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(!requests[2].contains("if-none-match"), "a failed acknowledgement must not move the cursor");
+        assert!(requests[4].contains("if-none-match: \"v1\""));
+        let ack = request_body(&requests[3]);
+        assert!(requests[3].contains("authorization: bearer source-token"));
+        assert_eq!(ack["accepted"], serde_json::json!(["good"]));
+        assert_eq!(ack["rejected"].as_array().unwrap().len(), 1);
+        assert_eq!(ack["rejected"][0]["id"], "internal");
+    }
+
+    #[tokio::test]
+    async fn a_fetch_the_store_cannot_take_is_not_a_success() {
+        let body = serde_json::json!([task("good", "https://example.com/hooks/run")]);
+        let (address, _) = start_source("200 OK", body.to_string()).await;
+        // Nothing listens on port 1, so every enqueue fails.
+        let store = Arc::new(RedisStore::new(redis::Client::open("redis://127.0.0.1:1").unwrap()));
+        let health = Arc::new(Health::default());
+
+        let mut once = Fetcher::new(store.clone(), &config_for(address), Arc::default()).unwrap();
+        let report = once.fetch_once().await.unwrap();
+        let (trigger, shutdown) = shutdown::channel();
+        let fetcher = Fetcher::new(store, &config_for(address), Arc::clone(&health)).unwrap();
+        let running = tokio::spawn(fetcher.run(shutdown));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        trigger.trigger();
+        running.await.unwrap();
+
+        assert_eq!(report.failed, vec!["good".to_string()]);
+        assert!(!report.is_complete());
+        let readiness = health.readiness(chrono::Utc::now().timestamp(), Ok(Duration::ZERO));
+        assert!(readiness.fetcher.details["last_success"].is_null());
+    }
+
+    #[test]
+    fn failed_fetches_back_off_exponentially_up_to_the_limit() {
+        let settings = FetcherConfig { interval_secs: 10, max_backoff_secs: 60, ..FetcherConfig::default() };
+
+        let delays: Vec<u64> = (0..5).map(|failures| poll_delay(&settings, failures).as_secs()).collect();
+
+        assert_eq!(delays, vec![10, 20, 40, 60, 60]);
+        assert_eq!(poll_delay(&settings, 200), Duration::from_secs(60));
+    }
+}
@@ -1,10 +1,22 @@
 #[cfg(test)]
 mod tests {
     use actix_web::{http::StatusCode, test as actix_test, web, App};
-    use std::sync::Mutex;
-    use thermite::handlers::{dead_letter_tasks, health_check, submit_task, AppState};
+    use std::sync::{Arc, Mutex};
+    use thermite::config::{Config, TargetPolicy};
+    use thermite::handlers::{
+        cancel_task, dead_letter_tasks, health_check, json_config, list_tasks, purge_dead_letter_tasks, query_config,
+        submit_task, AppState,
+    };
+    use thermite::store::MemoryStore;
     use thermite::task::BaseTask;
 
+    fn with_api_key(api_key: &str) -> Arc<Config> {
+        Arc::new(Config {
+            api_key: Some(api_key.to_string()),
+            ..Config::default()
+        })
+    }
+
     #[actix_web::test]
     async fn health_check_returns_ok() {
         let app = actix_test::init_service(
@@ -21,12 +33,10 @@ mod tests {
     #[actix_web::test]
     #[serial_test::serial]
     async fn submit_task_requires_api_key_when_configured() {
-        std::env::set_var("THERMITE_API_KEY", "test-secret");
-
-        let redis_client = redis::Client::open("redis://127.0.0.1/").unwrap();
+        let store = Arc::new(MemoryStore::new());
         let app = actix_test::init_service(
             App::new()
-                .app_data(web::Data::new(Mutex::new(AppState { redis_client })))
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: with_api_key("test-secret") })))
                 .route("/submit-task", web::post().to(submit_task)),
         )
         .await;
@@ -49,19 +59,16 @@ mod tests {
             .to_request();
         let resp = actix_test::call_service(&app, req).await;
 
-        std::env::remove_var("THERMITE_API_KEY");
         assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
     }
 
     #[actix_web::test]
     #[serial_test::serial]
     async fn dead_letter_endpoint_requires_api_key_when_configured() {
-        std::env::set_var("THERMITE_API_KEY", "test-secret");
-
-        let redis_client = redis::Client::open("redis://127.0.0.1/").unwrap();
+        let store = Arc::new(MemoryStore::new());
         let app = actix_test::init_service(
             App::new()
-                .app_data(web::Data::new(Mutex::new(AppState { redis_client })))
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: with_api_key("test-secret") })))
                 .route("/dead-letter-tasks", web::get().to(dead_letter_tasks)),
         )
         .await;
@@ -71,19 +78,35 @@ mod tests {
             .to_request();
         let resp = actix_test::call_service(&app, req).await;
 
-        std::env::remove_var("THERMITE_API_KEY");
         assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
     }
 
     #[actix_web::test]
     #[serial_test::serial]
-    async fn submit_task_rejects_localhost_target() {
-        std::env::set_var("THERMITE_API_KEY", "test-secret");
+    async fn dead_letter_purge_requires_api_key_when_configured() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: with_api_key("test-secret") })))
+                .route("/dead-letter-tasks", web::delete().to(purge_dead_letter_tasks)),
+        )
+        .await;
 
-        let redis_client = redis::Client::open("redis://127.0.0.1/").unwrap();
+        let req = actix_test::TestRequest::delete()
+            .uri("/dead-letter-tasks")
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn submit_task_rejects_localhost_target() {
+        let store = Arc::new(MemoryStore::new());
         let app = actix_test::init_service(
             App::new()
-                .app_data(web::Data::new(Mutex::new(AppState { redis_client })))
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: with_api_key("test-secret") })))
                 .route("/submit-task", web::post().to(submit_task)),
         )
         .await;
@@ -107,30 +130,231 @@ mod tests {
             .to_request();
         let resp = actix_test::call_service(&app, req).await;
 
-        std::env::remove_var("THERMITE_API_KEY");
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn submit_task_rejects_unknown_priority() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: Arc::default() })))
+                .app_data(json_config())
+                .route("/submit-task", web::post().to(submit_task)),
+        )
+        .await;
+
+        let payload = serde_json::json!({
+            "id": "task-priority",
+            "name": "Urgent Task",
+            "description": "desc",
+            "category": "non_periodic",
+            "priority": "urgent",
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": 1893456000_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        });
+
+        let req = actix_test::TestRequest::post()
+            .uri("/submit-task")
+            .set_json(&payload)
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn submit_task_rejects_oversized_idempotency_key() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: Arc::default() })))
+                .route("/submit-task", web::post().to(submit_task)),
+        )
+        .await;
+
+        let payload = serde_json::json!({
+            "id": "task-idempotent",
+            "name": "Test Task",
+            "description": "desc",
+            "category": "non_periodic",
+            "priority": "high",
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": 1893456000_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        });
+
+        let req = actix_test::TestRequest::post()
+            .uri("/submit-task")
+            .insert_header(("Idempotency-Key", "k".repeat(256)))
+            .set_json(&payload)
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn submit_task_rejects_unknown_duplicate_policy() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: Arc::default() })))
+                .app_data(query_config())
+                .route("/submit-task", web::post().to(submit_task)),
+        )
+        .await;
+
+        let payload = serde_json::json!({
+            "id": "task-policy",
+            "name": "Test Task",
+            "description": "desc",
+            "category": "non_periodic",
+            "priority": "high",
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": 1893456000_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        });
+
+        let req = actix_test::TestRequest::post()
+            .uri("/submit-task?on_duplicate=overwrite")
+            .set_json(&payload)
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn cancel_task_requires_api_key_when_configured() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: with_api_key("test-secret") })))
+                .route("/tasks/{id}", web::delete().to(cancel_task)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::delete()
+            .uri("/tasks/task-1")
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn submit_task_rejects_forbidden_delivery_header() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: Arc::default() })))
+                .app_data(json_config())
+                .route("/submit-task", web::post().to(submit_task)),
+        )
+        .await;
+
+        let payload = serde_json::json!({
+            "id": "task-host-header",
+            "name": "Spoofed Host",
+            "description": "desc",
+            "category": "non_periodic",
+            "task": "https://example.com/hooks/run",
+            "method": "PUT",
+            "headers": {"Host": "internal.example.com"},
+            "scheduled_at": 1893456000_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        });
+
+        let req = actix_test::TestRequest::post()
+            .uri("/submit-task")
+            .set_json(&payload)
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    #[serial_test::serial]
+    async fn list_tasks_rejects_unknown_priority_filter() {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: Arc::default() })))
+                .app_data(query_config())
+                .route("/tasks", web::get().to(list_tasks)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get()
+            .uri("/tasks?priority=urgent")
+            .to_request();
+        let resp = actix_test::call_service(&app, req).await;
+
         assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
 
     #[test]
     #[serial_test::serial]
     fn allowlist_enforces_configured_hosts() {
-        std::env::set_var("THERMITE_ALLOWED_HOSTS", "jobs.example.com");
-
-        let allowed_task = BaseTask {
-            task: "https://jobs.example.com/hooks/run".to_string(),
-            ..Default::default()
+        let targets = TargetPolicy {
+            allowed_hosts: vec!["jobs.example.com".to_string()],
+            ..TargetPolicy::default()
         };
-        let blocked_task = BaseTask {
-            task: "https://evil.example.net/hooks/run".to_string(),
-            ..Default::default()
+
+        assert!(targets.check("https://jobs.example.com/hooks/run").is_ok());
+        assert!(targets.check("https://eu.jobs.example.com/hooks/run").is_ok());
+        assert!(targets.check("https://evil.example.net/hooks/run").is_err());
+    }
+
+    #[actix_web::test]
+    async fn submit_task_enforces_configured_target_policy() {
+        let config = Config {
+            targets: TargetPolicy { require_https: true, allowed_hosts: Vec::new() },
+            ..Config::default()
         };
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState {
+                    store,
+                    health: Arc::default(),
+                    config: Arc::new(config),
+                })))
+                .route("/submit-task", web::post().to(submit_task)),
+        )
+        .await;
+
+        let mut payload = serde_json::json!({
+            "id": "plain-http",
+            "name": "Plain HTTP Task",
+            "description": "desc",
+            "category": "non_periodic",
+            "task": "http://example.com/hooks/run",
+            "scheduled_at": 1893456000_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        });
+        let req = actix_test::TestRequest::post().uri("/submit-task").set_json(&payload).to_request();
+        let rejected = actix_test::call_service(&app, req).await;
 
-        let allowed_result = allowed_task.validate_target_url();
-        let blocked_result = blocked_task.validate_target_url();
-        std::env::remove_var("THERMITE_ALLOWED_HOSTS");
+        payload["task"] = serde_json::json!("https://example.com/hooks/run");
+        let req = actix_test::TestRequest::post().uri("/submit-task").set_json(&payload).to_request();
+        let accepted = actix_test::call_service(&app, req).await;
 
-        assert!(allowed_result.is_ok());
-        assert!(blocked_result.is_err());
+        assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(accepted.status(), StatusCode::OK);
     }
 
     #[test]
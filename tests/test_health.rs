@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test as actix_test, web, App};
+    use chrono::Utc;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use thermite::errors::TaskQueueError;
+    use thermite::handlers::{readiness_check, AppState};
+    use thermite::health::{ComponentStatus, Health};
+    use thermite::store::MemoryStore;
+
+    async fn readyz(health: Arc<Health>) -> (StatusCode, serde_json::Value) {
+        let store = Arc::new(MemoryStore::new());
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health, config: Arc::default() })))
+                .route("/readyz", web::get().to(readiness_check)),
+        )
+        .await;
+
+        let req = actix_test::TestRequest::get().uri("/readyz").to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        let status = resp.status();
+        (status, actix_test::read_body_json(resp).await)
+    }
+
+    #[actix_web::test]
+    async fn readyz_reports_components_of_a_healthy_process() {
+        let health = Arc::new(Health::default());
+        health.dispatcher_heartbeat(Utc::now().timestamp(), 1, 4);
+
+        let (status, body) = readyz(health).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["store"]["status"], "ok");
+        assert_eq!(body["dispatcher"]["status"], "ok");
+        assert_eq!(body["fetcher"]["status"], "disabled");
+        assert_eq!(body["workers"]["channel_occupied"], 1);
+    }
+
+    #[actix_web::test]
+    async fn readyz_is_unavailable_when_the_dispatcher_stops_beating() {
+        let health = Arc::new(Health::default());
+        health.dispatcher_heartbeat(Utc::now().timestamp() - 120, 0, 4);
+
+        let (status, body) = readyz(health).await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["dispatcher"]["status"], "down");
+    }
+
+    #[test]
+    fn a_busy_worker_pool_stays_ready_until_it_stops_taking_tasks() {
+        let health = Health::default();
+        health.dispatcher_heartbeat(1_000, 4, 4);
+        health.task_dispatched(990);
+
+        let busy = health.readiness(1_000, Ok(Duration::from_millis(5)));
+        let stalled = health.readiness(1_400, Ok(Duration::from_millis(5)));
+
+        assert!(busy.ready);
+        assert_eq!(busy.workers.status, ComponentStatus::Ok);
+        assert_eq!(busy.workers.details["saturated"], true);
+        assert_eq!(stalled.workers.status, ComponentStatus::Degraded);
+    }
+
+    #[test]
+    fn stalled_workers_stale_fetches_and_store_errors_degrade_readiness() {
+        let health = Health::default();
+        health.dispatcher_heartbeat(1_000, 4, 4);
+        health.task_dispatched(600);
+        health.fetcher_started(800, 10);
+        health.fetcher_succeeded(900);
+
+        let slow = health.readiness(1_000, Ok(Duration::from_secs(3)));
+        let unreachable = health.readiness(1_000, Err(TaskQueueError::ConnectionError("refused".to_string())));
+
+        assert!(!slow.ready);
+        assert_eq!(slow.store.status, ComponentStatus::Degraded);
+        assert_eq!(slow.workers.status, ComponentStatus::Degraded);
+        assert_eq!(slow.fetcher.status, ComponentStatus::Degraded);
+        assert_eq!(unreachable.store.status, ComponentStatus::Down);
+    }
+}
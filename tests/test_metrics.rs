@@ -0,0 +1,57 @@
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test as actix_test, web, App};
+    use chrono::Utc;
+    use std::sync::{Arc, Mutex};
+    use thermite::config::RetryPolicy;
+    use thermite::handlers::{metrics_report, AppState};
+    use thermite::queue::{self, QueueOrder};
+    use thermite::store::MemoryStore;
+    use thermite::task::BaseTask;
+
+    fn task_at(id: &str, scheduled_at: i64) -> BaseTask {
+        BaseTask {
+            id: id.to_string(),
+            name: format!("Task {id}"),
+            category: "reports".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            scheduled_at: scheduled_at as u64,
+            max_retries: Some(0),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn metrics_report_queue_depth_and_task_counters() {
+        let store = Arc::new(MemoryStore::new());
+        let now = Utc::now().timestamp();
+        for task in [task_at("due", now - 60), task_at("later", now + 3600), task_at("doomed", now - 120)] {
+            queue::enqueue_task(store.as_ref(), &task).await.unwrap();
+        }
+        let claimed = queue::dequeue_task(store.as_ref(), QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::handle_task_failure(store.as_ref(), &claimed, "500 Internal Server Error", &RetryPolicy::default())
+            .await
+            .unwrap();
+
+        let app = actix_test::init_service(
+            App::new()
+                .app_data(web::Data::new(Mutex::new(AppState { store, health: Arc::default(), config: Arc::default() })))
+                .route("/metrics", web::get().to(metrics_report)),
+        )
+        .await;
+        let req = actix_test::TestRequest::get().uri("/metrics").to_request();
+        let resp = actix_test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = String::from_utf8(actix_test::read_body(resp).await.to_vec()).unwrap();
+
+        assert_eq!(claimed.id, "doomed");
+        assert!(body.contains("thermite_queue_depth{state=\"due\"} 1"));
+        assert!(body.contains("thermite_queue_depth{state=\"scheduled\"} 1"));
+        assert!(body.contains("thermite_dead_letter_tasks 1"));
+        assert!(body.contains("thermite_tasks_enqueued_total{category=\"other\",priority=\"normal\"} 3"));
+        assert!(body.contains("thermite_tasks_dequeued_total{category=\"other\",priority=\"normal\"} 1"));
+        assert!(body.contains("thermite_tasks_dead_lettered_total{category=\"other\",priority=\"normal\"} 1"));
+        assert!(body.contains("thermite_task_scheduling_lag_seconds_count{category=\"other\",priority=\"normal\"} 1"));
+        assert!(!body.contains("reports"));
+    }
+}
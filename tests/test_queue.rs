@@ -0,0 +1,814 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use thermite::config::RetryPolicy;
+    use thermite::errors::TaskQueueError;
+    use thermite::queue::{
+        self, DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, OccurrenceState, QueueOrder,
+        TaskFilter, TaskState,
+    };
+    use thermite::store::RedisStore;
+    use thermite::task::{BaseTask, MisfirePolicy, TaskPriority, TaskUpdate};
+
+    // These tests need a live Redis. They use a dedicated database so they never touch
+    // a developer's real queue, and skip themselves when no server is reachable, unless
+    // `CI` or `THERMITE_REQUIRE_REDIS` is set, where a missing Redis fails them instead.
+    async fn test_redis_client() -> Option<redis::Client> {
+        let redis_url = std::env::var("THERMITE_TEST_REDIS_URL")
+            .unwrap_or_else(|_| "redis://127.0.0.1:6379/15".to_string());
+        let required = std::env::var_os("CI").is_some() || std::env::var_os("THERMITE_REQUIRE_REDIS").is_some();
+        let connected = match redis::Client::open(redis_url.as_str()) {
+            Ok(client) => client.get_multiplexed_async_connection().await.map(|_| client),
+            Err(e) => Err(e),
+        };
+
+        match connected {
+            Ok(client) => Some(client),
+            Err(e) if required => panic!("Redis at {redis_url} is required but unavailable: {e}"),
+            Err(e) => {
+                eprintln!("skipping Redis-backed test, Redis unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    async fn test_redis_store() -> Option<RedisStore> {
+        test_redis_client().await.map(RedisStore::new)
+    }
+
+    fn due_task(id: &str) -> BaseTask {
+        BaseTask {
+            id: id.to_string(),
+            name: format!("Task {id}"),
+            category: "non_periodic".to_string(),
+            priority: TaskPriority::Normal,
+            task: "https://example.com/hooks/run".to_string(),
+            scheduled_at: (Utc::now().timestamp() - 60) as u64,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[serial_test::serial]
+    async fn concurrent_claimers_receive_each_task_exactly_once() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let task_count = 200;
+        for index in 0..task_count {
+            queue::enqueue_task(&store, &due_task(&format!("claim-{index}")))
+                .await
+                .unwrap();
+        }
+
+        let mut claimers = Vec::new();
+        for _ in 0..16 {
+            let store = store.clone();
+            claimers.push(tokio::spawn(async move {
+                let mut claimed = Vec::new();
+                while let Some(task) = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap() {
+                    claimed.push(task.id);
+                }
+                claimed
+            }));
+        }
+
+        let mut all_claimed = Vec::new();
+        for claimer in claimers {
+            all_claimed.extend(claimer.await.unwrap());
+        }
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let unique: HashSet<_> = all_claimed.iter().cloned().collect();
+        assert_eq!(all_claimed.len(), task_count);
+        assert_eq!(unique.len(), task_count);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn future_tasks_are_not_claimed() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let mut task = due_task("future-task");
+        task.scheduled_at = (Utc::now().timestamp() + 3600) as u64;
+        queue::enqueue_task(&store, &task).await.unwrap();
+
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert!(claimed.is_none());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn expired_lease_returns_task_to_queue() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        queue::enqueue_task(&store, &due_task("leased-task")).await.unwrap();
+        let claimed = queue::dequeue_task_with_lease(&store, QueueOrder::Fifo, Duration::ZERO).await.unwrap();
+        let requeued = queue::requeue_expired_leases(&store).await.unwrap();
+        let reclaimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(claimed.map(|task| task.id).as_deref(), Some("leased-task"));
+        assert_eq!(requeued, 1);
+        assert_eq!(reclaimed.map(|task| task.id).as_deref(), Some("leased-task"));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn acked_task_is_not_requeued() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        queue::enqueue_task(&store, &due_task("acked-task")).await.unwrap();
+        let claimed = queue::dequeue_task_with_lease(&store, QueueOrder::Fifo, Duration::ZERO).await.unwrap().unwrap();
+        queue::ack_task(&store, &claimed).await.unwrap();
+        let requeued = queue::requeue_expired_leases(&store).await.unwrap();
+
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(requeued, 0);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn stale_ack_after_reclaim_is_a_no_op() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        queue::enqueue_task(&store, &due_task("contested-task")).await.unwrap();
+        let stale = queue::dequeue_task_with_lease(&store, QueueOrder::Fifo, Duration::ZERO).await.unwrap().unwrap();
+        queue::requeue_expired_leases(&store).await.unwrap();
+        let current = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::ack_task(&store, &stale).await.unwrap();
+        let state_after_stale = queue::get_task(&store, "contested-task").await.unwrap().map(|(_, state)| state);
+        queue::ack_task(&store, &current).await.unwrap();
+        let state_after_ack = queue::get_task(&store, "contested-task").await.unwrap();
+
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(state_after_stale, Some(TaskState::InFlight));
+        assert!(state_after_ack.is_none());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn fifo_claims_oldest_due_task_first() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let mut older = due_task("older-task");
+        older.scheduled_at -= 600;
+        let newer = due_task("newer-task");
+        queue::enqueue_task(&store, &newer).await.unwrap();
+        queue::enqueue_task(&store, &older).await.unwrap();
+
+        let first = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(first.map(|task| task.id).as_deref(), Some("older-task"));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn lifo_claims_newest_due_task_first() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let mut older = due_task("older-task");
+        older.scheduled_at -= 600;
+        let newer = due_task("newer-task");
+        queue::enqueue_task(&store, &older).await.unwrap();
+        queue::enqueue_task(&store, &newer).await.unwrap();
+
+        let first = queue::dequeue_task(&store, QueueOrder::Lifo).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(first.map(|task| task.id).as_deref(), Some("newer-task"));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn higher_priority_due_tasks_are_claimed_first() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        // The low priority task has been due the longest, but priority wins over due time.
+        let mut low = due_task("low-task");
+        low.priority = TaskPriority::Low;
+        low.scheduled_at -= 600;
+        let normal = due_task("normal-task");
+        let mut high = due_task("high-task");
+        high.priority = TaskPriority::High;
+        queue::enqueue_task(&store, &low).await.unwrap();
+        queue::enqueue_task(&store, &normal).await.unwrap();
+        queue::enqueue_task(&store, &high).await.unwrap();
+
+        let mut claimed = Vec::new();
+        while let Some(task) = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap() {
+            claimed.push(task.id);
+        }
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(claimed, vec!["high-task", "normal-task", "low-task"]);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn tasks_can_be_looked_up_listed_and_cancelled_by_id() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let mut reminder = due_task("reminder");
+        reminder.category = "non_periodic".to_string();
+        reminder.scheduled_at = (Utc::now().timestamp() + 3600) as u64;
+        let mut report = due_task("report");
+        report.priority = TaskPriority::High;
+        report.scheduled_at = (Utc::now().timestamp() + 7200) as u64;
+        queue::enqueue_task(&store, &reminder).await.unwrap();
+        queue::enqueue_task(&store, &report).await.unwrap();
+
+        let (found, state) = queue::get_task(&store, "reminder").await.unwrap().unwrap();
+        let (all, total) = queue::list_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        let high_only = TaskFilter {
+            priority: Some(TaskPriority::High),
+            ..Default::default()
+        };
+        let (high, high_total) = queue::list_tasks(&store, &high_only, 0, 10).await.unwrap();
+        let (second_page, _) = queue::list_tasks(&store, &TaskFilter::default(), 1, 1).await.unwrap();
+
+        let cancelled = queue::cancel_task(&store, "reminder").await.unwrap();
+        let cancelled_again = queue::cancel_task(&store, "reminder").await.unwrap();
+        let missing = queue::get_task(&store, "reminder").await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(found.id, "reminder");
+        assert_eq!(state, TaskState::Queued);
+        assert_eq!(total, 2);
+        assert_eq!(all.iter().map(|task| task.id.as_str()).collect::<Vec<_>>(), vec!["reminder", "report"]);
+        assert_eq!(high_total, 1);
+        assert_eq!(high[0].id, "report");
+        assert_eq!(second_page[0].id, "report");
+        assert!(cancelled);
+        assert!(!cancelled_again);
+        assert!(missing.is_none());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn pages_across_priority_queues_follow_the_schedule() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let base = Utc::now().timestamp() as u64 + 3600;
+        for index in 0..30_u64 {
+            let mut task = due_task(&format!("page-{index:02}"));
+            task.priority = [TaskPriority::High, TaskPriority::Normal, TaskPriority::Low][index as usize % 3];
+            task.scheduled_at = base + index;
+            queue::enqueue_task(&store, &task).await.unwrap();
+        }
+
+        let mut paged = Vec::new();
+        let mut totals = Vec::new();
+        for offset in (0..30).step_by(7) {
+            let (page, total) = queue::list_tasks(&store, &TaskFilter::default(), offset, 7).await.unwrap();
+            paged.extend(page.into_iter().map(|task| task.id));
+            totals.push(total);
+        }
+        let window = TaskFilter {
+            scheduled_after: Some(base + 10),
+            scheduled_before: Some(base + 19),
+            ..Default::default()
+        };
+        let (windowed, windowed_total) = queue::list_tasks(&store, &window, 2, 3).await.unwrap();
+        let by_category = TaskFilter { category: Some("non_periodic".to_string()), ..window.clone() };
+        let (categorized, categorized_total) = queue::list_tasks(&store, &by_category, 2, 3).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let expected: Vec<String> = (0..30).map(|index| format!("page-{index:02}")).collect();
+        assert_eq!(paged, expected);
+        assert!(totals.iter().all(|total| *total == 30));
+        assert_eq!(windowed_total, 10);
+        let ids = |tasks: &[BaseTask]| tasks.iter().map(|task| task.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(&windowed), vec!["page-12", "page-13", "page-14"]);
+        assert_eq!(categorized_total, windowed_total);
+        assert_eq!(ids(&categorized), ids(&windowed));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn category_listings_follow_tasks_between_queues() {
+        let Some(client) = test_redis_client().await else {
+            return;
+        };
+        let store = RedisStore::new(client.clone());
+        queue::clear_task_queue(&store).await.unwrap();
+        let by_category = |category: &str| TaskFilter { category: Some(category.to_string()), ..Default::default() };
+        let listed = |category: &'static str| {
+            let store = store.clone();
+            async move {
+                let (tasks, total) = queue::list_tasks(&store, &by_category(category), 0, 10).await.unwrap();
+                (tasks.into_iter().map(|task| task.id).collect::<Vec<_>>(), total)
+            }
+        };
+
+        let mut mail = due_task("cat-mail");
+        mail.category = "mail".to_string();
+        mail.scheduled_at -= 60;
+        let mut report = due_task("cat-report");
+        report.category = "reports".to_string();
+        queue::enqueue_task(&store, &mail).await.unwrap();
+        queue::enqueue_task(&store, &report).await.unwrap();
+        let before_replace = listed("reports").await;
+        report.category = "mail".to_string();
+        queue::enqueue_task_with_policy(&store, &report, DuplicatePolicy::Replace).await.unwrap();
+        let after_replace = (listed("reports").await, listed("mail").await);
+
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        let while_claimed = listed("mail").await;
+        queue::handle_task_failure(&store, &claimed, "503 Service Unavailable", &RetryPolicy::default())
+            .await
+            .unwrap();
+        let after_retry = listed("mail").await;
+        queue::cancel_task(&store, "cat-report").await.unwrap();
+        let after_cancel = listed("mail").await;
+
+        // Releases before the category queues did not fill them; the migration does.
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let _: () = redis::AsyncCommands::del(&mut conn, "task_category:normal:mail").await.unwrap();
+        let unindexed = listed("mail").await;
+        let migration = store.migrate_legacy_layout().await.unwrap();
+        let backfilled = listed("mail").await;
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(before_replace, (vec!["cat-report".to_string()], 1));
+        assert_eq!(after_replace.0, (vec![], 0));
+        assert_eq!(after_replace.1, (vec!["cat-mail".to_string(), "cat-report".to_string()], 2));
+        assert_eq!(claimed.id, "cat-mail");
+        assert_eq!(while_claimed, (vec!["cat-report".to_string()], 1));
+        assert_eq!(after_retry.1, 2);
+        assert_eq!(after_cancel, (vec!["cat-mail".to_string()], 1));
+        assert_eq!(unindexed, (vec![], 0));
+        assert_eq!(migration.categorized, 1);
+        assert_eq!(backfilled, (vec!["cat-mail".to_string()], 1));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn cancelled_in_flight_task_is_not_requeued_on_failure() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        queue::enqueue_task(&store, &due_task("doomed-task")).await.unwrap();
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        let (_, state) = queue::get_task(&store, "doomed-task").await.unwrap().unwrap();
+        queue::cancel_task(&store, "doomed-task").await.unwrap();
+        queue::handle_task_failure(&store, &claimed, "connection refused", &RetryPolicy::default()).await.unwrap();
+        let after_failure = queue::get_task(&store, "doomed-task").await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(state, TaskState::InFlight);
+        assert!(after_failure.is_none());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn duplicate_ids_follow_the_selected_policy() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let original = due_task("dedup-task");
+        let mut changed = original.clone();
+        changed.scheduled_at += 30;
+        changed.priority = TaskPriority::High;
+
+        let first = queue::enqueue_task_with_policy(&store, &original, DuplicatePolicy::Reject).await.unwrap();
+        let rejected = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Reject).await;
+        let kept = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::KeepExisting).await.unwrap();
+        let (after_keep, _) = queue::get_task(&store, "dedup-task").await.unwrap().unwrap();
+        let replaced = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Replace).await.unwrap();
+        let (after_replace, _) = queue::get_task(&store, "dedup-task").await.unwrap().unwrap();
+        let (listed, total) = queue::list_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(first, EnqueueOutcome::Enqueued);
+        assert!(matches!(rejected, Err(TaskQueueError::DuplicateTask(_))));
+        assert_eq!(kept, EnqueueOutcome::AlreadyExists);
+        assert_eq!(after_keep.scheduled_at, original.scheduled_at);
+        assert_eq!(replaced, EnqueueOutcome::Replaced);
+        assert_eq!(after_replace.scheduled_at, changed.scheduled_at);
+        assert_eq!(after_replace.priority, TaskPriority::High);
+        assert_eq!(total, 1);
+        assert_eq!(listed[0].priority, TaskPriority::High);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn in_flight_task_cannot_be_replaced() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        queue::enqueue_task(&store, &due_task("busy-task")).await.unwrap();
+        queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        let replaced = queue::enqueue_task_with_policy(&store, &due_task("busy-task"), DuplicatePolicy::Replace).await;
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert!(matches!(replaced, Err(TaskQueueError::TaskInFlight(_))));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn idempotency_keys_replay_completed_responses() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        let scope = "/submit-task";
+        queue::release_idempotent_request(&store, scope, "key-1").await.unwrap();
+
+        let first = queue::begin_idempotent_request(&store, scope, "key-1", "fingerprint-a").await.unwrap();
+        let concurrent = queue::begin_idempotent_request(&store, scope, "key-1", "fingerprint-a").await.unwrap();
+        let response = IdempotentResponse {
+            status: 200,
+            body: serde_json::json!({"status": "Task submitted"}),
+        };
+        queue::complete_idempotent_request(&store, scope, "key-1", "fingerprint-a", &response)
+            .await
+            .unwrap();
+        let repeated = queue::begin_idempotent_request(&store, scope, "key-1", "fingerprint-a").await.unwrap();
+        let mismatched = queue::begin_idempotent_request(&store, scope, "key-1", "fingerprint-b").await.unwrap();
+        queue::release_idempotent_request(&store, scope, "key-1").await.unwrap();
+
+        assert!(matches!(first, IdempotencyState::New));
+        assert!(matches!(concurrent, IdempotencyState::InProgress));
+        assert!(matches!(repeated, IdempotencyState::Completed(ref stored) if stored.status == 200));
+        assert!(matches!(mismatched, IdempotencyState::Mismatch));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn queued_task_can_be_rescheduled_and_reprioritized() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let mut reminder = due_task("reminder");
+        reminder.scheduled_at = (Utc::now().timestamp() + 3600) as u64;
+        queue::enqueue_task(&store, &reminder).await.unwrap();
+
+        // Pull the reminder forward so it is due now, and bump it to high priority.
+        let update = TaskUpdate {
+            scheduled_at: Some((Utc::now().timestamp() - 1) as u64),
+            priority: Some(TaskPriority::High),
+            ..Default::default()
+        };
+        let updated = queue::update_task(&store, "reminder", &update).await.unwrap().unwrap();
+        let missing = queue::update_task(&store, "no-such-task", &update).await.unwrap();
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        let while_in_flight = queue::update_task(&store, "reminder", &update).await;
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(updated.priority, TaskPriority::High);
+        assert!(missing.is_none());
+        assert_eq!(claimed.map(|task| task.priority), Some(TaskPriority::High));
+        assert!(matches!(while_in_flight, Err(TaskQueueError::TaskInFlight(_))));
+    }
+
+    async fn dead_letter(store: &RedisStore, task: BaseTask) {
+        let mut task = task;
+        task.max_retries = Some(0);
+        queue::enqueue_task(store, &task).await.unwrap();
+        let claimed = queue::dequeue_task(store, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::handle_task_failure(store, &claimed, "502 Bad Gateway", &RetryPolicy::default()).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn dead_lettered_tasks_can_be_paged_replayed_and_deleted() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+
+        dead_letter(&store, due_task("dead-1")).await;
+        dead_letter(&store, due_task("dead-2")).await;
+        let mut high = due_task("dead-3");
+        high.priority = TaskPriority::High;
+        dead_letter(&store, high).await;
+
+        let (first_page, total) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 2).await.unwrap();
+        let high_only = TaskFilter {
+            priority: Some(TaskPriority::High),
+            ..Default::default()
+        };
+        let (high_page, high_total) = queue::list_dead_letter_tasks(&store, &high_only, 0, 10).await.unwrap();
+
+        let replayed = queue::replay_dead_letter_task(&store, "dead-1").await.unwrap().unwrap();
+        let (requeued, _) = queue::get_task(&store, "dead-1").await.unwrap().unwrap();
+        let replayed_missing = queue::replay_dead_letter_task(&store, "dead-1").await.unwrap();
+        let deleted = queue::delete_dead_letter_task(&store, "dead-2").await.unwrap();
+        let bulk = queue::replay_dead_letter_tasks(&store, &high_only, 100).await.unwrap();
+        let purged = queue::purge_dead_letter_tasks(&store).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(total, 3);
+        assert_eq!(first_page.len(), 2);
+        assert_eq!(first_page[0].last_error.as_deref(), Some("502 Bad Gateway"));
+        assert_eq!(high_total, 1);
+        assert_eq!(high_page[0].id, "dead-3");
+        assert_eq!(replayed.retry_count, 0);
+        assert!(!replayed.is_retry);
+        assert!(requeued.last_error.is_none());
+        assert!(replayed_missing.is_none());
+        assert!(deleted);
+        assert_eq!(bulk.replayed, vec!["dead-3"]);
+        assert!(bulk.failed.is_empty());
+        assert_eq!(bulk.remaining, 0);
+        assert_eq!(purged, 0);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn filtered_dead_letter_listings_read_in_chunks_and_cap_the_total() {
+        let Some(client) = test_redis_client().await else {
+            return;
+        };
+        let store = RedisStore::new(client.clone());
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+
+        // Written straight into the dead-letter index: 2400 failures, every other one high priority.
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        let mut pipe = redis::pipe();
+        for index in 0..2400_u64 {
+            let mut task = due_task(&format!("dead-{index:04}"));
+            task.priority = if index % 2 == 0 { TaskPriority::High } else { TaskPriority::Normal };
+            pipe.zadd("dead_letter_index", &task.id, index)
+                .hset("dead_letter_tasks", &task.id, serde_json::to_string(&task).unwrap())
+                .ignore();
+        }
+        let _: () = pipe.query_async(&mut conn).await.unwrap();
+
+        let high_only = TaskFilter { priority: Some(TaskPriority::High), ..Default::default() };
+        let (first_page, capped_total) = queue::list_dead_letter_tasks(&store, &high_only, 0, 10).await.unwrap();
+        let (deep_page, deep_total) = queue::list_dead_letter_tasks(&store, &high_only, 600, 3).await.unwrap();
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+
+        let ids = |tasks: &[BaseTask]| tasks.iter().map(|task| task.id.clone()).collect::<Vec<_>>();
+        assert_eq!(first_page.len(), 10);
+        assert_eq!(first_page[9].id, "dead-0018");
+        assert_eq!(capped_total, 1010);
+        assert_eq!(ids(&deep_page), vec!["dead-1200", "dead-1202", "dead-1204"]);
+        assert_eq!(deep_total, 1200);
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn replay_is_refused_while_a_live_task_uses_the_id() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+
+        dead_letter(&store, due_task("reused-id")).await;
+        let mut live = due_task("reused-id");
+        live.scheduled_at = (Utc::now().timestamp() + 3600) as u64;
+        queue::enqueue_task(&store, &live).await.unwrap();
+
+        let replayed = queue::replay_dead_letter_task(&store, "reused-id").await;
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert!(matches!(replayed, Err(TaskQueueError::DuplicateTask(_))));
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn run_all_catches_up_on_missed_periodic_runs() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        // An every-minute task due 30 seconds before the minute five minutes ago: the six
+        // occurrences since, up to the current minute, were missed.
+        let mut task = due_task("catch-up");
+        task.category = "periodic".to_string();
+        task.cron_scheduled_at = "* * * * *".to_string();
+        task.misfire_policy = MisfirePolicy::RunAll;
+        task.scheduled_at = ((Utc::now().timestamp() / 60 - 5) * 60 - 30) as u64;
+        let stale_run = task.scheduled_at;
+        queue::enqueue_task(&store, &task).await.unwrap();
+
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::ack_task(&store, &claimed).await.unwrap();
+        let next = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::ack_task(&store, &next).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(claimed.missed_runs, 6);
+        assert_eq!(next.scheduled_at, stale_run + 30);
+        assert_eq!(next.missed_runs, 5);
+    }
+
+    fn hourly_series(id: &str) -> BaseTask {
+        let mut task = due_task(id);
+        task.category = "periodic".to_string();
+        task.cron_scheduled_at = "0 * * * *".to_string();
+        // Due, but not yet an hour late, so no run was missed.
+        task.scheduled_at = (Utc::now().timestamp() / 3600 * 3600) as u64;
+        task
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn failed_occurrence_does_not_end_its_series() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+
+        let mut series = hourly_series("hourly-report");
+        series.max_retries = Some(0);
+        queue::enqueue_task(&store, &series).await.unwrap();
+
+        let occurrence = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        let (requeued_series, series_state) = queue::get_task(&store, "hourly-report").await.unwrap().unwrap();
+        queue::handle_task_failure(&store, &occurrence, "502 Bad Gateway", &RetryPolicy::default()).await.unwrap();
+
+        let still_scheduled = queue::get_task(&store, "hourly-report").await.unwrap();
+        let (dead_letters, _) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        let history = queue::list_occurrences(&store, "hourly-report").await.unwrap().unwrap();
+        let replayed = queue::replay_dead_letter_task(&store, &occurrence.id).await;
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert_eq!(occurrence.id, format!("hourly-report:{}", series.scheduled_at));
+        assert_eq!(occurrence.series_id.as_deref(), Some("hourly-report"));
+        // The series was rescheduled from its own schedule when the occurrence was created.
+        assert_eq!(series_state, TaskState::Queued);
+        assert_eq!(requeued_series.scheduled_at, series.scheduled_at + 3600);
+        assert!(still_scheduled.is_some());
+        assert_eq!(dead_letters.iter().map(|task| task.id.clone()).collect::<Vec<_>>(), vec![occurrence.id.clone()]);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].state, OccurrenceState::DeadLettered);
+        assert!(replayed.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn skip_policy_reschedules_stale_runs_without_delivering() {
+        let Some(store) = test_redis_store().await else {
+            return;
+        };
+        queue::clear_task_queue(&store).await.unwrap();
+
+        let mut series = hourly_series("stale-report");
+        series.misfire_policy = MisfirePolicy::Skip;
+        series.scheduled_at -= 3 * 3600;
+        queue::enqueue_task(&store, &series).await.unwrap();
+
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        let (rescheduled, _) = queue::get_task(&store, "stale-report").await.unwrap().unwrap();
+        let history = queue::list_occurrences(&store, "stale-report").await.unwrap().unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+
+        assert!(claimed.is_none());
+        assert!(rescheduled.scheduled_at as i64 > Utc::now().timestamp());
+        assert!(history.is_empty());
+    }
+
+    // A task as the original layout stored it: the whole payload is the `task_queue` member, and
+    // `priority` is free text.
+    fn legacy_payload(id: &str, priority: &str, scheduled_at: i64) -> String {
+        serde_json::json!({
+            "id": id,
+            "name": format!("Task {id}"),
+            "description": "",
+            "category": "non_periodic",
+            "priority": priority,
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": scheduled_at,
+            "cron_scheduled_at": "",
+            "args": null,
+            "max_retries": 3,
+            "retry_count": 0,
+            "last_error": null,
+            "is_retry": false
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn legacy_layout_is_migrated_into_the_current_one() {
+        let Some(client) = test_redis_client().await else {
+            return;
+        };
+        let store = RedisStore::new(client.clone());
+        let mut conn = client.get_multiplexed_async_connection().await.unwrap();
+        queue::clear_task_queue(&store).await.unwrap();
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+        let _: () = redis::AsyncCommands::del(&mut conn, &["task_queue", "dead_letter_queue"]).await.unwrap();
+
+        let due_at = Utc::now().timestamp() - 60;
+        let mut existing = due_task("legacy-kept");
+        existing.name = "Current task".to_string();
+        queue::enqueue_task(&store, &existing).await.unwrap();
+        for (member, score) in [
+            (legacy_payload("legacy-urgent", "high", due_at), due_at),
+            (legacy_payload("legacy-plain", "whenever", due_at - 10), due_at - 10),
+            (legacy_payload("legacy-kept", "low", due_at), due_at),
+            ("not a task".to_string(), due_at),
+        ] {
+            let _: () = redis::AsyncCommands::zadd(&mut conn, "task_queue", member, score).await.unwrap();
+        }
+        // Dead letters were appended, so the later entry for an id is the newer failure.
+        for entry in [
+            legacy_payload("legacy-failed", "normal", due_at - 100),
+            legacy_payload("legacy-twice", "normal", due_at - 300),
+            legacy_payload("legacy-twice", "normal", due_at - 200),
+            "not a task either".to_string(),
+        ] {
+            let _: () = redis::AsyncCommands::rpush(&mut conn, "dead_letter_queue", entry).await.unwrap();
+        }
+
+        let migration = store.migrate_legacy_layout().await.unwrap();
+        let rerun = store.migrate_legacy_layout().await.unwrap();
+        let left: Vec<String> = redis::AsyncCommands::zrange(&mut conn, "task_queue", 0, -1).await.unwrap();
+        let dead_left: Vec<String> = redis::AsyncCommands::lrange(&mut conn, "dead_letter_queue", 0, -1).await.unwrap();
+        let (dead_letters, dead_total) =
+            queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        let (urgent, urgent_state) = queue::get_task(&store, "legacy-urgent").await.unwrap().unwrap();
+        let (plain, _) = queue::get_task(&store, "legacy-plain").await.unwrap().unwrap();
+        let (kept, _) = queue::get_task(&store, "legacy-kept").await.unwrap().unwrap();
+        let first_claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+
+        queue::clear_task_queue(&store).await.unwrap();
+        queue::purge_dead_letter_tasks(&store).await.unwrap();
+        let _: () = redis::AsyncCommands::del(&mut conn, &["task_queue", "dead_letter_queue"]).await.unwrap();
+
+        assert_eq!((migration.tasks, migration.dead_letters, migration.skipped), (3, 3, 2));
+        assert_eq!((rerun.tasks, rerun.dead_letters, rerun.skipped), (0, 0, 2));
+        assert_eq!(left, vec!["not a task".to_string()]);
+        assert_eq!(dead_left, vec!["not a task either".to_string()]);
+        assert_eq!(dead_total, 2);
+        let dead_letters: Vec<(String, u64)> =
+            dead_letters.into_iter().map(|task| (task.id, task.scheduled_at)).collect();
+        assert_eq!(
+            dead_letters,
+            vec![
+                ("legacy-twice".to_string(), (due_at - 200) as u64),
+                ("legacy-failed".to_string(), (due_at - 100) as u64),
+            ]
+        );
+        assert_eq!((urgent.priority, urgent_state), (TaskPriority::High, TaskState::Queued));
+        assert_eq!(urgent.max_retries, Some(3));
+        assert_eq!(plain.priority, TaskPriority::Normal);
+        assert_eq!(kept.name, "Current task");
+        assert_eq!(first_claimed.map(|task| task.id).as_deref(), Some("legacy-urgent"));
+    }
+
+    #[test]
+    fn queue_order_parses_known_policies() {
+        assert_eq!("fifo".parse::<QueueOrder>().unwrap(), QueueOrder::Fifo);
+        assert_eq!(" LIFO ".parse::<QueueOrder>().unwrap(), QueueOrder::Lifo);
+        assert_eq!(QueueOrder::default(), QueueOrder::Fifo);
+        assert!("random".parse::<QueueOrder>().is_err());
+    }
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests {
+    use thermite::errors::SignatureError;
+    use thermite::signing::{compute_signature, verify_signature, WebhookSigner};
+
+    const BODY: &[u8] = br#"{"args":null,"task_id":"nightly-report"}"#;
+    const SENT_AT: i64 = 1_700_000_000;
+
+    fn signer(secrets: &[&str]) -> WebhookSigner {
+        WebhookSigner::new(secrets.iter().map(|s| s.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn signature_roundtrips() {
+        let header = signer(&["whsec_primary"]).sign(SENT_AT, BODY);
+
+        assert_eq!(
+            header,
+            format!("t={SENT_AT},v1={}", compute_signature("whsec_primary", SENT_AT, BODY))
+        );
+        assert_eq!(verify_signature(&header, BODY, &["whsec_primary"], SENT_AT + 10, 300), Ok(()));
+    }
+
+    #[test]
+    fn rotation_signs_with_every_active_secret() {
+        let header = signer(&["whsec_old", "whsec_new"]).sign(SENT_AT, BODY);
+
+        assert_eq!(verify_signature(&header, BODY, &["whsec_old"], SENT_AT, 300), Ok(()));
+        assert_eq!(verify_signature(&header, BODY, &["whsec_new"], SENT_AT, 300), Ok(()));
+        assert_eq!(
+            verify_signature(&header, BODY, &["whsec_unrelated", "whsec_new"], SENT_AT, 300),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn tampered_body_or_wrong_secret_is_rejected() {
+        let header = signer(&["whsec_primary"]).sign(SENT_AT, BODY);
+
+        assert_eq!(
+            verify_signature(&header, br#"{"args":null,"task_id":"other"}"#, &["whsec_primary"], SENT_AT, 300),
+            Err(SignatureError::NoMatchingSignature)
+        );
+        assert_eq!(
+            verify_signature(&header, BODY, &["whsec_other"], SENT_AT, 300),
+            Err(SignatureError::NoMatchingSignature)
+        );
+    }
+
+    #[test]
+    fn stale_timestamp_is_rejected() {
+        let header = signer(&["whsec_primary"]).sign(SENT_AT, BODY);
+
+        assert_eq!(
+            verify_signature(&header, BODY, &["whsec_primary"], SENT_AT + 301, 300),
+            Err(SignatureError::TimestampOutOfTolerance)
+        );
+    }
+
+    #[test]
+    fn malformed_headers_are_rejected() {
+        for header in ["", "v1=abcd", "t=1700000000", "t=soon,v1=abcd", "t=1700000000,v1=not-hex", "garbage"] {
+            assert_eq!(
+                verify_signature(header, BODY, &["whsec_primary"], SENT_AT, 300),
+                Err(SignatureError::MalformedHeader),
+                "header {header:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_secret_lists_are_rejected() {
+        assert!(WebhookSigner::new(vec![]).is_err());
+        assert!(WebhookSigner::new(vec![" ".to_string(), String::new()]).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+#![cfg(feature = "sql")]
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+    use thermite::config::RetryPolicy;
+    use thermite::errors::TaskQueueError;
+    use thermite::queue::{
+        self, DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, OccurrenceState, QueueOrder,
+        TaskFilter, TaskRun, TaskState,
+    };
+    use thermite::store::{SqlStore, TaskStore};
+    use thermite::task::{BaseTask, TaskPriority, TaskUpdate};
+
+    // An in-memory SQLite database runs the migrations and the same SQL as a file or Postgres
+    // database would, so these tests need no server.
+    async fn sqlite_store() -> SqlStore {
+        SqlStore::connect("sqlite::memory:").await.unwrap()
+    }
+
+    // Row locking only matters on Postgres, so the claim race runs against a live server. The
+    // test skips itself when none is reachable, unless `CI` or `THERMITE_REQUIRE_POSTGRES` is set.
+    async fn postgres_store() -> Option<SqlStore> {
+        let url = std::env::var("THERMITE_TEST_POSTGRES_URL")
+            .unwrap_or_else(|_| "postgres://postgres@127.0.0.1:5432/thermite_test".to_string());
+        let required = std::env::var_os("CI").is_some() || std::env::var_os("THERMITE_REQUIRE_POSTGRES").is_some();
+
+        match SqlStore::connect(&url).await {
+            Ok(store) => Some(store),
+            Err(e) if required => panic!("Postgres at {url} is required but unavailable: {e}"),
+            Err(e) => {
+                eprintln!("skipping Postgres-backed test, Postgres unavailable: {e}");
+                None
+            }
+        }
+    }
+
+    fn task_at(id: &str, scheduled_at: u64) -> BaseTask {
+        BaseTask {
+            id: id.to_string(),
+            name: format!("Task {id}"),
+            category: "non_periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            scheduled_at,
+            ..Default::default()
+        }
+    }
+
+    fn due_task(id: &str) -> BaseTask {
+        task_at(id, (Utc::now().timestamp() - 60) as u64)
+    }
+
+    #[tokio::test]
+    async fn claims_follow_priority_then_due_time_and_leases_expire() {
+        let store = sqlite_store().await;
+        let mut urgent = task_at("urgent", 150);
+        urgent.priority = TaskPriority::High;
+        for task in [task_at("newer", 120), task_at("older", 100), task_at("future", 500), urgent] {
+            store.enqueue(&task, DuplicatePolicy::Reject).await.unwrap();
+        }
+
+        let before_claims = store.stats(200).await.unwrap();
+        let mut claimed = Vec::new();
+        while let Some(task) = store.claim(200, 260, QueueOrder::Fifo).await.unwrap() {
+            claimed.push(task.id);
+        }
+        let after_claims = store.stats(200).await.unwrap();
+        let (_, state) = store.get("older").await.unwrap().unwrap();
+        let before_deadline = store.requeue_expired_leases(259, 100).await.unwrap();
+        let at_deadline = store.requeue_expired_leases(260, 100).await.unwrap();
+        let reclaimed = store.claim(260, 320, QueueOrder::Lifo).await.unwrap();
+
+        assert_eq!((before_claims.due, before_claims.scheduled, before_claims.in_flight), (3, 1, 0));
+        assert_eq!((after_claims.due, after_claims.scheduled, after_claims.in_flight), (0, 1, 3));
+        assert_eq!(claimed, vec!["urgent", "older", "newer"]);
+        assert_eq!(state, TaskState::InFlight);
+        assert_eq!(before_deadline, 0);
+        assert_eq!(at_deadline, 3);
+        assert_eq!(reclaimed.map(|task| task.id), Some("urgent".to_string()));
+    }
+
+    #[tokio::test]
+    async fn extended_leases_outlast_the_visibility_timeout() {
+        let store = sqlite_store().await;
+        store.enqueue(&task_at("slow", 100), DuplicatePolicy::Reject).await.unwrap();
+
+        let lease = store.claim(100, 160, QueueOrder::Fifo).await.unwrap().unwrap().lease.unwrap();
+        let extended = store.extend_lease("slow", lease, 700).await.unwrap();
+        let kept_longer = store.extend_lease("slow", lease, 300).await.unwrap();
+        let not_leased = store.extend_lease("missing", lease, 700).await.unwrap();
+        let past_visibility_timeout = store.requeue_expired_leases(650, 100).await.unwrap();
+        let past_extension = store.requeue_expired_leases(700, 100).await.unwrap();
+
+        assert!(extended && kept_longer);
+        assert!(!not_leased);
+        assert_eq!(past_visibility_timeout, 0);
+        assert_eq!(past_extension, 1);
+    }
+
+    #[tokio::test]
+    async fn stale_settle_after_reclaim_is_a_no_op() {
+        let store = sqlite_store().await;
+        store.enqueue(&task_at("contested", 100), DuplicatePolicy::Reject).await.unwrap();
+
+        let stale = store.claim(100, 160, QueueOrder::Fifo).await.unwrap().unwrap();
+        store.requeue_expired_leases(160, 100).await.unwrap();
+        let current = store.claim(160, 220, QueueOrder::Fifo).await.unwrap().unwrap();
+        let stale_lease = stale.lease.unwrap();
+        let stale_extended = store.extend_lease("contested", stale_lease, 900).await.unwrap();
+        let stale_settled = store.settle("contested", stale_lease, None, None, 170).await.unwrap();
+        let state_after_stale = store.get("contested").await.unwrap().map(|(_, state)| state);
+        let settled = store.settle("contested", current.lease.unwrap(), None, None, 180).await.unwrap();
+
+        assert!(!stale_extended && !stale_settled);
+        assert_eq!(state_after_stale, Some(TaskState::InFlight));
+        assert!(settled);
+        assert!(store.get("contested").await.unwrap().is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_postgres_claimers_receive_each_task_exactly_once() {
+        // Two stores stand in for two replicas, each with its own connection pool.
+        let (Some(first), Some(second)) = (postgres_store().await, postgres_store().await) else {
+            return;
+        };
+        queue::clear_task_queue(&first).await.unwrap();
+
+        let task_count = 200;
+        for index in 0..task_count {
+            queue::enqueue_task(&first, &due_task(&format!("claim-{index}"))).await.unwrap();
+        }
+
+        let mut claimers = Vec::new();
+        for store in [first.clone(), second].into_iter().cycle().take(16) {
+            claimers.push(tokio::spawn(async move {
+                let mut claimed = Vec::new();
+                while let Some(task) = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap() {
+                    claimed.push(task.id);
+                }
+                claimed
+            }));
+        }
+
+        let mut all_claimed = Vec::new();
+        for claimer in claimers {
+            all_claimed.extend(claimer.await.unwrap());
+        }
+        queue::clear_task_queue(&first).await.unwrap();
+
+        let unique: HashSet<_> = all_claimed.iter().cloned().collect();
+        assert_eq!(all_claimed.len(), task_count);
+        assert_eq!(unique.len(), task_count);
+    }
+
+    #[tokio::test]
+    async fn failed_task_is_retried_then_dead_lettered_and_replayed() {
+        let store = sqlite_store().await;
+        let mut task = due_task("flaky");
+        task.max_retries = Some(1);
+        queue::enqueue_task(&store, &task).await.unwrap();
+
+        let first = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::handle_task_failure(&store, &first, "503 Service Unavailable", &RetryPolicy::default()).await.unwrap();
+        let (retry, _) = queue::get_task(&store, "flaky").await.unwrap().unwrap();
+
+        let second = store.claim(retry.scheduled_at as i64, i64::MAX, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::handle_task_failure(&store, &second, "503 Service Unavailable", &RetryPolicy::default()).await.unwrap();
+        let gone = queue::get_task(&store, "flaky").await.unwrap();
+        let (dead_letters, total) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+
+        let replayed = queue::replay_dead_letter_task(&store, "flaky").await.unwrap().unwrap();
+        let (_, remaining) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+
+        assert_eq!(retry.retry_count, 1);
+        assert!(gone.is_none());
+        assert_eq!(total, 1);
+        assert_eq!(dead_letters[0].last_error.as_deref(), Some("503 Service Unavailable"));
+        assert_eq!(replayed.retry_count, 0);
+        assert_eq!(remaining, 0);
+    }
+
+    #[tokio::test]
+    async fn duplicates_and_updates_respect_leases() {
+        let store = sqlite_store().await;
+        let mut changed = due_task("report");
+        changed.priority = TaskPriority::High;
+
+        let first = queue::enqueue_task_with_policy(&store, &due_task("report"), DuplicatePolicy::Reject).await.unwrap();
+        let rejected = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Reject).await;
+        let kept = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::KeepExisting).await.unwrap();
+        let replaced = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Replace).await.unwrap();
+        let update = TaskUpdate { priority: Some(TaskPriority::Low), ..Default::default() };
+        let updated = queue::update_task(&store, "report", &update).await.unwrap().unwrap();
+
+        queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        let update_in_flight = queue::update_task(&store, "report", &update).await;
+        let (queued, queued_total) = queue::list_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+
+        assert_eq!(first, EnqueueOutcome::Enqueued);
+        assert!(matches!(rejected, Err(TaskQueueError::DuplicateTask(_))));
+        assert_eq!(kept, EnqueueOutcome::AlreadyExists);
+        assert_eq!(replaced, EnqueueOutcome::Replaced);
+        assert_eq!(updated.priority, TaskPriority::Low);
+        assert!(matches!(update_in_flight, Err(TaskQueueError::TaskInFlight(_))));
+        assert!(queued.is_empty());
+        assert_eq!(queued_total, 0);
+    }
+
+    #[tokio::test]
+    async fn listing_filters_and_pages_queued_tasks() {
+        let store = sqlite_store().await;
+        for (id, category, priority, scheduled_at) in [
+            ("leased", "reports", TaskPriority::High, 90),
+            ("daily", "reports", TaskPriority::High, 100),
+            ("weekly", "reports", TaskPriority::Normal, 110),
+            ("welcome", "emails", TaskPriority::High, 120),
+            ("monthly", "reports", TaskPriority::High, 130),
+        ] {
+            let mut task = task_at(id, scheduled_at);
+            task.category = category.to_string();
+            task.priority = priority;
+            store.enqueue(&task, DuplicatePolicy::Reject).await.unwrap();
+        }
+        store.claim(95, 400, QueueOrder::Fifo).await.unwrap().unwrap();
+
+        let urgent_reports = TaskFilter {
+            category: Some("reports".to_string()),
+            priority: Some(TaskPriority::High),
+            ..Default::default()
+        };
+        let window = TaskFilter { scheduled_after: Some(105), scheduled_before: Some(125), ..Default::default() };
+        let ids = |tasks: Vec<BaseTask>| tasks.into_iter().map(|task| task.id).collect::<Vec<_>>();
+
+        let (all, all_total) = store.list(&TaskFilter::default(), 0, 10).await.unwrap();
+        let (first, urgent_total) = store.list(&urgent_reports, 0, 1).await.unwrap();
+        let (second, _) = store.list(&urgent_reports, 1, 1).await.unwrap();
+        let (windowed, windowed_total) = store.list(&window, 0, 10).await.unwrap();
+
+        assert_eq!(ids(all), vec!["daily", "weekly", "welcome", "monthly"]);
+        assert_eq!(all_total, 4);
+        assert_eq!(ids(first), vec!["daily"]);
+        assert_eq!(ids(second), vec!["monthly"]);
+        assert_eq!(urgent_total, 2);
+        assert_eq!(ids(windowed), vec!["weekly", "welcome"]);
+        assert_eq!(windowed_total, 2);
+    }
+
+    #[tokio::test]
+    async fn periodic_tasks_spawn_tracked_occurrences() {
+        let store = sqlite_store().await;
+        let mut series = due_task("hourly-report");
+        series.category = "periodic".to_string();
+        series.cron_scheduled_at = "0 * * * *".to_string();
+        series.scheduled_at = (Utc::now().timestamp() / 3600 * 3600) as u64;
+        queue::enqueue_task(&store, &series).await.unwrap();
+
+        let occurrence = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        let (rescheduled, series_state) = queue::get_task(&store, "hourly-report").await.unwrap().unwrap();
+        let in_flight = queue::list_occurrences(&store, "hourly-report").await.unwrap().unwrap();
+        queue::ack_task(&store, &occurrence).await.unwrap();
+        let finished = queue::list_occurrences(&store, "hourly-report").await.unwrap().unwrap();
+
+        assert_eq!(occurrence.id, format!("hourly-report:{}", series.scheduled_at));
+        assert_eq!(series_state, TaskState::Queued);
+        assert_eq!(rescheduled.scheduled_at, series.scheduled_at + 3600);
+        assert_eq!(in_flight[0].state, OccurrenceState::InFlight);
+        assert_eq!(finished[0].state, OccurrenceState::Finished);
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_replay_completed_responses() {
+        let store = sqlite_store().await;
+        let response = IdempotentResponse { status: 201, body: serde_json::json!({"task_id": "task-1"}) };
+
+        let first = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+        let concurrent = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+        queue::complete_idempotent_request(&store, "/submit-task", "key-1", "a", &response).await.unwrap();
+        let repeated = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+        let mismatched = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "b").await.unwrap();
+        queue::release_idempotent_request(&store, "/submit-task", "key-1").await.unwrap();
+        let released = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "b").await.unwrap();
+
+        assert!(matches!(first, IdempotencyState::New));
+        assert!(matches!(concurrent, IdempotencyState::InProgress));
+        assert!(matches!(repeated, IdempotencyState::Completed(ref stored) if stored.status == 201));
+        assert!(matches!(mismatched, IdempotencyState::Mismatch));
+        assert!(matches!(released, IdempotencyState::New));
+    }
+
+    #[tokio::test]
+    async fn expired_idempotency_keys_are_deleted() {
+        // A file database, so a second connection can plant a key that expired long ago.
+        let path = std::env::temp_dir().join(format!("thermite-idempotency-{}.db", std::process::id()));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        let store = SqlStore::connect(&url).await.unwrap();
+        let pool = sqlx::AnyPool::connect(&url).await.unwrap();
+        sqlx::query(
+            "INSERT INTO thermite_idempotency_keys (idempotency_key, fingerprint, response, expires_at) \
+             VALUES ('/submit-task:stale', 'a', NULL, $1)",
+        )
+        .bind(Utc::now().timestamp() - 60)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        queue::begin_idempotent_request(&store, "/submit-task", "fresh", "a").await.unwrap();
+        let keys: Vec<String> = sqlx::query_scalar("SELECT idempotency_key FROM thermite_idempotency_keys")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(keys, vec!["/submit-task:fresh".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn run_histories_keep_the_most_recent_runs() {
+        let store = sqlite_store().await;
+        let mut occurrence = due_task("hourly:1700000000");
+        occurrence.series_id = Some("hourly".to_string());
+
+        for attempt in 1..=60 {
+            let run = TaskRun {
+                attempt,
+                started_at: 1_700_000_000 + u64::from(attempt),
+                finished_at: 1_700_000_000 + u64::from(attempt),
+                latency_ms: 12,
+                status: Some(503),
+                error: Some("503 Service Unavailable".to_string()),
+                occurrence_id: None,
+            };
+            queue::record_task_run(&store, &occurrence, &run).await.unwrap();
+        }
+        let runs = store.list_runs("hourly:1700000000").await.unwrap();
+        let series_runs = store.list_runs("hourly").await.unwrap();
+        store.clear().await.unwrap();
+        let cleared = store.list_runs("hourly").await.unwrap();
+
+        assert_eq!(runs.len(), 50);
+        assert_eq!((runs[0].attempt, runs[49].attempt), (60, 11));
+        assert!(runs[0].occurrence_id.is_none());
+        assert_eq!(series_runs.len(), 50);
+        assert_eq!(series_runs[0].occurrence_id.as_deref(), Some("hourly:1700000000"));
+        assert!(cleared.is_empty());
+    }
+}
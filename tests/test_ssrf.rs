@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+    use thermite::config::TargetPolicy;
+    use thermite::ssrf::{delivery_client, is_blocked_ip, GuardedResolver};
+    use thermite::task::validate_target;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // Resolves names from a fixed table instead of DNS.
+    #[derive(Clone)]
+    struct StubResolver(HashMap<String, Vec<IpAddr>>);
+
+    impl StubResolver {
+        fn new(entries: &[(&str, &[&str])]) -> Self {
+            StubResolver(
+                entries
+                    .iter()
+                    .map(|(name, ips)| (name.to_string(), ips.iter().map(|ip| ip.parse().unwrap()).collect()))
+                    .collect(),
+            )
+        }
+    }
+
+    impl Resolve for StubResolver {
+        fn resolve(&self, name: Name) -> Resolving {
+            let ips = self.0.get(name.as_str()).cloned().unwrap_or_default();
+            Box::pin(async move {
+                let addrs: Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 80)));
+                Ok(addrs)
+            })
+        }
+    }
+
+    fn stub() -> StubResolver {
+        StubResolver::new(&[
+            ("internal.test", &["10.0.0.5"]),
+            ("metadata.test", &["169.254.169.254"]),
+            ("mixed.test", &["192.168.1.10", "93.184.216.34"]),
+            ("benchmark.test", &["198.19.255.1"]),
+            ("assignments.test", &["192.0.0.8"]),
+            ("reserved.test", &["240.0.0.1"]),
+            ("nat64.test", &["64:ff9b::a9fe:a9fe"]),
+            ("6to4.test", &["2002:a00:1::1"]),
+            ("documentation.test", &["2001:db8::5"]),
+            ("site-local.test", &["fec0::5"]),
+            ("teredo.test", &["2001:0:4136:e378:8000:63bf:3fff:fdd2"]),
+            ("translated.test", &["64:ff9b::a00:5", "2002:c0a8:10a::1", "64:ff9b::5db8:d822"]),
+        ])
+    }
+
+    async fn resolve(resolver: &GuardedResolver, name: &str) -> Result<Vec<IpAddr>, Box<dyn Error + Send + Sync>> {
+        let addrs = resolver.resolve(name.parse().unwrap()).await?;
+        Ok(addrs.map(|address| address.ip()).collect())
+    }
+
+    fn mentions_blocked_range(error: &reqwest::Error) -> bool {
+        let mut source: Option<&dyn Error> = Some(error);
+        while let Some(current) = source {
+            if current.to_string().contains("blocked IP range") {
+                return true;
+            }
+            source = current.source();
+        }
+        false
+    }
+
+    // Answers every request with a redirect to `location`.
+    async fn start_redirecting_target(location: String) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let location = location.clone();
+                tokio::spawn(async move {
+                    let mut buffer = [0_u8; 4096];
+                    let _ = stream.read(&mut buffer).await;
+                    let response = format!(
+                        "HTTP/1.1 302 Found\r\nLocation: {location}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn blocked_ranges_include_metadata_shared_and_mapped_addresses() {
+        for ip in [
+            "10.0.0.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.1.2.3",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+            "::ffff:169.254.169.254",
+            "198.18.0.1",
+            "198.19.255.255",
+            "192.0.0.170",
+            "240.0.0.1",
+            "255.255.255.254",
+            "64:ff9b::a00:1",
+            "64:ff9b::7f00:1",
+            "2002:a9fe:a9fe::1",
+            "2002:c0a8:101::",
+            "2001:db8::1",
+            "2001:db8:ffff::1",
+            "fec0::1",
+            "feff::1",
+            "2001:0:4136:e378:8000:63bf:3fff:fdd2",
+        ] {
+            assert!(is_blocked_ip(ip.parse().unwrap()), "{ip} should be blocked");
+        }
+
+        for ip in [
+            "93.184.216.34",
+            "100.128.0.1",
+            "198.20.0.1",
+            "192.0.1.1",
+            "223.255.255.1",
+            "2606:4700::1111",
+            "64:ff9b::5db8:d822",
+            "2002:5db8:d822::1",
+            "2001:4860:4860::8888",
+            "2001:db9::1",
+        ] {
+            assert!(!is_blocked_ip(ip.parse().unwrap()), "{ip} should be allowed");
+        }
+    }
+
+    #[test]
+    fn literal_ipv6_targets_are_checked() {
+        assert!(validate_target("http://[::1]:8080/hooks").is_err());
+        assert!(validate_target("http://[::ffff:10.0.0.1]/hooks").is_err());
+        assert!(validate_target("http://[64:ff9b::a9fe:a9fe]/latest/meta-data").is_err());
+        assert!(validate_target("http://[2002:a00:1::1]/hooks").is_err());
+        assert!(validate_target("http://[2001:db8::1]/hooks").is_err());
+        assert!(validate_target("http://[fec0::1]/hooks").is_err());
+        assert!(validate_target("http://[2001:0:4136:e378:8000:63bf:3fff:fdd2]/hooks").is_err());
+        assert!(validate_target("http://[2606:4700::1111]/hooks").is_ok());
+    }
+
+    #[tokio::test]
+    async fn guarded_resolver_drops_blocked_addresses() {
+        let resolver = GuardedResolver::new(stub());
+
+        let allowed = resolve(&resolver, "mixed.test").await.unwrap();
+
+        assert_eq!(allowed, vec!["93.184.216.34".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn guarded_resolver_fails_when_every_address_is_blocked() {
+        let resolver = GuardedResolver::new(stub());
+
+        assert!(resolve(&resolver, "internal.test").await.is_err());
+        assert!(resolve(&resolver, "metadata.test").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn guarded_resolver_blocks_special_purpose_and_translated_ranges() {
+        let resolver = GuardedResolver::new(stub());
+
+        for name in [
+            "benchmark.test",
+            "assignments.test",
+            "reserved.test",
+            "nat64.test",
+            "6to4.test",
+            "documentation.test",
+            "site-local.test",
+            "teredo.test",
+        ] {
+            assert!(resolve(&resolver, name).await.is_err(), "{name} should be blocked");
+        }
+        let allowed = resolve(&resolver, "translated.test").await.unwrap();
+
+        assert_eq!(allowed, vec!["64:ff9b::5db8:d822".parse::<IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn delivery_to_hostname_resolving_to_private_address_fails() {
+        let client = delivery_client(stub(), Duration::from_secs(5), TargetPolicy::default()).unwrap();
+
+        let error = client.post("http://metadata.test/latest/meta-data").send().await.unwrap_err();
+
+        assert!(mentions_blocked_range(&error), "unexpected error: {error:?}");
+    }
+
+    #[tokio::test]
+    async fn redirect_to_blocked_literal_ip_is_not_followed() {
+        let target = start_redirecting_target("http://169.254.169.254/latest/meta-data".to_string()).await;
+        let client = delivery_client(stub(), Duration::from_secs(5), TargetPolicy::default()).unwrap();
+
+        let error = client.post(format!("http://{target}/hook")).send().await.unwrap_err();
+
+        assert!(error.is_redirect());
+    }
+
+    #[tokio::test]
+    async fn redirect_to_hostname_resolving_to_private_address_fails() {
+        let target = start_redirecting_target("http://internal.test/admin".to_string()).await;
+        let client = delivery_client(stub(), Duration::from_secs(5), TargetPolicy::default()).unwrap();
+
+        let error = client.post(format!("http://{target}/hook")).send().await.unwrap_err();
+
+        assert!(mentions_blocked_range(&error), "unexpected error: {error:?}");
+    }
+
+    #[tokio::test]
+    async fn deliveries_ignore_proxies_from_the_environment() {
+        // A proxy would resolve `metadata.test` itself, past the guarded resolver.
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_url = format!("http://{}", proxy.local_addr().unwrap());
+        let proxied = Arc::new(AtomicBool::new(false));
+        let seen = Arc::clone(&proxied);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = proxy.accept().await {
+                seen.store(true, Ordering::SeqCst);
+                let _ = stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .await;
+            }
+        });
+        for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY", "http_proxy"] {
+            std::env::set_var(name, &proxy_url);
+        }
+
+        let client = delivery_client(stub(), Duration::from_secs(5), TargetPolicy::default());
+        for name in ["HTTP_PROXY", "HTTPS_PROXY", "ALL_PROXY", "http_proxy"] {
+            std::env::remove_var(name);
+        }
+        let error = client.unwrap().post("http://metadata.test/latest/meta-data").send().await.unwrap_err();
+
+        assert!(mentions_blocked_range(&error), "unexpected error: {error:?}");
+        assert!(!proxied.load(Ordering::SeqCst));
+    }
+}
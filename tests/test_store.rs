@@ -0,0 +1,437 @@
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use chrono::Utc;
+    use thermite::config::RetryPolicy;
+    use thermite::errors::TaskQueueError;
+    use thermite::queue::{
+        self, DuplicatePolicy, EnqueueOutcome, IdempotencyState, IdempotentResponse, OccurrenceState, QueueOrder,
+        TaskFilter, TaskRun, TaskState,
+    };
+    use thermite::store::{MemoryStore, SeriesOutcome, TaskStore};
+    use thermite::task::{BaseTask, DeliveryHeaders, MisfirePolicy, TaskPriority, TaskUpdate};
+
+    // These tests run the scheduler against the in-memory store, so they need no server. Store
+    // methods take the time explicitly, which keeps lease and claim timing deterministic.
+    fn task_at(id: &str, scheduled_at: u64) -> BaseTask {
+        BaseTask {
+            id: id.to_string(),
+            name: format!("Task {id}"),
+            category: "non_periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            scheduled_at,
+            ..Default::default()
+        }
+    }
+
+    fn due_task(id: &str) -> BaseTask {
+        task_at(id, (Utc::now().timestamp() - 60) as u64)
+    }
+
+    async fn claim_all(store: &MemoryStore, now: i64, order: QueueOrder) -> Vec<String> {
+        let mut claimed = Vec::new();
+        while let Some(task) = store.claim(now, now + 300, order).await.unwrap() {
+            claimed.push(task.id);
+        }
+        claimed
+    }
+
+    #[tokio::test]
+    async fn claims_follow_priority_then_due_time() {
+        let store = MemoryStore::new();
+        let mut urgent = task_at("urgent", 150);
+        urgent.priority = TaskPriority::High;
+        for task in [task_at("newer", 120), task_at("older", 100), task_at("future", 500), urgent] {
+            store.enqueue(&task, DuplicatePolicy::Reject).await.unwrap();
+        }
+
+        let fifo = claim_all(&store, 200, QueueOrder::Fifo).await;
+        store.clear().await.unwrap();
+        for task in [task_at("newer", 120), task_at("older", 100)] {
+            store.enqueue(&task, DuplicatePolicy::Reject).await.unwrap();
+        }
+        let lifo = claim_all(&store, 200, QueueOrder::Lifo).await;
+
+        assert_eq!(fifo, vec!["urgent", "older", "newer"]);
+        assert_eq!(lifo, vec!["newer", "older"]);
+    }
+
+    #[tokio::test]
+    async fn expired_lease_returns_task_to_queue() {
+        let store = MemoryStore::new();
+        store.enqueue(&task_at("leased", 100), DuplicatePolicy::Reject).await.unwrap();
+
+        let claimed = store.claim(100, 160, QueueOrder::Fifo).await.unwrap();
+        let (_, state) = store.get("leased").await.unwrap().unwrap();
+        let before_deadline = store.requeue_expired_leases(159, 100).await.unwrap();
+        let at_deadline = store.requeue_expired_leases(160, 100).await.unwrap();
+        let reclaimed = store.claim(160, 220, QueueOrder::Fifo).await.unwrap();
+
+        assert!(claimed.is_some());
+        assert_eq!(state, TaskState::InFlight);
+        assert_eq!(before_deadline, 0);
+        assert_eq!(at_deadline, 1);
+        assert_eq!(reclaimed.map(|task| task.id), Some("leased".to_string()));
+    }
+
+    #[tokio::test]
+    async fn slow_task_is_not_reclaimed_while_its_delivery_may_still_run() {
+        let store = MemoryStore::new();
+        let slow = BaseTask { timeout_secs: Some(600), ..due_task("slow") };
+        store.enqueue(&slow, DuplicatePolicy::Reject).await.unwrap();
+        store.enqueue(&due_task("quick"), DuplicatePolicy::Reject).await.unwrap();
+
+        let visibility_timeout = Duration::from_secs(60);
+        let now = Utc::now().timestamp();
+        let first = queue::dequeue_task_with_lease(&store, QueueOrder::Fifo, visibility_timeout).await.unwrap();
+        let second = queue::dequeue_task_with_lease(&store, QueueOrder::Fifo, visibility_timeout).await.unwrap();
+        let past_visibility_timeout = store.requeue_expired_leases(now + 120, 100).await.unwrap();
+        let reclaimed = store.claim(now + 120, now + 180, QueueOrder::Fifo).await.unwrap();
+        let (_, slow_state) = store.get("slow").await.unwrap().unwrap();
+        store.requeue_expired_leases(now + 620, 100).await.unwrap();
+        let (_, expired_state) = store.get("slow").await.unwrap().unwrap();
+
+        let mut claimed: Vec<String> = [first, second].into_iter().flatten().map(|task| task.id).collect();
+        claimed.sort();
+        assert_eq!(claimed, vec!["quick", "slow"]);
+        assert_eq!(past_visibility_timeout, 1);
+        assert_eq!(reclaimed.map(|task| task.id).as_deref(), Some("quick"));
+        assert_eq!(slow_state, TaskState::InFlight);
+        assert_eq!(expired_state, TaskState::Queued);
+    }
+
+    #[tokio::test]
+    async fn cancelled_in_flight_task_is_not_settled() {
+        let store = MemoryStore::new();
+        store.enqueue(&task_at("doomed", 100), DuplicatePolicy::Reject).await.unwrap();
+
+        let claimed = store.claim(100, 400, QueueOrder::Fifo).await.unwrap().unwrap();
+        store.cancel("doomed").await.unwrap();
+        let settled = store.settle("doomed", claimed.lease.unwrap(), Some(&claimed), None, 120).await.unwrap();
+
+        assert!(!settled);
+        assert!(store.get("doomed").await.unwrap().is_none());
+        assert_eq!(store.requeue_expired_leases(1_000, 100).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn stale_settle_after_reclaim_is_a_no_op() {
+        let store = MemoryStore::new();
+        store.enqueue(&task_at("contested", 100), DuplicatePolicy::Reject).await.unwrap();
+
+        let stale = store.claim(100, 160, QueueOrder::Fifo).await.unwrap().unwrap();
+        store.requeue_expired_leases(160, 100).await.unwrap();
+        let current = store.claim(160, 220, QueueOrder::Fifo).await.unwrap().unwrap();
+        let stale_lease = stale.lease.unwrap();
+        let stale_extended = store.extend_lease("contested", stale_lease, 900).await.unwrap();
+        let stale_settled = store.settle("contested", stale_lease, None, None, 170).await.unwrap();
+        let state_after_stale = store.get("contested").await.unwrap().map(|(_, state)| state);
+        let settled = store.settle("contested", current.lease.unwrap(), None, None, 180).await.unwrap();
+
+        assert_ne!(stale.lease, current.lease);
+        assert!(!stale_extended && !stale_settled);
+        assert_eq!(state_after_stale, Some(TaskState::InFlight));
+        assert!(settled);
+        assert!(store.get("contested").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn failed_task_is_retried_then_dead_lettered_and_replayed() {
+        let store = MemoryStore::new();
+        let mut task = due_task("flaky");
+        task.max_retries = Some(1);
+        queue::enqueue_task(&store, &task).await.unwrap();
+
+        let first = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::handle_task_failure(&store, &first, "503 Service Unavailable", &RetryPolicy::default()).await.unwrap();
+        let (retry, _) = queue::get_task(&store, "flaky").await.unwrap().unwrap();
+        let not_yet_due = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+
+        let second = store.claim(retry.scheduled_at as i64, i64::MAX, QueueOrder::Fifo).await.unwrap().unwrap();
+        queue::handle_task_failure(&store, &second, "503 Service Unavailable", &RetryPolicy::default()).await.unwrap();
+        let gone = queue::get_task(&store, "flaky").await.unwrap();
+        let (dead_letters, total) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+
+        let replayed = queue::replay_dead_letter_task(&store, "flaky").await.unwrap().unwrap();
+        let (_, remaining) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        dead_letter_again(&store, &replayed).await;
+        queue::enqueue_task(&store, &due_task("flaky")).await.unwrap();
+        let blocked = queue::replay_dead_letter_task(&store, "flaky").await;
+
+        assert_eq!(retry.retry_count, 1);
+        assert!(retry.scheduled_at as i64 > Utc::now().timestamp());
+        assert!(not_yet_due.is_none());
+        assert!(gone.is_none());
+        assert_eq!(total, 1);
+        assert_eq!(dead_letters[0].last_error.as_deref(), Some("503 Service Unavailable"));
+        assert_eq!(replayed.retry_count, 0);
+        assert_eq!(remaining, 0);
+        assert!(matches!(blocked, Err(TaskQueueError::DuplicateTask(_))));
+    }
+
+    fn headers(entries: &[(&str, &str)]) -> DeliveryHeaders {
+        entries.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn dead_lettered_credentials_are_redacted_and_resupplied_on_replay() {
+        let store = MemoryStore::new();
+        let mut task = due_task("private");
+        task.headers = headers(&[("Authorization", "Bearer s3cret"), ("X-Tenant", "acme")]);
+        queue::enqueue_task(&store, &task).await.unwrap();
+        dead_letter_again(&store, &task).await;
+
+        let (dead_letters, _) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        let without_credentials = queue::replay_dead_letter_task(&store, "private").await;
+        let replayed = queue::replay_dead_letter_task_with_headers(
+            &store,
+            "private",
+            &headers(&[("authorization", "Bearer rotated")]),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(dead_letters[0].headers.get("Authorization"), Some("[redacted]"));
+        assert_eq!(dead_letters[0].headers.get("X-Tenant"), Some("acme"));
+        assert!(matches!(without_credentials, Err(TaskQueueError::InvalidDeliveryOptions(_))));
+        assert_eq!(replayed.headers.get("Authorization"), Some("Bearer rotated"));
+        assert_eq!(replayed.headers.iter().count(), 2);
+        let (queued, _) = queue::get_task(&store, "private").await.unwrap().unwrap();
+        assert_eq!(queued.headers.get("authorization"), Some("Bearer rotated"));
+    }
+
+    #[tokio::test]
+    async fn bulk_replay_pages_through_the_dead_letter_queue_up_to_a_limit() {
+        let store = MemoryStore::new();
+        for index in 0..240 {
+            let mut task = due_task(&format!("dead-{index:03}"));
+            if index % 10 == 0 {
+                // Its credentials are redacted in the dead-letter queue, so it cannot be replayed in bulk.
+                task.headers = headers(&[("Authorization", "Bearer s3cret")]);
+            }
+            queue::enqueue_task(&store, &task).await.unwrap();
+        }
+        while let Some(task) = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap() {
+            let mut exhausted = task.clone();
+            exhausted.retry_count = RetryPolicy::default().max_retries;
+            queue::handle_task_failure(&store, &exhausted, "502 Bad Gateway", &RetryPolicy::default()).await.unwrap();
+        }
+
+        let first = queue::replay_dead_letter_tasks(&store, &TaskFilter::default(), 150).await.unwrap();
+        let second = queue::replay_dead_letter_tasks(&store, &TaskFilter::default(), 1000).await.unwrap();
+        let (_, left) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 0).await.unwrap();
+
+        assert_eq!((first.replayed.len(), first.failed.len(), first.remaining), (135, 15, 90));
+        assert_eq!((second.replayed.len(), second.failed.len(), second.remaining), (81, 24, 0));
+        assert_eq!(left, 24);
+    }
+
+    async fn dead_letter_again(store: &MemoryStore, task: &BaseTask) {
+        let claimed = queue::dequeue_task(store, QueueOrder::Fifo).await.unwrap().unwrap();
+        assert_eq!(claimed.id, task.id);
+        let mut exhausted = claimed.clone();
+        exhausted.retry_count = RetryPolicy::default().max_retries;
+        queue::handle_task_failure(store, &exhausted, "502 Bad Gateway", &RetryPolicy::default()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn duplicates_and_updates_respect_leases() {
+        let store = MemoryStore::new();
+        let mut changed = due_task("report");
+        changed.priority = TaskPriority::High;
+
+        let first = queue::enqueue_task_with_policy(&store, &due_task("report"), DuplicatePolicy::Reject).await.unwrap();
+        let rejected = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Reject).await;
+        let kept = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::KeepExisting).await.unwrap();
+        let replaced = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Replace).await.unwrap();
+        let update = TaskUpdate { priority: Some(TaskPriority::Low), ..Default::default() };
+        let updated = queue::update_task(&store, "report", &update).await.unwrap().unwrap();
+        let (low, low_total) = queue::list_tasks(
+            &store,
+            &TaskFilter { priority: Some(TaskPriority::Low), ..Default::default() },
+            0,
+            10,
+        )
+        .await
+        .unwrap();
+
+        queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        let update_in_flight = queue::update_task(&store, "report", &update).await;
+        let replace_in_flight = queue::enqueue_task_with_policy(&store, &changed, DuplicatePolicy::Replace).await;
+        let (queued, queued_total) = queue::list_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+
+        assert_eq!(first, EnqueueOutcome::Enqueued);
+        assert!(matches!(rejected, Err(TaskQueueError::DuplicateTask(_))));
+        assert_eq!(kept, EnqueueOutcome::AlreadyExists);
+        assert_eq!(replaced, EnqueueOutcome::Replaced);
+        assert_eq!(updated.priority, TaskPriority::Low);
+        assert_eq!((low.len(), low_total), (1, 1));
+        assert!(matches!(update_in_flight, Err(TaskQueueError::TaskInFlight(_))));
+        assert!(matches!(replace_in_flight, Err(TaskQueueError::TaskInFlight(_))));
+        assert!(queued.is_empty());
+        assert_eq!(queued_total, 0);
+    }
+
+    #[tokio::test]
+    async fn tasks_stored_before_colons_were_reserved_stay_usable() {
+        // Ids with ':' were accepted before ':' was reserved for occurrences; stores may still hold them.
+        let store = MemoryStore::new();
+        store.enqueue(&due_task("legacy:1"), DuplicatePolicy::Reject).await.unwrap();
+        store.enqueue(&due_task("legacy:2"), DuplicatePolicy::Reject).await.unwrap();
+
+        let found = queue::get_task(&store, "legacy:1").await.unwrap();
+        let update = TaskUpdate { priority: Some(TaskPriority::High), ..Default::default() };
+        let updated = queue::update_task(&store, "legacy:1", &update).await.unwrap().unwrap();
+        dead_letter_again(&store, &updated).await;
+        let replayed = queue::replay_dead_letter_task(&store, "legacy:1").await.unwrap();
+        let cancelled = queue::cancel_task(&store, "legacy:2").await.unwrap();
+        let resubmitted = due_task("legacy:3").validate_submission();
+
+        assert!(found.is_some());
+        assert_eq!(updated.priority, TaskPriority::High);
+        assert_eq!(replayed.map(|task| task.id).as_deref(), Some("legacy:1"));
+        assert!(cancelled);
+        assert!(matches!(resubmitted, Err(TaskQueueError::InvalidTaskId(_))));
+    }
+
+    fn hourly_series(id: &str) -> BaseTask {
+        let mut task = due_task(id);
+        task.category = "periodic".to_string();
+        task.cron_scheduled_at = "0 * * * *".to_string();
+        // Due, but not yet an hour late, so no run was missed.
+        task.scheduled_at = (Utc::now().timestamp() / 3600 * 3600) as u64;
+        task
+    }
+
+    #[tokio::test]
+    async fn periodic_tasks_spawn_tracked_occurrences() {
+        let store = MemoryStore::new();
+        let series = hourly_series("hourly-report");
+        queue::enqueue_task(&store, &series).await.unwrap();
+
+        let occurrence = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        let (rescheduled, series_state) = queue::get_task(&store, "hourly-report").await.unwrap().unwrap();
+        let in_flight = queue::list_occurrences(&store, "hourly-report").await.unwrap().unwrap();
+        queue::ack_task(&store, &occurrence).await.unwrap();
+        let finished = queue::list_occurrences(&store, "hourly-report").await.unwrap().unwrap();
+        let unknown = queue::list_occurrences(&store, "no-such-series").await.unwrap();
+
+        assert_eq!(occurrence.id, format!("hourly-report:{}", series.scheduled_at));
+        assert_eq!(series_state, TaskState::Queued);
+        assert_eq!(rescheduled.scheduled_at, series.scheduled_at + 3600);
+        assert_eq!(in_flight[0].state, OccurrenceState::InFlight);
+        assert_eq!(finished[0].state, OccurrenceState::Finished);
+        assert!(unknown.is_none());
+    }
+
+    #[tokio::test]
+    async fn series_cancelled_while_claimed_is_not_rescheduled() {
+        let store = MemoryStore::new();
+        let series = hourly_series("cancelled-report");
+        store.enqueue(&series, DuplicatePolicy::Reject).await.unwrap();
+
+        let claimed = store.claim(Utc::now().timestamp(), i64::MAX, QueueOrder::Fifo).await.unwrap().unwrap();
+        store.cancel("cancelled-report").await.unwrap();
+        let outcome = store.reschedule_series(&claimed, Some(&claimed.occurrence()), i64::MAX).await.unwrap();
+
+        assert_eq!(outcome, SeriesOutcome::Cancelled);
+        assert!(store.get("cancelled-report").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn skip_policy_reschedules_stale_runs_without_delivering() {
+        let store = MemoryStore::new();
+        let mut series = hourly_series("stale-report");
+        series.misfire_policy = MisfirePolicy::Skip;
+        series.scheduled_at -= 3 * 3600;
+        queue::enqueue_task(&store, &series).await.unwrap();
+
+        let claimed = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap();
+        let (rescheduled, _) = queue::get_task(&store, "stale-report").await.unwrap().unwrap();
+        let history = queue::list_occurrences(&store, "stale-report").await.unwrap().unwrap();
+
+        assert!(claimed.is_none());
+        assert!(rescheduled.scheduled_at as i64 > Utc::now().timestamp());
+        assert!(history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn expired_run_histories_and_idempotency_keys_are_swept() {
+        let store = MemoryStore::new();
+        let run = TaskRun {
+            attempt: 1,
+            started_at: 100,
+            finished_at: 101,
+            latency_ms: 1000,
+            status: Some(200),
+            error: None,
+            occurrence_id: None,
+        };
+        store.record_run("finished-task", &run).await.unwrap();
+        queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+
+        let after_a_day = store.sweep_expired(Instant::now() + Duration::from_secs(25 * 60 * 60)).unwrap();
+        let runs = store.list_runs("finished-task").await.unwrap();
+        let after_a_week = store.sweep_expired(Instant::now() + Duration::from_secs(8 * 24 * 60 * 60)).unwrap();
+        let reused = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "b").await.unwrap();
+
+        assert_eq!(after_a_day, 1);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(after_a_week, 1);
+        assert!(store.list_runs("finished-task").await.unwrap().is_empty());
+        assert!(matches!(reused, IdempotencyState::New));
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_replay_completed_responses() {
+        let store = MemoryStore::new();
+        let response = IdempotentResponse { status: 201, body: serde_json::json!({"task_id": "task-1"}) };
+
+        let first = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+        let concurrent = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+        queue::complete_idempotent_request(&store, "/submit-task", "key-1", "a", &response).await.unwrap();
+        let repeated = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "a").await.unwrap();
+        let mismatched = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "b").await.unwrap();
+        queue::release_idempotent_request(&store, "/submit-task", "key-1").await.unwrap();
+        let released = queue::begin_idempotent_request(&store, "/submit-task", "key-1", "b").await.unwrap();
+
+        assert!(matches!(first, IdempotencyState::New));
+        assert!(matches!(concurrent, IdempotencyState::InProgress));
+        assert!(matches!(repeated, IdempotencyState::Completed(ref stored) if stored.status == 201));
+        assert!(matches!(mismatched, IdempotencyState::Mismatch));
+        assert!(matches!(released, IdempotencyState::New));
+    }
+
+    #[tokio::test]
+    async fn run_histories_keep_the_most_recent_runs() {
+        let store = MemoryStore::new();
+        let mut occurrence = due_task("hourly:1700000000");
+        occurrence.series_id = Some("hourly".to_string());
+
+        for attempt in 1..=60 {
+            let run = TaskRun {
+                attempt,
+                started_at: 1_700_000_000 + u64::from(attempt),
+                finished_at: 1_700_000_000 + u64::from(attempt),
+                latency_ms: 12,
+                status: Some(503),
+                error: Some("503 Service Unavailable".to_string()),
+                occurrence_id: None,
+            };
+            queue::record_task_run(&store, &occurrence, &run).await.unwrap();
+        }
+        let runs = store.list_runs("hourly:1700000000").await.unwrap();
+        let series_runs = store.list_runs("hourly").await.unwrap();
+        store.clear().await.unwrap();
+        let cleared = store.list_runs("hourly").await.unwrap();
+
+        assert_eq!(runs.len(), 50);
+        assert_eq!((runs[0].attempt, runs[49].attempt), (60, 11));
+        assert!(runs[0].occurrence_id.is_none());
+        assert_eq!(series_runs.len(), 50);
+        assert_eq!(series_runs[0].occurrence_id.as_deref(), Some("hourly:1700000000"));
+        assert!(cleared.is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 #[cfg(test)]
 mod tests {
-    use chrono::Utc;
-    use thermite::task::BaseTask;
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use thermite::config::RetryPolicy;
+    use thermite::task::{BaseTask, DeliveryHeaders, DeliveryMethod, MisfirePolicy, TaskPriority, TaskUpdate};
 
     #[test]
     fn test_get_next_unix_datetime_non_periodic() {
@@ -60,17 +61,15 @@ mod tests {
 
     #[test]
     fn test_schedule_retry_increments_count_and_records_error() {
-        std::env::set_var("THERMITE_RETRY_BASE_DELAY_SECS", "1");
-
         let mut task = BaseTask {
             id: "retry-task".to_string(),
             scheduled_at: Utc::now().timestamp() as u64,
-            max_retries: 3,
+            max_retries: Some(3),
             ..Default::default()
         };
 
-        let should_retry = task.schedule_retry("temporary network failure");
-        std::env::remove_var("THERMITE_RETRY_BASE_DELAY_SECS");
+        let retry = RetryPolicy { max_retries: 3, base_delay_secs: 1 };
+        let should_retry = task.schedule_retry("temporary network failure", &retry);
 
         assert!(should_retry);
         assert_eq!(task.retry_count, 1);
@@ -83,14 +82,412 @@ mod tests {
         let mut task = BaseTask {
             id: "retry-task".to_string(),
             retry_count: 1,
-            max_retries: 1,
+            max_retries: Some(1),
             ..Default::default()
         };
 
-        let should_retry = task.schedule_retry("permanent failure");
+        let should_retry = task.schedule_retry("permanent failure", &RetryPolicy::default());
 
         assert!(!should_retry);
         assert_eq!(task.retry_count, 1);
         assert_eq!(task.last_error.as_deref(), Some("permanent failure"));
     }
+
+    #[test]
+    fn test_priority_deserializes_known_levels_only() {
+        let payload = serde_json::json!({
+            "id": "priority-task",
+            "name": "Task",
+            "description": "desc",
+            "category": "non_periodic",
+            "priority": "low",
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": 1628764800_u64,
+            "cron_scheduled_at": "",
+            "args": null
+        });
+        let task: BaseTask = serde_json::from_value(payload.clone()).unwrap();
+        assert_eq!(task.priority, TaskPriority::Low);
+
+        let mut unknown = payload.clone();
+        unknown["priority"] = serde_json::json!("urgent");
+        assert!(serde_json::from_value::<BaseTask>(unknown).is_err());
+
+        let mut missing = payload;
+        missing.as_object_mut().unwrap().remove("priority");
+        let task: BaseTask = serde_json::from_value(missing).unwrap();
+        assert_eq!(task.priority, TaskPriority::Normal);
+    }
+
+    #[test]
+    fn test_task_update_changes_only_present_fields() {
+        let mut task = BaseTask {
+            id: "update-task".to_string(),
+            category: "non_periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            scheduled_at: 1628764800,
+            args: Some([("user_id".to_string(), serde_json::json!(42))].into_iter().collect()),
+            max_retries: Some(3),
+            ..Default::default()
+        };
+
+        let update: TaskUpdate = serde_json::from_value(serde_json::json!({
+            "scheduled_at": 1893456000_u64,
+            "priority": "high"
+        }))
+        .unwrap();
+        update.apply(&mut task).unwrap();
+
+        assert_eq!(task.scheduled_at, 1893456000);
+        assert_eq!(task.priority, TaskPriority::High);
+        assert_eq!(task.max_retries, Some(3));
+        assert!(task.args.is_some());
+
+        let clear_args: TaskUpdate = serde_json::from_value(serde_json::json!({"args": null})).unwrap();
+        clear_args.apply(&mut task).unwrap();
+        assert!(task.args.is_none());
+    }
+
+    #[test]
+    fn test_task_update_revalidates_the_result() {
+        let mut task = BaseTask {
+            id: "update-task".to_string(),
+            category: "periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            cron_scheduled_at: "0 0 * * *".to_string(),
+            ..Default::default()
+        };
+
+        let update = TaskUpdate {
+            cron_scheduled_at: Some("not a valid cron".to_string()),
+            ..Default::default()
+        };
+
+        assert!(update.apply(&mut task).is_err());
+    }
+
+    #[test]
+    fn test_task_update_moves_periodic_task_to_new_schedule() {
+        let mut task = BaseTask {
+            id: "update-task".to_string(),
+            category: "periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            scheduled_at: 1628764800,
+            cron_scheduled_at: "0 0 * * *".to_string(),
+            ..Default::default()
+        };
+
+        let update = TaskUpdate {
+            cron_scheduled_at: Some("30 9 * * *".to_string()),
+            ..Default::default()
+        };
+        update.apply(&mut task).unwrap();
+
+        assert!(task.scheduled_at as i64 > Utc::now().timestamp());
+        assert_eq!(task.scheduled_at % 3600, 1800);
+    }
+
+    #[test]
+    fn test_task_update_rejects_unknown_fields() {
+        let update = serde_json::from_value::<TaskUpdate>(serde_json::json!({"task": "https://evil.example.net"}));
+
+        assert!(update.is_err());
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> DeliveryHeaders {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn task_with_headers(pairs: &[(&str, &str)]) -> BaseTask {
+        BaseTask {
+            id: "headers-task".to_string(),
+            category: "non_periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            headers: headers(pairs),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_delivery_options_default_to_post_without_headers() {
+        let task: BaseTask = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "name": "n",
+            "description": "d",
+            "category": "non_periodic",
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": 0,
+            "cron_scheduled_at": "",
+            "args": null
+        }))
+        .unwrap();
+
+        assert_eq!(task.method, DeliveryMethod::Post);
+        assert!(task.headers.is_empty());
+        assert_eq!(task.timeout_secs, None);
+    }
+
+    #[test]
+    fn test_delivery_method_is_case_insensitive() {
+        let method: DeliveryMethod = serde_json::from_value(serde_json::json!("put")).unwrap();
+
+        assert_eq!(method, DeliveryMethod::Put);
+        assert_eq!(serde_json::to_value(method).unwrap(), serde_json::json!("PUT"));
+        assert!(serde_json::from_value::<DeliveryMethod>(serde_json::json!("TRACE")).is_err());
+    }
+
+    #[test]
+    fn test_hop_by_hop_and_host_headers_are_rejected() {
+        for name in ["Host", "Connection", "transfer-encoding", "Upgrade", "Content-Length", "Thermite-Signature"] {
+            assert!(task_with_headers(&[(name, "x")]).validate().is_err(), "{name} should be rejected");
+        }
+
+        assert!(task_with_headers(&[("Authorization", "Bearer abc"), ("X-Tenant", "acme")])
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn test_malformed_and_duplicate_headers_are_rejected() {
+        assert!(task_with_headers(&[("Bad Header", "x")]).validate().is_err());
+        assert!(task_with_headers(&[("X-Value", "line\nbreak")]).validate().is_err());
+        assert!(task_with_headers(&[("X-Tenant", "a"), ("x-tenant", "b")]).validate().is_err());
+    }
+
+    #[test]
+    fn test_timeout_must_be_within_bounds() {
+        let mut task = task_with_headers(&[]);
+
+        task.timeout_secs = Some(0);
+        assert!(task.validate().is_err());
+        task.timeout_secs = Some(3601);
+        assert!(task.validate().is_err());
+        task.timeout_secs = Some(120);
+        assert!(task.validate().is_ok());
+    }
+
+    #[test]
+    fn test_sensitive_headers_are_redacted() {
+        let task = task_with_headers(&[
+            ("Authorization", "Bearer abc"),
+            ("X-Api-Key", "k-123"),
+            ("X-Tenant", "acme"),
+        ]);
+
+        let redacted = task.redacted();
+        assert_eq!(redacted.headers.get("authorization"), Some("[redacted]"));
+        assert_eq!(redacted.headers.get("x-api-key"), Some("[redacted]"));
+        assert_eq!(redacted.headers.get("x-tenant"), Some("acme"));
+        assert_eq!(task.headers.get("authorization"), Some("Bearer abc"));
+
+        let debug = format!("{task:?}");
+        assert!(!debug.contains("Bearer abc"));
+        assert!(!debug.contains("k-123"));
+    }
+
+    #[test]
+    fn test_task_update_replaces_delivery_options() {
+        let mut task = task_with_headers(&[("X-Tenant", "acme")]);
+        task.timeout_secs = Some(60);
+
+        let update: TaskUpdate = serde_json::from_value(serde_json::json!({
+            "method": "PATCH",
+            "headers": {"X-Tenant": "globex"},
+            "timeout_secs": null
+        }))
+        .unwrap();
+        update.apply(&mut task).unwrap();
+
+        assert_eq!(task.method, DeliveryMethod::Patch);
+        assert_eq!(task.headers.get("x-tenant"), Some("globex"));
+        assert_eq!(task.timeout_secs, None);
+    }
+
+    fn periodic_in(cron: &str, timezone: &str) -> BaseTask {
+        BaseTask {
+            id: "tz-task".to_string(),
+            category: "periodic".to_string(),
+            task: "https://example.com/hooks/run".to_string(),
+            cron_scheduled_at: cron.to_string(),
+            timezone: Some(timezone.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap().and_utc()
+    }
+
+    #[test]
+    fn test_cron_is_evaluated_in_task_timezone_across_dst() {
+        let task = periodic_in("0 9 * * *", "America/New_York");
+
+        // 09:00 EST (UTC-5) before the switch, 09:00 EDT (UTC-4) after it.
+        assert_eq!(task.next_run_after(utc("2024-03-08 15:00:00")).unwrap(), utc("2024-03-09 14:00:00").timestamp());
+        assert_eq!(task.next_run_after(utc("2024-03-09 15:00:00")).unwrap(), utc("2024-03-10 13:00:00").timestamp());
+    }
+
+    #[test]
+    fn test_local_time_skipped_by_spring_forward_runs_after_the_gap() {
+        let task = periodic_in("30 2 * * *", "America/New_York");
+
+        // 02:30 does not exist on 2024-03-10; it runs at 03:30 EDT.
+        assert_eq!(task.next_run_after(utc("2024-03-10 05:00:00")).unwrap(), utc("2024-03-10 07:30:00").timestamp());
+        assert_eq!(task.next_run_after(utc("2024-03-10 07:30:00")).unwrap(), utc("2024-03-11 06:30:00").timestamp());
+    }
+
+    #[test]
+    fn test_local_time_repeated_by_fall_back_runs_once() {
+        let task = periodic_in("30 1 * * *", "America/New_York");
+
+        // 01:30 happens twice on 2024-11-03; only the first (EDT) occurrence runs.
+        assert_eq!(task.next_run_after(utc("2024-11-03 04:00:00")).unwrap(), utc("2024-11-03 05:30:00").timestamp());
+        assert_eq!(task.next_run_after(utc("2024-11-03 05:30:00")).unwrap(), utc("2024-11-04 06:30:00").timestamp());
+    }
+
+    #[test]
+    fn test_timezone_defaults_to_utc() {
+        let task = BaseTask {
+            timezone: None,
+            ..periodic_in("0 9 * * *", "UTC")
+        };
+
+        assert_eq!(task.next_run_after(utc("2024-07-01 10:00:00")).unwrap(), utc("2024-07-02 09:00:00").timestamp());
+    }
+
+    #[test]
+    fn test_unknown_timezone_is_rejected() {
+        assert!(periodic_in("0 9 * * *", "Mars/Olympus_Mons").validate().is_err());
+        assert!(periodic_in("0 9 * * *", "Europe/Berlin").validate().is_ok());
+    }
+
+    #[test]
+    fn test_task_update_of_timezone_moves_periodic_task() {
+        let mut task = periodic_in("0 9 * * *", "UTC");
+        task.set_next_unix_datetime().unwrap();
+
+        let update: TaskUpdate = serde_json::from_value(serde_json::json!({"timezone": "Asia/Kolkata"})).unwrap();
+        update.apply(&mut task).unwrap();
+
+        assert_eq!(task.timezone.as_deref(), Some("Asia/Kolkata"));
+        // 09:00 in India is 03:30 UTC.
+        assert_eq!(task.scheduled_at % 86400, 3 * 3600 + 1800);
+    }
+
+    fn hourly_due_at(scheduled_at: &str, policy: MisfirePolicy) -> BaseTask {
+        BaseTask {
+            scheduled_at: utc(scheduled_at).timestamp() as u64,
+            misfire_policy: policy,
+            ..periodic_in("0 * * * *", "UTC")
+        }
+    }
+
+    #[test]
+    fn test_missed_runs_count_passed_occurrences_after_the_run() {
+        let task = hourly_due_at("2024-05-01 08:00:00", MisfirePolicy::RunOnce);
+
+        assert_eq!(task.count_missed_runs(utc("2024-05-01 08:30:00")).unwrap(), 0);
+        assert_eq!(task.count_missed_runs(utc("2024-05-01 09:00:00")).unwrap(), 1);
+        assert_eq!(task.count_missed_runs(utc("2024-05-01 13:15:00")).unwrap(), 5);
+    }
+
+    #[test]
+    fn test_run_once_and_skip_continue_after_now() {
+        for policy in [MisfirePolicy::RunOnce, MisfirePolicy::Skip] {
+            let mut task = hourly_due_at("2024-05-01 08:00:00", policy);
+
+            task.advance_schedule(utc("2024-05-01 13:15:00")).unwrap();
+
+            assert_eq!(task.scheduled_at as i64, utc("2024-05-01 14:00:00").timestamp());
+        }
+    }
+
+    #[test]
+    fn test_run_all_continues_with_the_oldest_missed_occurrence() {
+        let mut task = hourly_due_at("2024-05-01 08:00:00", MisfirePolicy::RunAll);
+
+        task.advance_schedule(utc("2024-05-01 13:15:00")).unwrap();
+        assert_eq!(task.scheduled_at as i64, utc("2024-05-01 09:00:00").timestamp());
+
+        // Once caught up it waits for the next occurrence.
+        let mut caught_up = hourly_due_at("2024-05-01 13:00:00", MisfirePolicy::RunAll);
+        caught_up.advance_schedule(utc("2024-05-01 13:15:00")).unwrap();
+        assert_eq!(caught_up.scheduled_at as i64, utc("2024-05-01 14:00:00").timestamp());
+    }
+
+    #[test]
+    fn test_run_all_is_bounded_by_max_catch_up() {
+        let mut task = BaseTask {
+            max_catch_up: 2,
+            ..hourly_due_at("2024-05-01 08:00:00", MisfirePolicy::RunAll)
+        };
+
+        task.advance_schedule(utc("2024-05-01 13:15:00")).unwrap();
+
+        // 09:00 to 13:00 were missed; only the latest two are still run.
+        assert_eq!(task.scheduled_at as i64, utc("2024-05-01 12:00:00").timestamp());
+    }
+
+    #[test]
+    fn test_misfire_policy_serde_and_validation() {
+        let task: BaseTask = serde_json::from_value(serde_json::json!({
+            "id": "1",
+            "name": "n",
+            "description": "d",
+            "category": "periodic",
+            "task": "https://example.com/hooks/run",
+            "scheduled_at": 0,
+            "cron_scheduled_at": "0 * * * *",
+            "args": null,
+            "misfire_policy": "run_all"
+        }))
+        .unwrap();
+
+        assert_eq!(task.misfire_policy, MisfirePolicy::RunAll);
+        assert_eq!(task.max_catch_up, 10);
+        assert!(serde_json::from_value::<MisfirePolicy>(serde_json::json!("run_twice")).is_err());
+
+        let unbounded = BaseTask { max_catch_up: 0, ..task.clone() };
+        assert!(unbounded.validate().is_err());
+        let too_many = BaseTask { max_catch_up: 1001, ..task };
+        assert!(too_many.validate().is_err());
+    }
+
+    #[test]
+    fn test_occurrence_is_a_one_off_copy_linked_to_its_series() {
+        let series = BaseTask {
+            scheduled_at: 1_700_000_000,
+            retry_count: 2,
+            is_retry: true,
+            last_error: Some("502 Bad Gateway".to_string()),
+            missed_runs: 3,
+            ..periodic_in("0 * * * *", "Europe/Berlin")
+        };
+
+        let occurrence = series.occurrence();
+
+        assert_eq!(occurrence.id, "tz-task:1700000000");
+        assert_eq!(occurrence.series_id.as_deref(), Some("tz-task"));
+        assert_eq!(occurrence.category, "non_periodic");
+        assert_eq!(occurrence.scheduled_at, 1_700_000_000);
+        assert_eq!(occurrence.missed_runs, 3);
+        assert_eq!(occurrence.retry_count, 0);
+        assert!(!occurrence.is_retry);
+        assert!(occurrence.last_error.is_none());
+    }
+
+    #[test]
+    fn test_colons_in_ids_are_reserved_for_occurrences() {
+        let series = BaseTask { scheduled_at: 1_700_000_000, ..periodic_in("0 * * * *", "UTC") };
+        let occurrence = series.occurrence();
+        assert!(occurrence.validate().is_ok());
+        assert!(occurrence.validate_submission().is_err());
+
+        let submitted = BaseTask { id: "tz-task:1700000000".to_string(), ..task_with_headers(&[]) };
+        assert!(submitted.validate_submission().unwrap_err().is_client_error());
+        assert!(submitted.validate().is_ok());
+
+        let forged = BaseTask { id: "other:1700000000".to_string(), ..occurrence };
+        assert!(forged.validate().is_err());
+    }
 }
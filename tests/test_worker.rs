@@ -0,0 +1,385 @@
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use thermite::config::TargetPolicy;
+    use thermite::shutdown;
+    use thermite::signing::{verify_signature, WebhookSigner, SIGNATURE_HEADER};
+    use thermite::queue::{self, DuplicatePolicy, QueueOrder, TaskFilter, TaskState};
+    use thermite::store::{MemoryStore, TaskStore};
+    use thermite::task::{BaseTask, DeliveryMethod, MisfirePolicy};
+    use thermite::worker::{self, DeliveryPolicy};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc;
+
+    // A request answered by the test target.
+    #[derive(Clone)]
+    struct Received {
+        method: String,
+        path: String,
+        head: String,
+        signature: Option<String>,
+        body: Vec<u8>,
+    }
+
+    // Minimal HTTP target: requests to '/slow' take two seconds, everything else answers
+    // immediately. Every answered request is recorded in completion order.
+    async fn start_target() -> (SocketAddr, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let completed = Arc::new(Mutex::new(Vec::new()));
+
+        let recorder = Arc::clone(&completed);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let recorder = Arc::clone(&recorder);
+                tokio::spawn(async move { answer(stream, recorder).await });
+            }
+        });
+
+        (address, completed)
+    }
+
+    async fn answer(mut stream: TcpStream, completed: Arc<Mutex<Vec<Received>>>) {
+        let mut request = Vec::new();
+        let mut buffer = [0_u8; 4096];
+        let header_end = loop {
+            let read = stream.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                return;
+            }
+            request.extend_from_slice(&buffer[..read]);
+            if let Some(position) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                break position + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..header_end]).to_string();
+        let content_length = head
+            .lines()
+            .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+            .and_then(|value| value.parse::<usize>().ok())
+            .unwrap_or(0);
+        while request.len() < header_end + content_length {
+            let read = stream.read(&mut buffer).await.unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+
+        let method = head.split_whitespace().next().unwrap_or("").to_string();
+        let path = head.split_whitespace().nth(1).unwrap_or("/").to_string();
+        if path.starts_with("/slow") {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        }
+
+        let signature = head.lines().find_map(|line| {
+            line.split_once(':')
+                .filter(|(name, _)| name.eq_ignore_ascii_case(SIGNATURE_HEADER))
+                .map(|(_, value)| value.trim().to_string())
+        });
+        let body = request[header_end..].to_vec();
+
+        completed.lock().unwrap().push(Received { method, path, head, signature, body });
+        let _ = stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await;
+    }
+
+    // Targets are named by a hostname the test client resolves to the local target, since the
+    // worker refuses literal loopback addresses like any other blocked IP.
+    const TARGET_HOST: &str = "target.test";
+
+    fn task_for(address: SocketAddr, path: &str) -> BaseTask {
+        BaseTask {
+            id: path.trim_start_matches('/').to_string(),
+            category: "non_periodic".to_string(),
+            task: format!("http://{TARGET_HOST}:{}{path}", address.port()),
+            ..Default::default()
+        }
+    }
+
+    fn local_client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .resolve(TARGET_HOST, SocketAddr::from(([127, 0, 0, 1], 0)))
+            .build()
+            .unwrap()
+    }
+
+    async fn run_deliveries(concurrency: usize) -> Vec<String> {
+        let (address, completed) = start_target().await;
+        // The tasks were never enqueued, so their acks find nothing to settle and are only logged.
+        let store = Arc::new(MemoryStore::new());
+        let (tx, rx) = mpsc::channel(concurrency);
+
+        let (_trigger, shutdown) = shutdown::channel();
+        worker::spawn_task_processor(
+            store,
+            local_client(),
+            None,
+            DeliveryPolicy::default(),
+            rx,
+            concurrency,
+            shutdown,
+        );
+        tx.send(task_for(address, "/slow")).await.unwrap();
+        tx.send(task_for(address, "/fast")).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while completed.lock().unwrap().len() < 2 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let order = completed.lock().unwrap().iter().map(|received| received.path.clone()).collect();
+        order
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn slow_target_does_not_block_fast_target() {
+        let order = run_deliveries(2).await;
+
+        assert_eq!(order, vec!["/fast", "/slow"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn single_worker_pool_delivers_serially() {
+        let order = run_deliveries(1).await;
+
+        assert_eq!(order, vec!["/slow", "/fast"]);
+    }
+
+    #[tokio::test]
+    async fn signed_delivery_verifies_against_the_sent_body() {
+        let (address, completed) = start_target().await;
+        let signer = WebhookSigner::new(vec!["old-secret".to_string(), "new-secret".to_string()]).unwrap();
+        let task = BaseTask {
+            args: Some([("report".to_string(), serde_json::json!("daily"))].into_iter().collect()),
+            ..task_for(address, "/signed")
+        };
+
+        worker::execute_task(Arc::new(local_client()), Some(Arc::new(signer)), task)
+            .await
+            .unwrap();
+
+        let received = completed.lock().unwrap()[0].clone();
+        let header = received.signature.expect("delivery should carry a signature header");
+        let now = chrono::Utc::now().timestamp();
+        assert_eq!(header.matches("v1=").count(), 2);
+        assert!(verify_signature(&header, &received.body, &["new-secret"], now, 300).is_ok());
+        assert!(verify_signature(&header, &received.body, &["old-secret"], now, 300).is_ok());
+    }
+
+    #[tokio::test]
+    async fn unsigned_delivery_has_no_signature_header() {
+        let (address, completed) = start_target().await;
+
+        worker::execute_task(Arc::new(local_client()), None, task_for(address, "/plain"))
+            .await
+            .unwrap();
+
+        assert!(completed.lock().unwrap()[0].signature.is_none());
+    }
+
+    #[tokio::test]
+    async fn delivery_uses_task_method_and_headers() {
+        let (address, completed) = start_target().await;
+        let task = BaseTask {
+            method: DeliveryMethod::Put,
+            headers: [("X-Tenant".to_string(), "acme".to_string())].into_iter().collect(),
+            ..task_for(address, "/custom")
+        };
+
+        worker::execute_task(Arc::new(local_client()), None, task).await.unwrap();
+
+        let received = completed.lock().unwrap()[0].clone();
+        assert_eq!(received.method, "PUT");
+        assert!(received.head.to_ascii_lowercase().contains("x-tenant: acme"));
+        assert!(received.head.to_ascii_lowercase().contains("content-type: application/json"));
+    }
+
+    #[tokio::test]
+    async fn per_task_timeout_overrides_client_timeout() {
+        let (address, _) = start_target().await;
+        let task = BaseTask {
+            timeout_secs: Some(1),
+            ..task_for(address, "/slow")
+        };
+
+        let result = worker::execute_task(Arc::new(local_client()), None, task).await;
+
+        assert!(result.unwrap_err().is_timeout());
+    }
+
+    fn stale_periodic_task(address: SocketAddr, path: &str, policy: MisfirePolicy) -> BaseTask {
+        BaseTask {
+            category: "periodic".to_string(),
+            cron_scheduled_at: "0 * * * *".to_string(),
+            misfire_policy: policy,
+            missed_runs: 3,
+            ..task_for(address, path)
+        }
+    }
+
+    #[tokio::test]
+    async fn delivery_payload_reports_missed_runs() {
+        let (address, completed) = start_target().await;
+        let task = stale_periodic_task(address, "/catch-up", MisfirePolicy::RunOnce);
+
+        worker::execute_task(Arc::new(local_client()), None, task).await.unwrap();
+
+        let body: serde_json::Value = serde_json::from_slice(&completed.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body["missed_runs"], 3);
+    }
+
+    #[tokio::test]
+    async fn occurrence_payload_names_series_and_occurrence() {
+        let (address, completed) = start_target().await;
+        let series = BaseTask {
+            id: "nightly".to_string(),
+            scheduled_at: 1_700_000_000,
+            ..stale_periodic_task(address, "/nightly", MisfirePolicy::RunOnce)
+        };
+
+        worker::execute_task(Arc::new(local_client()), None, series.occurrence()).await.unwrap();
+
+        let body: serde_json::Value = serde_json::from_slice(&completed.lock().unwrap()[0].body).unwrap();
+        assert_eq!(body["task_id"], "nightly");
+        assert_eq!(body["occurrence_id"], "nightly:1700000000");
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_acknowledged_or_retried_in_the_store() {
+        let (address, _) = start_target().await;
+        let unreachable = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let store = MemoryStore::new();
+        // Stored directly: local targets would not pass the checks `queue::enqueue_task` runs.
+        store.enqueue(&task_for(address, "/delivered"), DuplicatePolicy::Reject).await.unwrap();
+        store.enqueue(&task_for(unreachable, "/refused"), DuplicatePolicy::Reject).await.unwrap();
+
+        let client = Arc::new(local_client());
+        while let Some(task) = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap() {
+            worker::deliver_task(&store, Arc::clone(&client), None, &DeliveryPolicy::default(), task).await;
+        }
+
+        let delivered = queue::get_task(&store, "delivered").await.unwrap();
+        let (refused, state) = queue::get_task(&store, "refused").await.unwrap().unwrap();
+        assert!(delivered.is_none());
+        assert_eq!(state, TaskState::Queued);
+        assert_eq!(refused.retry_count, 1);
+        assert!(refused.last_error.is_some());
+    }
+
+    #[tokio::test]
+    async fn targets_outside_the_target_policy_are_dead_lettered_without_delivery() {
+        let (address, completed) = start_target().await;
+        let store = MemoryStore::new();
+        // Stored directly, as a library caller or a replay could, so only the worker checks it.
+        store.enqueue(&task_for(address, "/outside"), DuplicatePolicy::Reject).await.unwrap();
+        let targets = TargetPolicy { allowed_hosts: vec!["example.com".to_string()], ..TargetPolicy::default() };
+        let policy = DeliveryPolicy { targets, ..DeliveryPolicy::default() };
+
+        let task = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        worker::deliver_task(&store, Arc::new(local_client()), None, &policy, task).await;
+
+        let queued = queue::get_task(&store, "outside").await.unwrap();
+        let (dead_letters, _) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        let runs = queue::list_task_runs(&store, "outside").await.unwrap().unwrap();
+        assert!(completed.lock().unwrap().is_empty());
+        assert!(queued.is_none());
+        assert_eq!(dead_letters[0].id, "outside");
+        assert_eq!(dead_letters[0].retry_count, 0);
+        assert_eq!(runs.len(), 1);
+        assert!(runs[0].error.as_deref().unwrap().contains("not in the allowed hosts"));
+    }
+
+    #[tokio::test]
+    async fn blocked_literal_ip_targets_are_dead_lettered_on_the_first_attempt() {
+        let (address, completed) = start_target().await;
+        let store = MemoryStore::new();
+        let mut task = task_for(address, "/literal");
+        task.task = format!("http://{address}/literal");
+        // Stored directly, so only the worker's own check stands between it and the target.
+        store.enqueue(&task, DuplicatePolicy::Reject).await.unwrap();
+
+        let task = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap().unwrap();
+        worker::deliver_task(&store, Arc::new(local_client()), None, &DeliveryPolicy::default(), task).await;
+
+        let queued = queue::get_task(&store, "literal").await.unwrap();
+        let (dead_letters, _) = queue::list_dead_letter_tasks(&store, &TaskFilter::default(), 0, 10).await.unwrap();
+        let runs = queue::list_task_runs(&store, "literal").await.unwrap().unwrap();
+        assert!(completed.lock().unwrap().is_empty());
+        assert!(queued.is_none());
+        assert_eq!(dead_letters[0].id, "literal");
+        assert!(dead_letters[0].last_error.as_deref().unwrap().contains("blocked IP range"));
+        assert_eq!(runs.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn deliveries_are_recorded_in_the_run_history() {
+        let (address, _) = start_target().await;
+        let unreachable = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let store = MemoryStore::new();
+        store.enqueue(&task_for(address, "/delivered"), DuplicatePolicy::Reject).await.unwrap();
+        store.enqueue(&task_for(unreachable, "/refused"), DuplicatePolicy::Reject).await.unwrap();
+
+        let client = Arc::new(local_client());
+        while let Some(task) = queue::dequeue_task(&store, QueueOrder::Fifo).await.unwrap() {
+            worker::deliver_task(&store, Arc::clone(&client), None, &DeliveryPolicy::default(), task).await;
+        }
+
+        let delivered = queue::list_task_runs(&store, "delivered").await.unwrap().unwrap();
+        let refused = queue::list_task_runs(&store, "refused").await.unwrap().unwrap();
+        let unknown = queue::list_task_runs(&store, "no-such-task").await.unwrap();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].attempt, 1);
+        assert_eq!(delivered[0].status, Some(200));
+        assert!(delivered[0].error.is_none());
+        assert!(delivered[0].finished_at >= delivered[0].started_at);
+        assert_eq!(refused.len(), 1);
+        assert!(refused[0].status.is_none());
+        assert!(refused[0].error.is_some());
+        assert!(unknown.is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn shutdown_releases_buffered_tasks_and_waits_for_running_deliveries() {
+        let (address, completed) = start_target().await;
+        let store = Arc::new(MemoryStore::new());
+        store.enqueue(&task_for(address, "/slow"), DuplicatePolicy::Reject).await.unwrap();
+        let mut buffered = task_for(address, "/buffered");
+        buffered.scheduled_at += 1;
+        store.enqueue(&buffered, DuplicatePolicy::Reject).await.unwrap();
+
+        let (tx, rx) = mpsc::channel(2);
+        let (trigger, shutdown) = shutdown::channel();
+        let processor = worker::spawn_task_processor(
+            store.clone(),
+            local_client(),
+            None,
+            DeliveryPolicy::default(),
+            rx,
+            1,
+            shutdown,
+        );
+        while let Some(task) = queue::dequeue_task(store.as_ref(), QueueOrder::Fifo).await.unwrap() {
+            tx.send(task).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        trigger.trigger();
+        tokio::time::timeout(Duration::from_secs(10), processor).await.unwrap().unwrap();
+
+        let delivered: Vec<String> = completed.lock().unwrap().iter().map(|received| received.path.clone()).collect();
+        let slow = queue::get_task(store.as_ref(), "slow").await.unwrap();
+        let (_, released) = queue::get_task(store.as_ref(), "buffered").await.unwrap().unwrap();
+        assert_eq!(delivered, vec!["/slow"]);
+        assert!(slow.is_none());
+        assert_eq!(released, TaskState::Queued);
+    }
+}